@@ -0,0 +1,232 @@
+//! This crate provides the `#[dylint_ui_test]` attribute, re-exported by `dylint_uitesting` when its
+//! `macros` feature is enabled.
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::{format_ident, quote};
+use std::{env::var_os, fs::read_dir, path::PathBuf};
+use syn::{
+    Error, Expr, ExprArray, ExprLit, ItemFn, Lit, LitBool, LitStr, meta::ParseNestedMeta,
+    parse_macro_input,
+};
+
+#[derive(Default)]
+struct Args {
+    dir: Option<LitStr>,
+    name: Option<LitStr>,
+    rustc_flags: Vec<LitStr>,
+    dylint_toml: Option<LitStr>,
+    shard: bool,
+}
+
+impl Args {
+    fn parse(&mut self, meta: &ParseNestedMeta) -> syn::Result<()> {
+        if meta.path.is_ident("dir") {
+            self.dir = Some(meta.value()?.parse()?);
+        } else if meta.path.is_ident("name") {
+            self.name = Some(meta.value()?.parse()?);
+        } else if meta.path.is_ident("dylint_toml") {
+            self.dylint_toml = Some(meta.value()?.parse()?);
+        } else if meta.path.is_ident("shard") {
+            self.shard = meta.value()?.parse::<LitBool>()?.value;
+        } else if meta.path.is_ident("rustc_flags") {
+            let array = meta.value()?.parse::<ExprArray>()?;
+            for elem in array.elems {
+                let Expr::Lit(ExprLit {
+                    lit: Lit::Str(flag),
+                    ..
+                }) = elem
+                else {
+                    return Err(Error::new_spanned(elem, "expected a string literal"));
+                };
+                self.rustc_flags.push(flag);
+            }
+        } else {
+            return Err(meta.error(
+                "unsupported argument; expected `dir`, `name`, `rustc_flags`, `dylint_toml`, or \
+                 `shard`",
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Expands a function into a UI test of the current package's library.
+///
+/// ```rust,ignore
+/// #[dylint_ui_test(dir = "ui", rustc_flags = ["--cfg", "foo"])]
+/// fn ui() {}
+/// ```
+///
+/// The function's body, if any, runs before the test. Arguments:
+///
+/// - `dir` (required) - the directory of source files, relative to the package root
+/// - `name` - the library's name (default `env!("CARGO_PKG_NAME")`)
+/// - `rustc_flags` - flags to pass to the compiler
+/// - `dylint_toml` - the `dylint.toml` file's contents
+/// - `shard` - generate one test per subdirectory of `dir`, in a module named after the function
+///   (default `false`)
+///
+/// Subdirectories are listed when the attribute is expanded. So after adding or removing one,
+/// touch the file containing the attribute so that the tests are regenerated.
+#[proc_macro_attribute]
+pub fn dylint_ui_test(args: TokenStream, item: TokenStream) -> TokenStream {
+    let mut parsed = Args::default();
+    let parser = syn::meta::parser(|meta| parsed.parse(&meta));
+    parse_macro_input!(args with parser);
+    let item = parse_macro_input!(item as ItemFn);
+
+    expand(&parsed, &item)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn expand(args: &Args, item: &ItemFn) -> syn::Result<proc_macro2::TokenStream> {
+    let Some(dir) = &args.dir else {
+        return Err(Error::new(
+            Span::call_site(),
+            "missing required argument `dir`",
+        ));
+    };
+
+    let ItemFn {
+        attrs,
+        vis,
+        sig,
+        block,
+        ..
+    } = item;
+    if !sig.inputs.is_empty() {
+        return Err(Error::new_spanned(
+            &sig.inputs,
+            "a UI test function cannot take arguments",
+        ));
+    }
+    let ident = &sig.ident;
+
+    let name = args.name.as_ref().map_or_else(
+        || quote! { ::core::env!("CARGO_PKG_NAME") },
+        |name| quote! { #name },
+    );
+    let rustc_flags = (!args.rustc_flags.is_empty()).then(|| {
+        let rustc_flags = &args.rustc_flags;
+        quote! { .rustc_flags([#(#rustc_flags),*]) }
+    });
+    let dylint_toml = args
+        .dylint_toml
+        .as_ref()
+        .map(|dylint_toml| quote! { .dylint_toml(#dylint_toml) });
+
+    let test = |ident: &syn::Ident, dir: &LitStr| {
+        quote! {
+            #[test]
+            #(#attrs)*
+            #vis fn #ident() {
+                #block
+                ::dylint_uitesting::ui::Test::src_base(#name, #dir)
+                    #rustc_flags
+                    #dylint_toml
+                    .run();
+            }
+        }
+    };
+
+    if !args.shard {
+        return Ok(test(ident, dir));
+    }
+
+    let tests = subdirectories(dir)?
+        .into_iter()
+        .map(|subdir| {
+            let ident = format_ident!("{}", sanitize(&subdir));
+            let dir = LitStr::new(&format!("{}/{subdir}", dir.value()), dir.span());
+            test(&ident, &dir)
+        })
+        .collect::<Vec<_>>();
+
+    Ok(quote! {
+        #vis mod #ident {
+            #[allow(unused_imports)]
+            use super::*;
+
+            #(#tests)*
+        }
+    })
+}
+
+/// Lists the subdirectories of `dir`, relative to the package root, in sorted order.
+fn subdirectories(dir: &LitStr) -> syn::Result<Vec<String>> {
+    let manifest_dir = var_os("CARGO_MANIFEST_DIR")
+        .map(PathBuf::from)
+        .ok_or_else(|| Error::new(dir.span(), "`CARGO_MANIFEST_DIR` is not set"))?;
+    let path = manifest_dir.join(dir.value());
+    let entries = read_dir(&path).map_err(|error| {
+        Error::new(
+            dir.span(),
+            format!("`read_dir` failed for `{}`: {error}", path.display()),
+        )
+    })?;
+
+    let mut subdirectories = Vec::new();
+    for entry in entries {
+        let entry = entry.map_err(|error| {
+            Error::new(
+                dir.span(),
+                format!("`read_dir` failed for `{}`: {error}", path.display()),
+            )
+        })?;
+        let entry_path = entry.path();
+        if entry_path.is_dir() {
+            subdirectories.push(entry.file_name().to_string_lossy().into_owned());
+        } else if entry_path.extension().is_some_and(|ext| ext == "rs") {
+            // `src_base` tests are recursive, so a shard for `dir` itself would repeat the others.
+            return Err(Error::new(
+                dir.span(),
+                format!(
+                    "`shard` requires that every source file be in a subdirectory, but found `{}`",
+                    entry_path.display()
+                ),
+            ));
+        }
+    }
+    subdirectories.sort();
+    if subdirectories.is_empty() {
+        return Err(Error::new(
+            dir.span(),
+            format!("`{}` has no subdirectories to shard", path.display()),
+        ));
+    }
+    Ok(subdirectories)
+}
+
+/// Turns a directory name into a valid identifier. Names that would otherwise be keywords, e.g.,
+/// `mod`, are prefixed with an underscore, as are those that start with a digit.
+fn sanitize(name: &str) -> String {
+    let mut ident = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect::<String>();
+    if ident.is_empty()
+        || ident.starts_with(|c: char| c.is_ascii_digit())
+        || syn::parse_str::<syn::Ident>(&ident).is_err()
+    {
+        ident.insert(0, '_');
+    }
+    ident
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sanitize_makes_identifiers() {
+        assert_eq!(sanitize("lints"), "lints");
+        assert_eq!(sanitize("my-lint.v2"), "my_lint_v2");
+        assert_eq!(sanitize("2024"), "_2024");
+        assert_eq!(sanitize("mod"), "_mod");
+        assert_eq!(sanitize("async"), "_async");
+        assert_eq!(sanitize("self"), "_self");
+        assert_eq!(sanitize("_"), "__");
+    }
+}
@@ -0,0 +1,279 @@
+use crate::{
+    diagnostics::{Diagnostic, DiagnosticLevel, compile_diagnostics, file_revisions},
+    runtime::Driver,
+    test_runner::source_files,
+    ui,
+};
+use anyhow::{Context, Result};
+use log::debug;
+use std::{
+    collections::BTreeMap,
+    fs::{read_to_string, write},
+    path::Path,
+};
+
+/// Injects `//~` annotations into each source file beneath `src_base` that has none, based on the
+/// diagnostics the driver actually emits.
+///
+/// This is meant for migrating suites written for `compiletest`. The annotations should be
+/// reviewed, since they merely record the current behavior.
+pub(crate) fn inject_annotations(
+    driver: &Driver,
+    src_base: &Path,
+    config: &ui::Config,
+) -> Result<()> {
+    for file in source_files(src_base, config)? {
+        let source = read_to_string(&file)
+            .with_context(|| format!("Could not read `{}`", file.display()))?;
+        if source.contains("//~") {
+            debug!(
+                "inject_annotations: Skipping already annotated {}",
+                file.display()
+            );
+            continue;
+        }
+
+        let mut diagnostics = Vec::new();
+        for revision in file_revisions(&file)? {
+            let revision_diagnostics =
+                compile_diagnostics(driver, &file, revision.as_deref(), config)?;
+            diagnostics.push((revision, revision_diagnostics));
+        }
+        let file_name = file
+            .file_name()
+            .map(|file_name| file_name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let annotated = annotate(
+            &source,
+            &file_name,
+            &diagnostics,
+            config.require_spanless_annotations,
+        );
+        if annotated != source {
+            debug!("inject_annotations: Annotating {}", file.display());
+            write(&file, annotated)
+                .with_context(|| format!("Could not write `{}`", file.display()))?;
+        }
+    }
+    Ok(())
+}
+
+/// Returns `source` with an annotation for each error or warning whose primary span lies in
+/// `file_name`.
+///
+/// `diagnostics` holds each revision's diagnostics, or a single entry with no revision if the file
+/// has none. An annotation for a diagnostic emitted by only some revisions is restricted to them,
+/// e.g., `//~[a]^`.
+///
+/// Annotations are inserted on the lines following the one they refer to, as `//~^` followed by
+/// `//~|` for further diagnostics on the same line, so that existing trailing comments are left
+/// alone.
+///
+/// If `file_level` is true, an error or warning without a primary span in `file_name`, e.g., a
+/// crate-level diagnostic, gets an `//@error-in-other-file:` header after the file's leading
+/// headers.
+fn annotate(
+    source: &str,
+    file_name: &str,
+    diagnostics: &[(Option<String>, Vec<Diagnostic>)],
+    file_level: bool,
+) -> String {
+    // Maps each line to its annotations, in order, and the revisions emitting each. A diagnostic
+    // repeated on one line is counted, so that each occurrence gets its own annotation.
+    let mut annotations = BTreeMap::<usize, Vec<(String, Vec<&str>)>>::new();
+    // File-level annotations, and the revisions emitting each. One header matches any number of
+    // diagnostics.
+    let mut file_annotations = Vec::<(&str, Vec<&str>)>::new();
+    for (revision, diagnostics) in diagnostics {
+        let mut seen = BTreeMap::<(usize, String), usize>::new();
+        for diagnostic in diagnostics {
+            let level = match diagnostic.level {
+                DiagnosticLevel::Error => "ERROR",
+                DiagnosticLevel::Warning => "WARN",
+                _ => continue,
+            };
+            let message = diagnostic.message.lines().next().unwrap_or_default();
+            let Some(span) = diagnostic
+                .spans
+                .iter()
+                .find(|span| span.is_primary && span.file_name == file_name)
+            else {
+                if file_level {
+                    let index = file_annotations
+                        .iter()
+                        .position(|(existing, _)| *existing == message)
+                        .unwrap_or_else(|| {
+                            file_annotations.push((message, Vec::new()));
+                            file_annotations.len() - 1
+                        });
+                    file_annotations[index].1.extend(revision.as_deref());
+                }
+                continue;
+            };
+            let annotation = format!("{level}: {message}");
+
+            let occurrence = seen
+                .entry((span.line_start, annotation.clone()))
+                .or_default();
+            let entries = annotations.entry(span.line_start).or_default();
+            let index = entries
+                .iter()
+                .enumerate()
+                .filter(|(_, (existing, _))| *existing == annotation)
+                .map(|(index, _)| index)
+                .nth(*occurrence);
+            *occurrence += 1;
+            let revisions = if let Some(index) = index {
+                &mut entries[index].1
+            } else {
+                entries.push((annotation, Vec::new()));
+                &mut entries.last_mut().unwrap().1
+            };
+            revisions.extend(revision.as_deref());
+        }
+    }
+
+    let revision_count = diagnostics
+        .iter()
+        .filter(|(revision, _)| revision.is_some())
+        .count();
+
+    let restrict = |revisions: &[&str]| {
+        if revisions.len() < revision_count {
+            format!("[{}]", revisions.join(","))
+        } else {
+            String::new()
+        }
+    };
+
+    // Headers can refer to the revisions declared in the leading headers, so they follow them.
+    let header_count = source
+        .lines()
+        .take_while(|line| line.trim_start().starts_with("//@"))
+        .count();
+
+    let headers = file_annotations
+        .iter()
+        .map(|(message, revisions)| {
+            let revisions = restrict(revisions);
+            let space = if revisions.is_empty() { "" } else { " " };
+            format!("//@{revisions}{space}error-in-other-file: {message}")
+        })
+        .collect::<Vec<_>>();
+
+    let mut lines = source
+        .lines()
+        .take(header_count)
+        .map(ToOwned::to_owned)
+        .collect::<Vec<_>>();
+    lines.extend(headers);
+    for (index, line) in source.lines().enumerate().skip(header_count) {
+        lines.push(line.to_owned());
+        let Some(annotations) = annotations.get(&(index + 1)) else {
+            continue;
+        };
+        let indent = &line[..line.len() - line.trim_start().len()];
+        for (i, (annotation, revisions)) in annotations.iter().enumerate() {
+            let marker = if i == 0 { "^" } else { "|" };
+            let revisions = restrict(revisions);
+            lines.push(format!("{indent}//~{revisions}{marker} {annotation}"));
+        }
+    }
+
+    let mut annotated = lines.join("\n");
+    if source.ends_with('\n') {
+        annotated.push('\n');
+    }
+    annotated
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    fn diagnostic(level: &str, message: &str, file_name: &str, line: usize) -> Diagnostic {
+        serde_json::from_value(json!({
+            "message": message,
+            "code": null,
+            "level": level,
+            "spans": [{
+                "file_name": file_name,
+                "byte_start": 0,
+                "byte_end": 0,
+                "line_start": line,
+                "line_end": line,
+                "column_start": 1,
+                "column_end": 1,
+                "is_primary": true,
+                "text": [],
+                "label": null,
+                "suggested_replacement": null,
+                "suggestion_applicability": null,
+                "expansion": null
+            }],
+            "children": [],
+            "rendered": null
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn annotations_follow_their_lines() {
+        let source = "fn main() {\n    let x = 1; // comment\n}\n";
+        let diagnostics = [
+            diagnostic("error", "first", "main.rs", 2),
+            diagnostic("warning", "second\nmore", "main.rs", 2),
+            diagnostic("note", "ignored", "main.rs", 1),
+            diagnostic("error", "elsewhere", "other.rs", 1),
+        ];
+        assert_eq!(
+            annotate(source, "main.rs", &[(None, diagnostics.to_vec())], false),
+            "fn main() {\n    let x = 1; // comment\n    //~^ ERROR: first\n    //~| WARN: second\n}\n"
+        );
+    }
+
+    #[test]
+    fn annotations_are_restricted_to_revisions() {
+        let source = "//@revisions: a b\nfn main() {}\n";
+        let diagnostics = [
+            (
+                Some("a".to_owned()),
+                vec![
+                    diagnostic("error", "both", "main.rs", 2),
+                    diagnostic("error", "only a", "main.rs", 2),
+                ],
+            ),
+            (
+                Some("b".to_owned()),
+                vec![diagnostic("error", "both", "main.rs", 2)],
+            ),
+        ];
+        assert_eq!(
+            annotate(source, "main.rs", &diagnostics, false),
+            "//@revisions: a b\nfn main() {}\n//~^ ERROR: both\n//~[a]| ERROR: only a\n"
+        );
+    }
+
+    #[test]
+    fn spanless_diagnostics_get_file_level_headers() {
+        let source = "//@revisions: a b\nfn main() {}\n";
+        let mut crate_level = diagnostic("warning", "crate level", "main.rs", 1);
+        crate_level.spans.clear();
+        let diagnostics = [
+            (
+                Some("a".to_owned()),
+                vec![
+                    crate_level.clone(),
+                    diagnostic("error", "only a", "other.rs", 1),
+                ],
+            ),
+            (Some("b".to_owned()), vec![crate_level]),
+        ];
+        assert_eq!(
+            annotate(source, "main.rs", &diagnostics, true),
+            "//@revisions: a b\n//@error-in-other-file: crate level\n//@[a] error-in-other-file: only a\nfn main() {}\n"
+        );
+        assert_eq!(annotate(source, "main.rs", &diagnostics, false), source);
+    }
+}
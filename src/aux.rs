@@ -0,0 +1,210 @@
+use crate::{
+    diagnostics::{default_edition, header, header_flags},
+    env,
+    test_runner::files_with_extension,
+    ui,
+};
+use anyhow::{Context, Result, anyhow, ensure};
+use log::debug;
+use std::{
+    collections::BTreeSet,
+    env::consts,
+    fs::read_to_string,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// Names of the directories holding auxiliary crates. Files in them are not tested themselves,
+/// but compiled for the files that name them in `//@aux-build:` headers.
+const DIRS: [&str; 2] = ["auxiliary", "aux"];
+
+/// Returns true if `file` is beneath an auxiliary directory within `src_base`.
+pub(crate) fn is_aux(src_base: &Path, file: &Path) -> bool {
+    file.strip_prefix(src_base).is_ok_and(|relative| {
+        relative.parent().is_some_and(|parent| {
+            parent
+                .components()
+                .any(|component| DIRS.iter().any(|dir| component.as_os_str() == *dir))
+        })
+    })
+}
+
+/// Returns the auxiliary crates' source files beneath `src_base`.
+pub(crate) fn files(src_base: &Path) -> Result<Vec<PathBuf>> {
+    Ok(files_with_extension(src_base, "rs")?
+        .into_iter()
+        .filter(|file| is_aux(src_base, file))
+        .collect())
+}
+
+/// Compiles the auxiliary crates named by `sources`' `//@aux-build:` headers into `out_dir`, and
+/// returns the flags that make them available to the driver.
+///
+/// The crates are compiled with plain `rustc` rather than the driver, so that the library's lints
+/// do not fire on them. An auxiliary crate can itself have `//@aux-build:` headers. A crate with a
+/// `//@proc-macro` header or a `#![crate_type = "proc-macro"]` attribute is compiled as a
+/// procedural macro for the host.
+pub(crate) fn build(
+    sources: &[PathBuf],
+    config: &ui::Config,
+    out_dir: &Path,
+) -> Result<Vec<String>> {
+    let mut built = BTreeSet::new();
+    let mut flags = Vec::new();
+    for source in sources {
+        for aux_file in aux_files(source)? {
+            build_one(&aux_file, config, out_dir, &mut built, &mut flags)?;
+        }
+    }
+    if !flags.is_empty() {
+        flags.extend(["-L".to_owned(), format!("dependency={}", out_dir.display())]);
+    }
+    Ok(flags)
+}
+
+fn build_one(
+    aux_file: &Path,
+    config: &ui::Config,
+    out_dir: &Path,
+    built: &mut BTreeSet<PathBuf>,
+    flags: &mut Vec<String>,
+) -> Result<()> {
+    if !built.insert(aux_file.to_owned()) {
+        return Ok(());
+    }
+    for dependency in aux_files(aux_file)? {
+        build_one(&dependency, config, out_dir, built, flags)?;
+    }
+
+    let source = read_to_string(aux_file)
+        .with_context(|| format!("Could not read `{}`", aux_file.display()))?;
+    let crate_name = crate_name(aux_file)?;
+    let proc_macro = is_proc_macro(&source);
+
+    let rustc = std::env::var_os(env::RUSTC).unwrap_or_else(|| "rustc".into());
+    let mut command = Command::new(rustc);
+    if let Some(toolchain) = &config.toolchain {
+        command.env(env::RUSTUP_TOOLCHAIN, toolchain);
+    }
+    command
+        .arg("--crate-type")
+        .arg(if proc_macro { "proc-macro" } else { "lib" })
+        .args(["--crate-name", &crate_name, "--out-dir"])
+        .arg(out_dir)
+        .arg("-L")
+        .arg(format!("dependency={}", out_dir.display()))
+        .args(header_flags(&source, default_edition(config), None))
+        .args(flags.iter());
+    // Procedural macros are compiled for the host and need the compiler's `proc_macro` crate.
+    if proc_macro {
+        command.args(["--extern", "proc_macro"]);
+    } else if let Some(target) = &config.target {
+        command.arg(format!("--target={target}"));
+    }
+    command.arg(aux_file);
+
+    debug!("aux: {:?}", command);
+    let output = command
+        .output()
+        .with_context(|| format!("Could not compile `{}`", aux_file.display()))?;
+    ensure!(
+        output.status.success(),
+        "Could not compile `{}`:\n{}",
+        aux_file.display(),
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let artifact = if proc_macro {
+        format!("{}{crate_name}{}", consts::DLL_PREFIX, consts::DLL_SUFFIX)
+    } else {
+        format!("lib{crate_name}.rlib")
+    };
+    flags.extend([
+        "--extern".to_owned(),
+        format!("{crate_name}={}", out_dir.join(artifact).display()),
+    ]);
+    Ok(())
+}
+
+fn is_proc_macro(source: &str) -> bool {
+    source.lines().any(|line| {
+        header(line, None).is_some_and(|header| header.trim() == "proc-macro")
+            || line.split_whitespace().collect::<String>() == r#"#![crate_type="proc-macro"]"#
+    })
+}
+
+/// Resolves the `//@aux-build:` headers in `file` to paths in the auxiliary directories next to
+/// `file`.
+fn aux_files(file: &Path) -> Result<Vec<PathBuf>> {
+    let source =
+        read_to_string(file).with_context(|| format!("Could not read `{}`", file.display()))?;
+    let dir = file
+        .parent()
+        .ok_or_else(|| anyhow!("Could not get parent of `{}`", file.display()))?;
+    source
+        .lines()
+        .filter_map(|line| header(line, None)?.strip_prefix("aux-build:"))
+        .map(|name| {
+            let name = name.trim();
+            DIRS.iter()
+                .map(|aux_dir| dir.join(aux_dir).join(name))
+                .find(|path| path.is_file())
+                .ok_or_else(|| {
+                    anyhow!(
+                        "Could not find auxiliary crate `{name}` for `{}` in `{}`",
+                        file.display(),
+                        DIRS.join("` or `")
+                    )
+                })
+        })
+        .collect()
+}
+
+fn crate_name(aux_file: &Path) -> Result<String> {
+    aux_file
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().replace('-', "_"))
+        .ok_or_else(|| anyhow!("Could not get file stem of `{}`", aux_file.display()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs::{create_dir_all, write};
+
+    #[test]
+    fn aux_files_are_resolved_in_aux_directories() {
+        let tmp = tempfile::tempdir().unwrap();
+        for dir in DIRS {
+            create_dir_all(tmp.path().join(dir)).unwrap();
+        }
+        write(tmp.path().join("auxiliary/helper.rs"), "").unwrap();
+        write(tmp.path().join("aux/other.rs"), "").unwrap();
+        let file = tmp.path().join("main.rs");
+        write(
+            &file,
+            "//@aux-build: helper.rs\n//@aux-build:other.rs\nfn main() {}\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            aux_files(&file).unwrap(),
+            [
+                tmp.path().join("auxiliary/helper.rs"),
+                tmp.path().join("aux/other.rs")
+            ]
+        );
+        assert!(is_aux(tmp.path(), &tmp.path().join("aux/other.rs")));
+        assert!(!is_aux(tmp.path(), &file));
+
+        write(&file, "//@aux-build: missing.rs\n").unwrap();
+        assert!(aux_files(&file).is_err());
+    }
+
+    #[test]
+    fn proc_macros_are_detected() {
+        assert!(is_proc_macro("//@proc-macro\n"));
+        assert!(is_proc_macro("#![crate_type = \"proc-macro\"]\n"));
+        assert!(!is_proc_macro("#![crate_type = \"lib\"]\n"));
+    }
+}
@@ -0,0 +1,5 @@
+use anyhow::Result;
+
+fn main() -> Result<()> {
+    dylint_uitesting::review::main(std::env::args())
+}
@@ -0,0 +1,330 @@
+use crate::env;
+use anyhow::{Context, Result};
+use cargo_metadata::{
+    MetadataCommand,
+    camino::{Utf8Path, Utf8PathBuf},
+};
+use log::debug;
+use serde_json::{Value, json};
+use std::{
+    collections::BTreeSet,
+    fs::{File, OpenOptions, create_dir_all, read_dir, read_to_string, write},
+    hash::{DefaultHasher, Hash, Hasher},
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+/// Name of the directory (relative to the target directory) holding the cache.
+const CACHE_DIR: &str = "dylint_testing";
+
+/// A driver and library built by a previous test binary.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Entry {
+    pub driver: PathBuf,
+    pub library: PathBuf,
+}
+
+/// On-disk cache of the library build and driver path, shared across test binaries.
+///
+/// The cache file is keyed by library name, toolchain, Cargo profile, and prebuilt driver (if
+/// any), and stores a fingerprint of the library's build inputs. An exclusive lock on a sibling
+/// lock file is held for the lifetime of the `Cache`, so concurrent test binaries block on a single
+/// build rather than racing in the same target directory.
+pub struct Cache {
+    path: PathBuf,
+    fingerprint: String,
+    // The lock is released when the file is closed.
+    _lock: File,
+}
+
+impl Cache {
+    pub fn open(
+        target_directory: &Utf8Path,
+        name: &str,
+        toolchain: &str,
+        profile: &str,
+        driver_override: Option<&Path>,
+        package_root: &Path,
+    ) -> Result<Self> {
+        let dir = target_directory.join(CACHE_DIR);
+        create_dir_all(&dir).with_context(|| format!("`create_dir_all` failed for `{dir}`"))?;
+
+        // An entry holding a prebuilt driver must not be used by a run without one, or vice versa.
+        let mut key = format!("{name}@{toolchain}@{profile}");
+        if let Some(driver) = driver_override {
+            let mut hasher = DefaultHasher::new();
+            driver.hash(&mut hasher);
+            key.push_str(&format!("@{:016x}", hasher.finish()));
+        }
+        let lock = lock(&dir.join(format!("{key}.lock")))?;
+
+        let fingerprint = fingerprint(package_root)?;
+
+        Ok(Self {
+            path: dir.join(format!("{key}.json")).into_std_path_buf(),
+            fingerprint,
+            _lock: lock,
+        })
+    }
+
+    /// Returns the cached entry if the library's fingerprint is unchanged and both the driver and
+    /// library still exist.
+    pub fn load(&self) -> Option<Entry> {
+        let contents = read_to_string(&self.path).ok()?;
+        let value = serde_json::from_str::<Value>(&contents).ok()?;
+        if value["fingerprint"].as_str()? != self.fingerprint {
+            debug!(
+                "cache: Fingerprint changed, ignoring {}",
+                self.path.display()
+            );
+            return None;
+        }
+        let entry = Entry {
+            driver: PathBuf::from(value["driver"].as_str()?),
+            library: PathBuf::from(value["library"].as_str()?),
+        };
+        (entry.driver.exists() && entry.library.exists()).then_some(entry)
+    }
+
+    pub fn store(&self, entry: &Entry) -> Result<()> {
+        let value = json!({
+            "fingerprint": self.fingerprint,
+            "driver": entry.driver,
+            "library": entry.library,
+        });
+        write(&self.path, value.to_string())
+            .with_context(|| format!("Could not write `{}`", self.path.display()))
+    }
+}
+
+/// Hashes the inputs from which Cargo decides whether the library is fresh, so that the build is
+/// skipped only when Cargo would do nothing: the manifests, build scripts, and library sources of
+/// the package at `package_root` and of the path dependencies it reaches, the lockfiles, toolchain
+/// files, and Cargo configuration files in `package_root`, its ancestors, and `CARGO_HOME`, and the
+/// variables that pass flags to `rustc`.
+///
+/// Files are hashed by path, size, and modification time.
+pub fn fingerprint(package_root: &Path) -> Result<String> {
+    let mut files = Vec::new();
+    collect_package_files(
+        &package_root.join("Cargo.toml"),
+        &mut BTreeSet::new(),
+        &mut files,
+    )?;
+    for dir in package_root.ancestors() {
+        for name in [
+            "Cargo.lock",
+            "rust-toolchain",
+            "rust-toolchain.toml",
+            ".cargo/config",
+            ".cargo/config.toml",
+        ] {
+            files.push(dir.join(name));
+        }
+    }
+    if let Some(cargo_home) = std::env::var_os(env::CARGO_HOME) {
+        files.extend(["config", "config.toml"].map(|name| Path::new(&cargo_home).join(name)));
+    }
+    files.retain(|path| path.is_file());
+    files.sort();
+    files.dedup();
+
+    let mut hasher = DefaultHasher::new();
+    fingerprint_files(files)?.hash(&mut hasher);
+    for var in [
+        env::CARGO_BUILD_RUSTFLAGS,
+        env::CARGO_ENCODED_RUSTFLAGS,
+        env::RUSTFLAGS,
+    ] {
+        std::env::var_os(var).hash(&mut hasher);
+    }
+    Ok(format!("{:016x}", hasher.finish()))
+}
+
+/// Adds the manifests, build scripts, and library sources of the packages in the workspace of
+/// `manifest_path` to `files`, and then those of the packages their path dependencies are in.
+/// Packages whose manifests are in `visited` are skipped.
+fn collect_package_files(
+    manifest_path: &Path,
+    visited: &mut BTreeSet<Utf8PathBuf>,
+    files: &mut Vec<PathBuf>,
+) -> Result<()> {
+    let metadata = MetadataCommand::new()
+        .manifest_path(manifest_path)
+        .no_deps()
+        .exec()
+        .with_context(|| format!("Could not get metadata for `{}`", manifest_path.display()))?;
+    let mut dependencies = Vec::new();
+    for package in metadata.packages {
+        if !visited.insert(package.manifest_path.clone()) {
+            continue;
+        }
+        files.push(package.manifest_path.into_std_path_buf());
+        for target in package.targets {
+            if target.is_custom_build() {
+                files.push(target.src_path.into_std_path_buf());
+            } else if !(target.is_bin()
+                || target.is_example()
+                || target.is_test()
+                || target.is_bench())
+            {
+                // A library's modules are beneath the directory of its root source file, wherever
+                // `[lib] path` puts it.
+                if let Some(dir) = target.src_path.parent() {
+                    collect_files(dir.as_std_path(), files)?;
+                }
+            }
+        }
+        dependencies.extend(
+            package
+                .dependencies
+                .into_iter()
+                .filter_map(|dependency| dependency.path)
+                .map(|path| path.join("Cargo.toml")),
+        );
+    }
+    for manifest_path in dependencies {
+        if !visited.contains(&manifest_path) {
+            collect_package_files(manifest_path.as_std_path(), visited, files)?;
+        }
+    }
+    Ok(())
+}
+
+/// Hashes the paths, sizes, and modification times of `files`, in any order.
+pub fn fingerprint_files(mut files: Vec<PathBuf>) -> Result<String> {
+    files.sort();
+
+    let mut hasher = DefaultHasher::new();
+    for path in files {
+        let metadata = path
+            .metadata()
+            .with_context(|| format!("Could not get metadata of `{}`", path.display()))?;
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .unwrap_or_default();
+        path.hash(&mut hasher);
+        metadata.len().hash(&mut hasher);
+        modified.hash(&mut hasher);
+    }
+    Ok(format!("{:016x}", hasher.finish()))
+}
+
+/// Takes an exclusive lock on `lock_path`, creating the file and its parent directory if needed, and
+/// blocking until any other process holding the lock releases it.
+///
+/// The lock is released when the returned file is closed.
+pub fn lock(lock_path: &Utf8Path) -> Result<File> {
+    if let Some(parent) = lock_path.parent() {
+        create_dir_all(parent)
+            .with_context(|| format!("`create_dir_all` failed for `{parent}`"))?;
+    }
+    let lock = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(lock_path)
+        .with_context(|| format!("Could not open `{lock_path}`"))?;
+    debug!("cache: Waiting for lock on {}", lock_path);
+    lock.lock()
+        .with_context(|| format!("Could not lock `{lock_path}`"))?;
+    debug!("cache: Acquired lock on {}", lock_path);
+    Ok(lock)
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    if !dir.is_dir() {
+        return Ok(());
+    }
+    for entry in
+        read_dir(dir).with_context(|| format!("`read_dir` failed for `{}`", dir.display()))?
+    {
+        let entry = entry.with_context(|| format!("`read_dir` failed for `{}`", dir.display()))?;
+        let path = entry.path();
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Writes a package named `name` beneath `dir`, depending on the packages at `paths`.
+    fn write_package(dir: &Path, name: &str, paths: &[&str]) {
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+        let mut manifest = format!(
+            "[package]\nname = \"{name}\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n[workspace]\n\n\
+             [dependencies]\n"
+        );
+        for path in paths {
+            let dependency = Path::new(path).file_name().unwrap().to_string_lossy();
+            manifest.push_str(&format!("{dependency} = {{ path = \"{path}\" }}\n"));
+        }
+        std::fs::write(dir.join("Cargo.toml"), manifest).unwrap();
+        std::fs::write(dir.join("src/lib.rs"), "").unwrap();
+    }
+
+    #[test]
+    fn fingerprint_changes_with_sources() {
+        let tmp = tempfile::tempdir().unwrap();
+        write_package(tmp.path(), "lint", &[]);
+
+        let before = fingerprint(tmp.path()).unwrap();
+        assert_eq!(before, fingerprint(tmp.path()).unwrap());
+
+        std::fs::write(tmp.path().join("src/lib.rs"), "fn f() {}\n").unwrap();
+        assert_ne!(before, fingerprint(tmp.path()).unwrap());
+    }
+
+    #[test]
+    fn fingerprint_changes_with_path_dependencies() {
+        let tmp = tempfile::tempdir().unwrap();
+        let lint = tmp.path().join("lint");
+        let utils = tmp.path().join("utils");
+        write_package(&lint, "lint", &["../utils"]);
+        write_package(&utils, "utils", &[]);
+
+        let before = fingerprint(&lint).unwrap();
+        assert_eq!(before, fingerprint(&lint).unwrap());
+
+        std::fs::write(utils.join("src/lib.rs"), "pub fn f() {}\n").unwrap();
+        assert_ne!(before, fingerprint(&lint).unwrap());
+    }
+
+    #[test]
+    fn prebuilt_driver_is_not_used_without_override() {
+        let tmp = tempfile::tempdir().unwrap();
+        write_package(tmp.path(), "lint", &[]);
+        let target_directory = Utf8Path::from_path(tmp.path()).unwrap();
+        let prebuilt = tmp.path().join("prebuilt_driver");
+        let library = tmp.path().join("library.so");
+        std::fs::write(&prebuilt, "").unwrap();
+        std::fs::write(&library, "").unwrap();
+        let open = |driver_override| {
+            Cache::open(
+                target_directory,
+                "lib",
+                "nightly",
+                "debug",
+                driver_override,
+                tmp.path(),
+            )
+            .unwrap()
+        };
+
+        let entry = Entry {
+            driver: prebuilt.clone(),
+            library,
+        };
+        open(Some(prebuilt.as_path())).store(&entry).unwrap();
+        assert_eq!(open(Some(prebuilt.as_path())).load(), Some(entry));
+        assert_eq!(open(None).load(), None);
+    }
+}
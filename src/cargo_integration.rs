@@ -1,17 +1,46 @@
-use crate::env;
+use crate::{cache, env, runtime::offline_note, rustc_wrapper, ui};
 use anyhow::{Context, Result, anyhow, ensure};
-use cargo_metadata::{Metadata, Package, Target, TargetKind};
-use dylint_internal::{CommandExt, rustup::is_rustc};
-use regex::Regex;
+use cargo_metadata::{
+    BuildScript, Message, Metadata, Package, Target, TargetKind,
+    camino::{Utf8Path, Utf8PathBuf},
+};
 use std::{
-    env::consts,
-    fs::{read_dir, remove_file},
-    io::BufRead,
-    sync::{LazyLock, OnceLock},
+    collections::{BTreeMap, BTreeSet},
+    fs::{read_dir, read_to_string, remove_file},
+    hash::{DefaultHasher, Hash, Hasher},
+    path::Path,
+    process::Stdio,
+    sync::Mutex,
 };
 
-static RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\s*Running\s*`(.*)`$").unwrap());
-static LINKING_FLAGS: OnceLock<Vec<String>> = OnceLock::new();
+/// The flags and environment with which the driver compiles a target as Cargo did
+#[derive(Debug, Default)]
+pub struct Linking {
+    pub flags: Vec<String>,
+    /// `OUT_DIR` and the variables set by the package's build script, if it has one
+    pub envs: Vec<(String, String)>,
+}
+
+/// Linking flags and environments keyed by target directory, toolchain, compilation target,
+/// features, package id, target source path, and additionally retained flags.
+//
+// Examples can have different dependencies and editions, so their flags must not be
+// shared. The target directory is part of the key because the flags contain paths into it.
+#[allow(clippy::type_complexity)]
+static LINKING: Mutex<
+    BTreeMap<
+        (
+            Utf8PathBuf,
+            Option<String>,
+            Option<String>,
+            String,
+            String,
+            Utf8PathBuf,
+            Vec<String>,
+        ),
+        &'static Linking,
+    >,
+> = Mutex::new(BTreeMap::new());
 
 // smoelius: We need to recover the `rustc` flags used to build a target. I can see four options:
 //
@@ -29,13 +58,37 @@ static LINKING_FLAGS: OnceLock<Vec<String>> = OnceLock::new();
 //   - Pros: Ground truth
 //   - Cons: Requires a separate executable/script, portability could be an issue
 //
-// I am going with the second option for now, because it seems to be the least of all evils. This
-// decision may need to be revisited.
+// The second option was used originally, because it seemed to be the least of all evils. But
+// parsing human-readable output proved fragile, e.g., with spaces in paths. The fourth option is
+// now used instead. The wrapper is a small `std`-only program that is compiled on first use (see
+// `rustc_wrapper`), which avoids portability issues.
+//
+// Nothing is parsed from Cargo's human-readable output. The build is run with
+// `--message-format=json-render-diagnostics`, and the paths of the target's artifacts are taken from
+// its `compiler-artifact` messages. The crate name and type under which an invocation is recorded
+// follow from the target's metadata. (`--unit-graph` would give the same information before
+// building, but it is unstable.)
+//
+// The wrapper records each invocation under `target/dylint_testing/rustc_invocations`. The records
+// persist across runs. So if `cargo build` finds a target fresh and does not invoke `rustc`, the
+// previously recorded invocation is still accurate, and the target need not be removed to force a
+// rebuild. Invocations are recorded per toolchain, compilation target, and feature set, so that a
+// variant not yet built is not handed the flags of another, e.g., `--extern` paths to rlibs built by
+// another toolchain.
 
 fn snake_case(name: &str) -> String {
     name.replace('-', "_")
 }
 
+pub fn package_with_name(metadata: &Metadata, name: &str) -> Result<Package> {
+    metadata
+        .packages
+        .iter()
+        .find(|package| package.name == name && metadata.workspace_members.contains(&package.id))
+        .cloned()
+        .ok_or_else(|| anyhow!("Could not find workspace member `{}`", name))
+}
+
 pub fn example_target(package: &Package, example: &str) -> Result<Target> {
     package
         .targets
@@ -45,6 +98,15 @@ pub fn example_target(package: &Package, example: &str) -> Result<Target> {
         .ok_or_else(|| anyhow!("Could not find example `{}`", example))
 }
 
+pub fn bin_target(package: &Package, bin: &str) -> Result<Target> {
+    package
+        .targets
+        .iter()
+        .find(|target| target.kind == [TargetKind::Bin] && target.name == bin)
+        .cloned()
+        .ok_or_else(|| anyhow!("Could not find binary `{}`", bin))
+}
+
 #[allow(clippy::unnecessary_wraps)]
 pub fn example_targets(package: &Package) -> Result<Vec<Target>> {
     Ok(package
@@ -55,92 +117,269 @@ pub fn example_targets(package: &Package) -> Result<Vec<Target>> {
         .collect())
 }
 
-pub fn rustc_flags(metadata: &Metadata, package: &Package, target: &Target) -> Result<Vec<String>> {
-    // smoelius: The following comments are old and retained for posterity. The linking flags are
-    // now initialized using a `OnceCell`, which makes the mutex unnecessary.
-    //   smoelius: Force rebuilding of the example by removing it. This is kind of messy. The
-    //   example is a shared resource that may be needed by multiple tests. For now, I lock a mutex
-    //   while the example is removed and put back.
-    //   smoelius: Should we use a temporary target directory here?
-    let output = {
-        remove_example(metadata, package, target)?;
-
-        // smoelius: Because of lazy initialization, `cargo build` is run only once. Seeing
-        // "Building example `target`" for one example but not for others is confusing. So instead
-        // say "Building `package` examples".
-        dylint_internal::cargo::build(&format!("`{}` examples", package.name))
-            .build()
+/// Returns the required features of `target` that `package` cannot enable, because it declares no
+/// such feature or optional dependency.
+pub fn unsatisfiable_features(package: &Package, target: &Target) -> Vec<String> {
+    target
+        .required_features
+        .iter()
+        .filter(|feature| {
+            let name = feature.split('/').next().unwrap_or_default();
+            let name = name.strip_prefix("dep:").unwrap_or(name);
+            !package.features.contains_key(name)
+                && !package.dependencies.iter().any(|dependency| {
+                    dependency.optional
+                        && dependency.rename.as_deref().unwrap_or(&dependency.name) == name
+                })
+        })
+        .cloned()
+        .collect()
+}
+
+pub fn test_target(package: &Package, test: &str) -> Result<Target> {
+    package
+        .targets
+        .iter()
+        .find(|target| target.kind == [TargetKind::Test] && target.name == test)
+        .cloned()
+        .ok_or_else(|| anyhow!("Could not find integration test `{}`", test))
+}
+
+/// Returns the `rustc` flags with which Cargo built `target`, and the output of the package's build
+/// script, if it has one.
+pub fn rustc_flags(
+    metadata: &Metadata,
+    package: &Package,
+    target: &Target,
+    config: &ui::Config,
+) -> Result<(Vec<String>, Option<BuildScript>)> {
+    let (target_flag, description) = if target.kind == [TargetKind::Example] {
+        ("--example", "examples")
+    } else if target.kind == [TargetKind::Bin] {
+        ("--bin", "binaries")
+    } else if target.kind == [TargetKind::Test] {
+        ("--test", "tests")
+    } else {
+        return Err(anyhow!("Unsupported target kind for `{}`", target.name));
+    };
+    // The wrapper records an invocation under its crate type, or under `test` for a test harness.
+    // The crate types come from Cargo's metadata, so they need not be guessed.
+    let kinds = if target.kind == [TargetKind::Test] {
+        vec!["test".to_owned()]
+    } else {
+        target.crate_types.iter().map(ToString::to_string).collect()
+    };
+
+    let wrapper = rustc_wrapper::path(&metadata.target_directory)?;
+    let log_dir = invocations_dir(&metadata.target_directory, &package.name, config);
+    let find_invocation = || {
+        kinds
+            .iter()
+            .map(|kind| log_dir.join(format!("{}-{kind}", snake_case(&target.name))))
+            .find(|path| path.exists())
+    };
+
+    let build = || -> Result<Vec<u8>> {
+        // smoelius: Seeing "Building example `target`" for one example but not for others is
+        // confusing. So instead say "Building `package` examples".
+        let mut command =
+            dylint_internal::cargo::build(&format!("`{}` {description}", package.name)).build();
+        for key in env::cleared_vars(config, true) {
+            command.env_remove(key);
+        }
+        command
             .env_remove(env::CARGO_TERM_COLOR)
+            .env(env::RUSTC_WORKSPACE_WRAPPER, &wrapper)
+            .env(env::DYLINT_TESTING_RUSTC_LOG, &log_dir)
             .args([
                 "--manifest-path",
                 package.manifest_path.as_ref(),
-                "--example",
+                "--target-dir",
+                metadata.target_directory.as_ref(),
+                // Diagnostics are still rendered to stderr, while stdout holds the artifact
+                // messages.
+                "--message-format=json-render-diagnostics",
+                target_flag,
                 &target.name,
-                "--verbose",
-            ])
-            .logged_output(true)?
+            ]);
+        if config.all_features {
+            command.arg("--all-features");
+        } else {
+            let features = target
+                .required_features
+                .iter()
+                .chain(&config.features)
+                .map(String::as_str)
+                .collect::<Vec<_>>();
+            if !features.is_empty() {
+                command.args(["--features", &features.join(",")]);
+            }
+        }
+        // The target's dependencies must be built by the toolchain the driver uses.
+        if let Some(toolchain) = &config.toolchain {
+            command.env(env::RUSTUP_TOOLCHAIN, toolchain);
+        }
+        if let Some(target) = &config.target {
+            command.args(["--target", target]);
+        }
+        if config.offline {
+            command.arg("--offline");
+        }
+        let output = command
+            .stderr(Stdio::inherit())
+            .output()
+            .with_context(|| offline_note(config))?;
+        ensure!(output.status.success(), "{}", offline_note(config));
+        Ok(output.stdout)
     };
 
-    let matches = output
-        .stderr
-        .lines()
-        .map(|line| {
-            let line =
-                line.with_context(|| format!("Could not read from `{}`", package.manifest_path))?;
-            Ok((*RE).captures(&line).and_then(|captures| {
-                let args = captures[1]
-                    .split(' ')
-                    .map(ToOwned::to_owned)
-                    .collect::<Vec<_>>();
-                if args.first().is_some_and(is_rustc)
-                    && args
-                        .as_slice()
-                        .windows(2)
-                        .any(|window| window == ["--crate-name", &snake_case(&target.name)])
-                {
-                    Some(args)
-                } else {
-                    None
-                }
-            }))
-        })
-        .collect::<Result<Vec<Option<Vec<_>>>>>()?;
+    // Test binaries for other packages in the workspace may share the target directory. Hold a lock
+    // while building, so that one binary cannot remove artifacts or invocations another is reading.
+    let _lock = cache::lock(&log_dir.with_extension("lock"))?;
 
-    let mut matches = matches.into_iter().flatten().collect::<Vec<Vec<_>>>();
-    ensure!(
-        matches.len() <= 1,
-        "Found multiple `rustc` invocations for `{}`",
-        target.name
-    );
-    matches
-        .pop()
-        .ok_or_else(|| anyhow!("Found no `rustc` invocations for `{}`", target.name))
+    let stdout = build()?;
+
+    // The target was fresh, but no invocation was recorded, e.g., because
+    // `target/dylint_testing` was removed. Force a rebuild by removing the target's artifacts.
+    let path = if let Some(path) = find_invocation() {
+        path
+    } else {
+        remove_artifacts(target, &artifact_files(&stdout, target))?;
+        build()?;
+        find_invocation()
+            .ok_or_else(|| anyhow!("Found no `rustc` invocations for `{}`", target.name))?
+    };
+
+    Ok((
+        read_invocation(path.as_std_path())?,
+        build_script(&stdout, package),
+    ))
+}
+
+/// Returns the directory in which the wrapper records the invocations for `package_name`.
+fn invocations_dir(
+    target_directory: &Utf8Path,
+    package_name: &str,
+    config: &ui::Config,
+) -> Utf8PathBuf {
+    let mut log_dir = target_directory
+        .join("dylint_testing/rustc_invocations")
+        .join(package_name);
+    // Invocations for different toolchains, compilation targets, or feature sets must not
+    // overwrite one another. A toolchain can be named by its path, and feature names can contain
+    // `/`, so both are hashed.
+    if let Some(toolchain) = &config.toolchain {
+        log_dir.push(format!("toolchain-{}", hash(toolchain)));
+    }
+    if let Some(target) = &config.target {
+        log_dir.push(target);
+    }
+    let features = features_key(config);
+    if !features.is_empty() {
+        log_dir.push(format!("features-{}", hash(&features)));
+    }
+    log_dir
+}
+
+fn hash(key: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
 }
 
-pub fn linking_flags(
+/// Reads an invocation recorded by the wrapper, which writes one argument per line.
+fn read_invocation(path: &Path) -> Result<Vec<String>> {
+    let contents =
+        read_to_string(path).with_context(|| format!("Could not read `{}`", path.display()))?;
+    let args = contents.lines().map(ToOwned::to_owned).collect::<Vec<_>>();
+    ensure!(!args.is_empty(), "`{}` is empty", path.display());
+    Ok(args)
+}
+
+pub fn linking(
     metadata: &Metadata,
     package: &Package,
     target: &Target,
-) -> Result<&'static [String]> {
-    if let Some(existing) = LINKING_FLAGS.get() {
-        return Ok(existing.as_slice());
+    config: &ui::Config,
+) -> Result<&'static Linking> {
+    // The lock is held while building, so that each target is built at most once.
+    let mut cache = LINKING.lock().unwrap();
+    let key = (
+        metadata.target_directory.clone(),
+        config.toolchain.clone(),
+        config.target.clone(),
+        features_key(config),
+        package.id.repr.clone(),
+        target.src_path.clone(),
+        config.retain_flags.clone(),
+    );
+
+    if let Some(existing) = cache.get(&key) {
+        return Ok(existing);
+    }
+
+    let (rustc_flags, build_script) = rustc_flags(metadata, package, target, config)?;
+    let mut linking = Linking {
+        flags: retain_linking_flags(rustc_flags, &config.retain_flags)?,
+        envs: Vec::new(),
+    };
+    // Code generated by the build script is found through `OUT_DIR`, e.g., with
+    // `include!(concat!(env!("OUT_DIR"), "/generated.rs"))`. The cfgs the script emits are among
+    // the retained flags.
+    if let Some(build_script) = build_script {
+        linking
+            .envs
+            .push((env::OUT_DIR.to_owned(), build_script.out_dir.into_string()));
+        linking.envs.extend(build_script.env);
     }
 
-    let rustc_flags = rustc_flags(metadata, package, target)?;
+    let linking = Box::leak(Box::new(linking));
+    cache.insert(key, linking);
+    Ok(linking)
+}
 
+fn features_key(config: &ui::Config) -> String {
+    if config.all_features {
+        "*".to_owned()
+    } else {
+        config.features.join(",")
+    }
+}
+
+/// Returns the flags in `rustc_flags` that the driver needs to compile the target as Cargo did.
+///
+/// Besides the flags retained by default, each flag in `retain` is retained, along with its
+/// argument, which is either attached (e.g., `--remap-path-prefix=...` or `-Cdebuginfo=2`) or the
+/// next flag if that does not start with `-`.
+fn retain_linking_flags(rustc_flags: Vec<String>, retain: &[String]) -> Result<Vec<String>> {
     let mut linking_flags = Vec::new();
-    let mut iter = rustc_flags.into_iter();
+    let mut iter = rustc_flags.into_iter().peekable();
     while let Some(flag) = iter.next() {
-        if flag.starts_with("--edition=") {
+        // `--test` is needed so that `#[cfg(test)]` code and the test harness are seen
+        // exactly as they are when the integration test is built.
+        if flag.starts_with("--edition=") || flag == "--test" {
             linking_flags.push(flag);
         } else if flag == "--extern" || flag == "-L" {
             let arg = next_arg_for_flag(&flag, &mut iter)?;
-            linking_flags.extend([flag, arg.trim_matches('\'').to_owned()]);
+            linking_flags.extend([flag, arg]);
+        } else if flag == "--cfg" || flag == "--check-cfg" || flag == "--cap-lints" {
+            // Enabled features and the cfgs set by build scripts must be seen, so that the same
+            // code is linted as is built. `--check-cfg` keeps `unexpected_cfgs` as Cargo has it.
+            let arg = next_arg_for_flag(&flag, &mut iter)?;
+            linking_flags.extend([flag, arg]);
+        } else if retain.contains(&flag) {
+            linking_flags.push(flag);
+            if let Some(arg) = iter.next_if(|arg| !arg.starts_with('-')) {
+                linking_flags.push(arg);
+            }
+        } else if retain
+            .iter()
+            .any(|retained| flag.starts_with(retained.as_str()))
+        {
+            linking_flags.push(flag);
         }
     }
-
-    let _ = LINKING_FLAGS.set(linking_flags);
-    Ok(LINKING_FLAGS.get().unwrap().as_slice())
+    Ok(linking_flags)
 }
 
 fn next_arg_for_flag<I, T>(flag: &str, iter: &mut I) -> Result<T>
@@ -151,19 +390,69 @@ where
         .ok_or_else(|| anyhow!("Missing argument for `{}`", flag))
 }
 
-pub fn remove_example(metadata: &Metadata, _package: &Package, target: &Target) -> Result<()> {
-    let examples = metadata.target_directory.join("debug/examples");
-    for entry in
-        read_dir(&examples).with_context(|| format!("`read_dir` failed for `{examples}`"))?
-    {
-        let entry = entry.with_context(|| format!("`read_dir` failed for `{examples}`"))?;
-        let path = entry.path();
+/// Returns the files of `target`'s artifacts, as reported in Cargo's JSON messages.
+///
+/// Cargo knows where it put them, whatever the target's `path` and the compilation target, so
+/// their names need not be guessed.
+fn artifact_files(stdout: &[u8], target: &Target) -> Vec<Utf8PathBuf> {
+    Message::parse_stream(stdout)
+        .filter_map(Result::ok)
+        .filter_map(|message| match message {
+            Message::CompilerArtifact(artifact)
+                if artifact.target.name == target.name && artifact.target.kind == target.kind =>
+            {
+                Some(artifact.filenames)
+            }
+            _ => None,
+        })
+        .flatten()
+        .collect()
+}
 
-        if let Some(file_name) = path.file_name() {
-            let s = file_name.to_string_lossy();
-            let target_name = snake_case(&target.name);
-            if s == target_name.clone() + consts::EXE_SUFFIX
-                || s.starts_with(&(target_name.clone() + "-"))
+/// Returns the output of `package`'s build script, as reported in Cargo's JSON messages.
+///
+/// Cargo reports it even if the script was fresh and did not run.
+fn build_script(stdout: &[u8], package: &Package) -> Option<BuildScript> {
+    Message::parse_stream(stdout)
+        .filter_map(Result::ok)
+        .find_map(|message| match message {
+            Message::BuildScriptExecuted(build_script) if build_script.package_id == package.id => {
+                Some(build_script)
+            }
+            _ => None,
+        })
+}
+
+/// Removes `target`'s artifacts, so that Cargo rebuilds it.
+///
+/// Besides the files Cargo reported, the files next to them whose names are the target's crate
+/// name followed by a hash (e.g., `foo-0123456789abcdef` and its `.d` file) are removed, since
+/// Cargo decides freshness from those.
+pub fn remove_artifacts(target: &Target, artifacts: &[Utf8PathBuf]) -> Result<()> {
+    let prefix = snake_case(&target.name) + "-";
+    let mut dirs = BTreeSet::new();
+
+    for artifact in artifacts {
+        if artifact.is_file() {
+            remove_file(artifact)
+                .with_context(|| format!("`remove_file` failed for `{artifact}`"))?;
+        }
+        dirs.extend(artifact.parent());
+    }
+
+    for dir in dirs {
+        if !dir.is_dir() {
+            continue;
+        }
+
+        for entry in read_dir(dir).with_context(|| format!("`read_dir` failed for `{dir}`"))? {
+            let entry = entry.with_context(|| format!("`read_dir` failed for `{dir}`"))?;
+            let path = entry.path();
+
+            if path.is_file()
+                && path
+                    .file_name()
+                    .is_some_and(|file_name| file_name.to_string_lossy().starts_with(&prefix))
             {
                 remove_file(&path).with_context(|| {
                     format!("`remove_file` failed for `{}`", path.to_string_lossy())
@@ -174,3 +463,144 @@ pub fn remove_example(metadata: &Metadata, _package: &Package, target: &Target)
 
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn invocations_are_recorded_per_feature_set() {
+        let target_directory = Utf8Path::new("target");
+        let dir = |features: &[&str], all_features| {
+            let config = ui::Config {
+                features: features.iter().map(ToString::to_string).collect(),
+                all_features,
+                ..ui::Config::default()
+            };
+            invocations_dir(target_directory, "pkg", &config)
+        };
+        assert_eq!(
+            dir(&[], false),
+            Utf8Path::new("target/dylint_testing/rustc_invocations/pkg")
+        );
+        assert_ne!(dir(&["a"], false), dir(&[], false));
+        assert_ne!(dir(&["a"], false), dir(&["a", "b"], false));
+        assert_ne!(dir(&["a"], false), dir(&[], true));
+        assert_eq!(dir(&["dep/a"], false), dir(&["dep/a"], false));
+
+        let toolchain_dir = |toolchain: &str| {
+            let config = ui::Config {
+                toolchain: Some(toolchain.to_owned()),
+                features: vec!["a".to_owned()],
+                ..ui::Config::default()
+            };
+            invocations_dir(target_directory, "pkg", &config)
+        };
+        assert_ne!(toolchain_dir("nightly-2025-01-01"), dir(&["a"], false));
+        assert_ne!(
+            toolchain_dir("nightly-2025-01-01"),
+            toolchain_dir("nightly-2025-06-01")
+        );
+    }
+
+    // Recorded invocations hold one argument per line, so no shell-style unquoting is needed, and
+    // paths containing spaces must come through intact.
+    #[test]
+    fn paths_with_spaces_are_preserved() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("foo-bin");
+        std::fs::write(
+            &path,
+            [
+                "--crate-name",
+                "foo",
+                "--edition=2021",
+                "examples/foo.rs",
+                "--extern",
+                "foo=/path with space/libfoo.rlib",
+                "-L",
+                "dependency=/path with space/deps",
+                "-C",
+                "debuginfo=2",
+                "--cfg",
+                "feature=\"foo\"",
+                "--cfg",
+                "docsrs",
+                "--check-cfg",
+                "cfg(docsrs)",
+            ]
+            .join("\n"),
+        )
+        .unwrap();
+
+        let rustc_flags = read_invocation(&path).unwrap();
+        assert_eq!(
+            retain_linking_flags(rustc_flags, &[]).unwrap(),
+            [
+                "--edition=2021",
+                "--extern",
+                "foo=/path with space/libfoo.rlib",
+                "-L",
+                "dependency=/path with space/deps",
+                "--cfg",
+                "feature=\"foo\"",
+                "--cfg",
+                "docsrs",
+                "--check-cfg",
+                "cfg(docsrs)",
+            ]
+        );
+    }
+
+    #[test]
+    fn extra_flags_are_retained() {
+        let rustc_flags = [
+            "--crate-name",
+            "foo",
+            "-C",
+            "debuginfo=2",
+            "-Copt-level=0",
+            "--test",
+        ]
+        .map(ToOwned::to_owned)
+        .to_vec();
+        assert_eq!(
+            retain_linking_flags(rustc_flags, &["-C".to_owned()]).unwrap(),
+            ["-C", "debuginfo=2", "-Copt-level=0", "--test"]
+        );
+    }
+
+    #[test]
+    fn missing_argument_is_an_error() {
+        assert!(retain_linking_flags(vec!["--extern".to_owned()], &[]).is_err());
+    }
+
+    // An example whose `path` is outside `examples/` is still found by its name and kind.
+    #[test]
+    fn artifacts_are_read_from_json_messages() {
+        let artifact = |kind: &str, name: &str, src_path: &str, filename: &str| {
+            format!(
+                r#"{{"reason":"compiler-artifact","package_id":"path+file:///ex#0.1.0","manifest_path":"/ex/Cargo.toml","target":{{"kind":["{kind}"],"crate_types":["bin"],"name":"{name}","src_path":"{src_path}","edition":"2021","doc":false,"doctest":false,"test":false}},"profile":{{"opt_level":"0","debuginfo":2,"debug_assertions":true,"overflow_checks":true,"test":false}},"features":[],"filenames":["{filename}"],"executable":"{filename}","fresh":true}}"#
+            )
+        };
+        let stdout = [
+            artifact("bin", "foo", "/ex/src/main.rs", "/ex/target/debug/foo"),
+            artifact(
+                "example",
+                "foo",
+                "/ex/fixtures/foo.rs",
+                "/ex/target/debug/examples/foo",
+            ),
+            r#"{"reason":"build-finished","success":true}"#.to_owned(),
+        ]
+        .join("\n");
+        let target = serde_json::from_str::<Target>(
+            r#"{"kind":["example"],"crate_types":["bin"],"name":"foo","src_path":"/ex/fixtures/foo.rs","edition":"2021","doc":false,"doctest":false,"test":false}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            artifact_files(stdout.as_bytes(), &target),
+            [Utf8PathBuf::from("/ex/target/debug/examples/foo")]
+        );
+    }
+}
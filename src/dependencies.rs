@@ -0,0 +1,88 @@
+use anyhow::{Context, Result, ensure};
+use cargo_metadata::camino::Utf8Path;
+use std::{
+    fmt::Write as _,
+    fs::{create_dir_all, read_to_string, write},
+    path::PathBuf,
+};
+
+/// Name of the package whose manifest declares the dependencies.
+const PACKAGE_NAME: &str = "dylint_testing_dependencies";
+
+/// Returns the path of a manifest declaring `dependencies`, writing it into `target_directory` if
+/// necessary.
+///
+/// Each dependency is a crate name and a version requirement, as in a `[dependencies]` table. The
+/// manifest is rewritten only when its contents change, so that the dependencies are not rebuilt
+/// needlessly. It is keyed by library name, so that concurrently running test binaries for
+/// different libraries do not overwrite one another's manifests.
+pub fn manifest(
+    target_directory: &Utf8Path,
+    name: &str,
+    dependencies: &[(String, String)],
+) -> Result<PathBuf> {
+    let dir = target_directory
+        .join("dylint_testing/dependencies")
+        .join(name);
+    let src = dir.join("src");
+    create_dir_all(&src).with_context(|| format!("`create_dir_all` failed for `{src}`"))?;
+
+    let lib_rs = src.join("lib.rs");
+    if !lib_rs.exists() {
+        write(&lib_rs, "").with_context(|| format!("Could not write `{lib_rs}`"))?;
+    }
+
+    let contents = manifest_contents(dependencies)?;
+    let manifest_path = dir.join("Cargo.toml");
+    if read_to_string(&manifest_path).ok().as_deref() != Some(&contents) {
+        write(&manifest_path, contents)
+            .with_context(|| format!("Could not write `{manifest_path}`"))?;
+    }
+
+    Ok(manifest_path.into_std_path_buf())
+}
+
+fn manifest_contents(dependencies: &[(String, String)]) -> Result<String> {
+    let mut contents = format!(
+        "[package]\nname = \"{PACKAGE_NAME}\"\nversion = \"0.0.0\"\nedition = \"2021\"\npublish = false\n\n[dependencies]\n"
+    );
+    for (name, version) in dependencies {
+        ensure!(
+            !name.is_empty()
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'),
+            "Invalid dependency name `{name}`"
+        );
+        ensure!(
+            !version.contains(['"', '\\', '\n']),
+            "Invalid version requirement `{version}` for `{name}`"
+        );
+        writeln!(contents, "{name} = \"{version}\"").unwrap();
+    }
+    // An empty workspace keeps the package out of any enclosing workspace.
+    contents.push_str("\n[workspace]\n");
+    Ok(contents)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn manifest_declares_dependencies() {
+        let contents = manifest_contents(&[
+            ("serde".to_owned(), "1".to_owned()),
+            ("serde_json".to_owned(), "=1.0.100".to_owned()),
+        ])
+        .unwrap();
+        assert!(contents.contains("[dependencies]\nserde = \"1\"\nserde_json = \"=1.0.100\"\n"));
+        assert!(contents.ends_with("[workspace]\n"));
+    }
+
+    #[test]
+    fn invalid_dependencies_are_rejected() {
+        assert!(manifest_contents(&[("a b".to_owned(), "1".to_owned())]).is_err());
+        assert!(manifest_contents(&[("serde".to_owned(), "1\"".to_owned())]).is_err());
+    }
+}
@@ -0,0 +1,485 @@
+//! Diagnostics emitted by the driver in rustc's JSON format
+//!
+//! The types are those of [`cargo_metadata::diagnostic`], re-exported for use with
+//! [`Test::assert_diagnostics`](crate::ui::Test::assert_diagnostics).
+
+pub use cargo_metadata::diagnostic::{
+    Applicability, Diagnostic, DiagnosticCode, DiagnosticLevel, DiagnosticSpan, DiagnosticSpanLine,
+    DiagnosticSpanMacroExpansion,
+};
+
+use crate::{
+    diagnostics_wrapper, env, report,
+    runtime::Driver,
+    test_runner::{default_args, source_files},
+    ui,
+};
+use anyhow::{Context, Result, anyhow, bail};
+use log::debug;
+use serde_json::Value;
+use std::{
+    fs::{read_to_string, write},
+    path::Path,
+    process::Command,
+};
+
+/// Default edition used by ui_test when a file has no `//@edition:` header and no edition is
+/// otherwise configured.
+const DEFAULT_EDITION: &str = "2021";
+
+/// Keys removed from each diagnostic before comparison. Byte offsets shift with unrelated edits,
+/// and rendered text is what `.stderr` files are for.
+const UNSTABLE_KEYS: [&str; 3] = ["byte_start", "byte_end", "rendered"];
+
+/// Compiles `file` with the driver and returns its exit code and JSON diagnostics.
+///
+/// The driver is run in `file`'s directory and passed only the file name, so that file names in
+/// the diagnostics do not depend on where the fixtures live. If `revision` is given, the file is
+/// compiled as ui_test would compile that revision.
+pub(crate) fn compile_json(
+    driver: &Driver,
+    file: &Path,
+    revision: Option<&str>,
+    config: &ui::Config,
+) -> Result<(Option<i32>, Vec<Value>)> {
+    let out_dir = tempfile::tempdir().with_context(|| "`tempdir` failed")?;
+    let mut command = driver_command(driver, file, revision, config, out_dir.path())?;
+    command.arg("--error-format=json");
+
+    debug!("compile_json: {:?}", command);
+    let output = command
+        .output()
+        .with_context(|| format!("Could not run `{}`", driver.path.display()))?;
+
+    let diagnostics = String::from_utf8_lossy(&output.stderr)
+        .lines()
+        .filter(|line| line.starts_with('{'))
+        .filter_map(|line| serde_json::from_str::<Value>(line).ok())
+        .filter(|value| {
+            value
+                .get("$message_type")
+                .is_none_or(|ty| ty == "diagnostic")
+        })
+        .filter_map(|value| filter_levels(value, config.level_filter))
+        .collect();
+
+    Ok((output.status.code(), diagnostics))
+}
+
+/// Returns a command that compiles `file` (and `revision`) with the driver as ui_test would, with
+/// its output written to `out_dir`.
+///
+/// The driver is run in `file`'s directory and passed only the file name.
+pub(crate) fn driver_command(
+    driver: &Driver,
+    file: &Path,
+    revision: Option<&str>,
+    config: &ui::Config,
+    out_dir: &Path,
+) -> Result<Command> {
+    let source =
+        read_to_string(file).with_context(|| format!("Could not read `{}`", file.display()))?;
+    let dir = file
+        .parent()
+        .ok_or_else(|| anyhow!("Could not get parent of `{}`", file.display()))?;
+    let file_name = file
+        .file_name()
+        .ok_or_else(|| anyhow!("Could not get file name of `{}`", file.display()))?;
+
+    let default_edition = default_edition(config);
+
+    let mut command =
+        if let (Some(timeout), Some(wrapper)) = (config.timeout, &config.timeout_wrapper) {
+            let mut command = Command::new(wrapper);
+            command
+                .arg(timeout.as_millis().to_string())
+                .arg(&driver.path);
+            command
+        } else {
+            Command::new(&driver.path)
+        };
+    if let (Some(wrapper), Some(options)) = (
+        &config.diagnostics_wrapper,
+        diagnostics_wrapper::options(config, false),
+    ) {
+        let mut wrapped = Command::new(wrapper);
+        wrapped
+            .arg(options)
+            .arg(command.get_program())
+            .args(command.get_args());
+        command = wrapped;
+    }
+    if let Some(wrap_command) = &config.wrap_command {
+        wrap_command(&mut command);
+    }
+    for key in env::cleared_vars(config, false) {
+        command.env_remove(key);
+    }
+    command.envs(env::hermetic_vars(config));
+    command
+        .current_dir(dir)
+        .envs(driver.envs.iter().map(|(key, val)| (*key, val)))
+        .args(default_args(config))
+        .args(
+            config
+                .rustc_flags
+                .iter()
+                .filter(|flag| !flag.starts_with("--edition=")),
+        )
+        .args(header_flags(&source, default_edition, revision))
+        .arg("--out-dir")
+        .arg(out_dir)
+        .arg(file_name);
+    if let Some(dylint_toml) = &config.dylint_toml {
+        command.env(env::DYLINT_TOML, dylint_toml);
+    } else {
+        command.env_remove(env::DYLINT_TOML);
+    }
+    command.envs(config.envs.iter().map(|(key, val)| (key, val)));
+    if let Some(msrv) = header_msrv(&source, revision) {
+        command.env(env::CARGO_PKG_RUST_VERSION, msrv);
+    }
+    Ok(command)
+}
+
+/// Returns the edition under test, or else the one an example target was built with. Either is
+/// used unless a file has an `//@edition:` header.
+pub(crate) fn default_edition(config: &ui::Config) -> &str {
+    config
+        .edition
+        .as_deref()
+        .or_else(|| {
+            config
+                .rustc_flags
+                .iter()
+                .rev()
+                .find_map(|flag| flag.strip_prefix("--edition="))
+        })
+        .unwrap_or(DEFAULT_EDITION)
+}
+
+/// Compiles `file` with the driver and returns its diagnostics, excluding summaries.
+pub(crate) fn compile_diagnostics(
+    driver: &Driver,
+    file: &Path,
+    revision: Option<&str>,
+    config: &ui::Config,
+) -> Result<Vec<Diagnostic>> {
+    let (_, values) = compile_json(driver, file, revision, config)?;
+    values
+        .into_iter()
+        .filter(|value| !is_summary(value))
+        .map(serde_json::from_value::<Diagnostic>)
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("Could not parse diagnostics for `{}`", file.display()))
+}
+
+/// Compiles each file (and each of its revisions) beneath `src_base` and passes its diagnostics to
+/// `assert`.
+pub(crate) fn assert_diagnostics(
+    driver: &Driver,
+    src_base: &Path,
+    config: &ui::Config,
+    assert: &ui::AssertDiagnostics,
+) -> Result<()> {
+    for file in source_files(src_base, config)? {
+        for revision in file_revisions(&file)? {
+            let diagnostics = compile_diagnostics(driver, &file, revision.as_deref(), config)?;
+            debug!(
+                "assert_diagnostics: {} diagnostics for {} (revision {:?})",
+                diagnostics.len(),
+                file.display(),
+                revision
+            );
+            assert(&diagnostics);
+        }
+    }
+    Ok(())
+}
+
+/// Compares each file's normalized JSON diagnostics against its `.json` file, or writes the file
+/// when blessing.
+pub(crate) fn check_json_snapshots(
+    driver: &Driver,
+    src_base: &Path,
+    config: &ui::Config,
+    bless: bool,
+) -> Result<()> {
+    let mut mismatches = Vec::new();
+
+    for file in source_files(src_base, config)? {
+        for revision in file_revisions(&file)? {
+            let (_, diagnostics) = compile_json(driver, &file, revision.as_deref(), config)?;
+            let actual = normalize(Value::Array(diagnostics));
+            let snapshot = file.with_extension(expected_extension(revision.as_deref(), "json"));
+
+            if bless {
+                let contents = serde_json::to_string_pretty(&actual)? + "\n";
+                write(&snapshot, contents)
+                    .with_context(|| format!("Could not write `{}`", snapshot.display()))?;
+                continue;
+            }
+
+            let expected = if snapshot.exists() {
+                let contents = read_to_string(&snapshot)
+                    .with_context(|| format!("Could not read `{}`", snapshot.display()))?;
+                serde_json::from_str::<Value>(&contents)
+                    .with_context(|| format!("Could not parse `{}`", snapshot.display()))?
+            } else {
+                Value::Array(Vec::new())
+            };
+
+            let expected = normalize(expected);
+            if expected != actual {
+                mismatches.push(format!(
+                    "`{}` differs from actual diagnostics:\n{}",
+                    snapshot.display(),
+                    report::unified_diff(
+                        &snapshot.display().to_string(),
+                        "actual",
+                        &serde_json::to_string_pretty(&expected)?,
+                        &serde_json::to_string_pretty(&actual)?,
+                        config.diff_context_lines,
+                        report::color_enabled(),
+                    )
+                ));
+            }
+        }
+    }
+
+    if !mismatches.is_empty() {
+        bail!(
+            "{}\n\nRun `{}` to update the `.json` files.",
+            mismatches.join("\n\n"),
+            env::bless_command(config)
+        );
+    }
+
+    Ok(())
+}
+
+/// Returns `value` without its children at excluded levels, or `None` if `value` is itself at an
+/// excluded level.
+fn filter_levels(mut value: Value, level_filter: ui::LevelFilter) -> Option<Value> {
+    let level = value["level"].as_str().and_then(ui::Level::from_name);
+    if level.is_some_and(|level| !level_filter.contains(level)) {
+        return None;
+    }
+    if let Some(children) = value.get_mut("children").and_then(Value::as_array_mut) {
+        *children = std::mem::take(children)
+            .into_iter()
+            .filter_map(|child| filter_levels(child, level_filter))
+            .collect();
+    }
+    Some(value)
+}
+
+/// Removes unstable keys and summary diagnostics (e.g., "aborting due to 2 previous errors").
+fn normalize(value: Value) -> Value {
+    match value {
+        Value::Array(values) => Value::Array(
+            values
+                .into_iter()
+                .filter(|value| !is_summary(value))
+                .map(normalize)
+                .collect(),
+        ),
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .filter(|(key, _)| !UNSTABLE_KEYS.contains(&key.as_str()))
+                .map(|(key, value)| (key, normalize(value)))
+                .collect(),
+        ),
+        value => value,
+    }
+}
+
+pub(crate) fn is_summary(value: &Value) -> bool {
+    value["spans"].as_array().is_some_and(Vec::is_empty)
+        && value["children"].as_array().is_some_and(Vec::is_empty)
+        && value["message"].as_str().is_some_and(|message| {
+            message.starts_with("aborting due to")
+                || message.starts_with("Some errors have detailed explanations")
+                || message.starts_with("For more information about this error")
+        })
+}
+
+/// Returns the revisions declared by `file`'s `//@revisions:` header, or `[None]` if there are
+/// none, so that the result can be iterated over to visit each way the file is compiled.
+pub(crate) fn file_revisions(file: &Path) -> Result<Vec<Option<String>>> {
+    let source =
+        read_to_string(file).with_context(|| format!("Could not read `{}`", file.display()))?;
+    let revisions = revisions(&source);
+    if revisions.is_empty() {
+        Ok(vec![None])
+    } else {
+        Ok(revisions.into_iter().map(Some).collect())
+    }
+}
+
+/// Returns the revisions declared by `source`'s `//@revisions:` header.
+pub(crate) fn revisions(source: &str) -> Vec<String> {
+    source
+        .lines()
+        .filter_map(|line| line.trim_start().strip_prefix("//@revisions:"))
+        .flat_map(str::split_whitespace)
+        .map(ToOwned::to_owned)
+        .collect()
+}
+
+/// Returns the extension of a revision's expected file, as ui_test names them, e.g., `stderr` for
+/// `foo.stderr` or `rev.stderr` for `foo.rev.stderr`.
+pub(crate) fn expected_extension(revision: Option<&str>, extension: &str) -> String {
+    match revision {
+        Some(revision) => format!("{revision}.{extension}"),
+        None => extension.to_owned(),
+    }
+}
+
+/// Returns the header in `line`, if any, provided it applies to `revision`. Headers such as
+/// `//@[foo] compile-flags: ...` apply only to the listed revisions.
+pub(crate) fn header<'a>(line: &'a str, revision: Option<&str>) -> Option<&'a str> {
+    let header = line.trim_start().strip_prefix("//@")?;
+    let Some(rest) = header.strip_prefix('[') else {
+        return Some(header);
+    };
+    let (revisions, header) = rest.split_once(']')?;
+    let revision = revision?;
+    revisions
+        .split(',')
+        .any(|candidate| candidate.trim() == revision)
+        .then(|| header.trim_start())
+}
+
+/// Translates the `//@edition:` and `//@compile-flags:` headers understood by ui_test into
+/// command-line flags. Like ui_test, a revision is passed to the compiler as a `--cfg`.
+pub(crate) fn header_flags(
+    source: &str,
+    default_edition: &str,
+    revision: Option<&str>,
+) -> Vec<String> {
+    let mut edition = default_edition.to_owned();
+    let mut flags = Vec::new();
+    if let Some(revision) = revision {
+        flags.push(format!("--cfg={revision}"));
+    }
+    for line in source.lines() {
+        let Some(header) = header(line, revision) else {
+            continue;
+        };
+        if let Some(value) = header.strip_prefix("edition:") {
+            value.trim().clone_into(&mut edition);
+        } else if let Some(value) = header.strip_prefix("compile-flags:") {
+            flags.extend(value.split_whitespace().map(ToOwned::to_owned));
+        }
+    }
+    flags.insert(0, format!("--edition={edition}"));
+    flags
+}
+
+/// Returns the MSRV set by a `//@msrv:` header applying to `revision`, if any. The last such
+/// header wins, so that a revision's header can follow a default.
+pub(crate) fn header_msrv<'a>(source: &'a str, revision: Option<&str>) -> Option<&'a str> {
+    source
+        .lines()
+        .rev()
+        .find_map(|line| header(line, revision)?.strip_prefix("msrv:"))
+        .map(str::trim)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn normalize_ignores_offsets_and_summaries() {
+        let value = json!([
+            {
+                "message": "unused variable",
+                "rendered": "warning: unused variable",
+                "spans": [{ "byte_start": 10, "byte_end": 11, "line_start": 2 }],
+                "children": []
+            },
+            {
+                "message": "aborting due to 1 previous error",
+                "spans": [],
+                "children": []
+            }
+        ]);
+        assert_eq!(
+            normalize(value),
+            json!([
+                {
+                    "message": "unused variable",
+                    "spans": [{ "line_start": 2 }],
+                    "children": []
+                }
+            ])
+        );
+    }
+
+    #[test]
+    fn header_flags_default_edition() {
+        assert_eq!(
+            header_flags("fn main() {}\n", DEFAULT_EDITION, None),
+            ["--edition=2021"]
+        );
+        assert_eq!(
+            header_flags("fn main() {}\n", "2024", None),
+            ["--edition=2024"]
+        );
+        assert_eq!(
+            header_flags(
+                "//@edition: 2024\n//@compile-flags: --cfg foo\n",
+                DEFAULT_EDITION,
+                None
+            ),
+            ["--edition=2024", "--cfg", "foo"]
+        );
+    }
+
+    #[test]
+    fn header_flags_per_revision() {
+        let source =
+            "//@revisions: a b\n//@[a] compile-flags: --cfg x\n//@[a, b] compile-flags: -O\n";
+        assert_eq!(revisions(source), ["a", "b"]);
+        assert_eq!(
+            header_flags(source, DEFAULT_EDITION, Some("a")),
+            ["--edition=2021", "--cfg=a", "--cfg", "x", "-O"]
+        );
+        assert_eq!(
+            header_flags(source, DEFAULT_EDITION, Some("b")),
+            ["--edition=2021", "--cfg=b", "-O"]
+        );
+    }
+
+    #[test]
+    fn header_msrv_per_revision() {
+        let source = "//@revisions: old new\n//@msrv: 1.70\n//@[old] msrv: 1.60\n";
+        assert_eq!(header_msrv(source, None), Some("1.70"));
+        assert_eq!(header_msrv(source, Some("old")), Some("1.60"));
+        assert_eq!(header_msrv(source, Some("new")), Some("1.70"));
+        assert_eq!(header_msrv("fn main() {}\n", None), None);
+    }
+
+    #[test]
+    fn filter_levels_removes_excluded_children() {
+        let value = json!({
+            "level": "warning",
+            "children": [
+                { "level": "note", "children": [] },
+                { "level": "help", "children": [] }
+            ]
+        });
+        let level_filter = ui::Level::Warning | ui::Level::Help;
+        assert_eq!(
+            filter_levels(value.clone(), level_filter),
+            Some(json!({
+                "level": "warning",
+                "children": [{ "level": "help", "children": [] }]
+            }))
+        );
+        assert_eq!(filter_levels(value, ui::Level::Error.into()), None);
+    }
+}
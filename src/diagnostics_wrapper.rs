@@ -0,0 +1,54 @@
+use crate::{rustc_wrapper, ui};
+use anyhow::Result;
+use cargo_metadata::camino::Utf8Path;
+use std::path::PathBuf;
+
+/// Source of the wrapper that filters the driver's JSON diagnostics.
+const SOURCE: &str = include_str!("diagnostics_wrapper/main.rs");
+
+/// Returns the path of the wrapper, compiling it into `target_directory` if necessary.
+///
+/// The wrapper is invoked as `diagnostics_wrapper <options> <program> [args...]`, where `options`
+/// is a comma-separated list (see [`options`]).
+pub fn path(target_directory: &Utf8Path) -> Result<PathBuf> {
+    rustc_wrapper::compile(target_directory, "diagnostics_wrapper", SOURCE)
+}
+
+/// Returns the wrapper's options for `config`, or `None` if the driver need not be wrapped.
+///
+/// `dedup` drops repeated diagnostics. `spanless-as-text` replaces diagnostics without spans with
+/// their rendered text, so that ui_test keeps them in the output but does not require annotations
+/// for them. The latter is only wanted for ui_test (`for_ui_test`), since the crate's own JSON
+/// handling would lose the diagnostics. In JSON mode, `code-in-message` appends each diagnostic's
+/// code to its message for ui_test, so that an annotation such as `//~ ERROR my_lint` checks the
+/// lint's name and level. The `.json` files are unaffected. With [`ui::Test::check_suggestions`],
+/// `suggestions-in-message` appends each suggested replacement and its applicability to the
+/// message of the sub-diagnostic making it, so that an annotation such as
+/// `//~| HELP: SUGGESTION foo()` checks the suggestion. It only adds text to messages, so existing
+/// annotations still match. `only-lints=<name>|<name>...` drops the
+/// diagnostics whose code is not one of the library's lints.
+pub(crate) fn options(config: &ui::Config, for_ui_test: bool) -> Option<String> {
+    let options = [
+        (config.dedup_diagnostics, "dedup".to_owned()),
+        (
+            for_ui_test && !config.require_spanless_annotations,
+            "spanless-as-text".to_owned(),
+        ),
+        (
+            for_ui_test && config.diagnostics_format == ui::DiagnosticsFormat::Json,
+            "code-in-message".to_owned(),
+        ),
+        (
+            for_ui_test && config.check_suggestions,
+            "suggestions-in-message".to_owned(),
+        ),
+        (
+            config.only_lint_diagnostics,
+            format!("only-lints={}", config.library_lints.join("|")),
+        ),
+    ]
+    .into_iter()
+    .filter_map(|(enabled, option)| enabled.then_some(option))
+    .collect::<Vec<_>>();
+    (!options.is_empty()).then(|| options.join(","))
+}
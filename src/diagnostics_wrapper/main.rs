@@ -0,0 +1,283 @@
+// This file is compiled on first use and wraps the driver when its diagnostics are filtered. It
+// runs the program given on its command line and forwards its output. With the `dedup` option, any
+// JSON diagnostic with the same message, level, and primary span as an earlier one is dropped. With
+// the `spanless-as-text` option, JSON diagnostics without spans are replaced by their rendered
+// text. With the `code-in-message` option, a JSON diagnostic's code (e.g., its lint's name) is
+// appended to its message in brackets. With the `suggestions-in-message` option, each suggested
+// replacement is appended to the message of the sub-diagnostic making it, along with its
+// applicability. With the `only-lints=<name>|<name>...` option, JSON diagnostics whose code is not
+// one of the named lints are dropped. It must not depend on anything other than `std`.
+
+use std::{
+    collections::HashSet,
+    env::args_os,
+    io::{BufRead, BufReader, Write, stderr},
+    process::{Command, Stdio, exit},
+};
+
+fn main() {
+    let mut args = args_os().skip(1);
+    let (Some(options), Some(program)) = (args.next(), args.next()) else {
+        eprintln!("diagnostics_wrapper: usage: diagnostics_wrapper <options> <program> [args...]");
+        exit(1);
+    };
+    let options = options.to_string_lossy().into_owned();
+    let dedup = options.split(',').any(|option| option == "dedup");
+    let spanless_as_text = options.split(',').any(|option| option == "spanless-as-text");
+    let code_in_message = options.split(',').any(|option| option == "code-in-message");
+    let suggestions_in_message = options
+        .split(',')
+        .any(|option| option == "suggestions-in-message");
+    let only_lints = options
+        .split(',')
+        .find_map(|option| option.strip_prefix("only-lints="))
+        .map(|lints| lints.split('|').map(ToOwned::to_owned).collect::<HashSet<_>>());
+
+    let mut child = match Command::new(&program)
+        .args(args)
+        .stderr(Stdio::piped())
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(error) => {
+            eprintln!("diagnostics_wrapper: could not run {program:?}: {error}");
+            exit(1);
+        }
+    };
+
+    let mut seen = HashSet::new();
+    let mut stderr = stderr().lock();
+    for line in BufReader::new(child.stderr.take().unwrap()).split(b'\n') {
+        let Ok(line) = line else {
+            break;
+        };
+        let text = String::from_utf8_lossy(&line);
+        if only_lints
+            .as_ref()
+            .is_some_and(|lints| is_other_diagnostic(&text, lints))
+        {
+            continue;
+        }
+        if dedup && key(&text).is_some_and(|key| !seen.insert(key)) {
+            continue;
+        }
+        if let Some(rendered) = spanless_as_text.then(|| spanless_rendered(&text)).flatten() {
+            let _ = stderr.write_all(rendered.as_bytes());
+            continue;
+        }
+        if let Some(line) = annotated(&text, code_in_message, suggestions_in_message) {
+            let _ = stderr.write_all(line.as_bytes());
+            let _ = stderr.write_all(b"\n");
+            continue;
+        }
+        let _ = stderr.write_all(&line);
+        let _ = stderr.write_all(b"\n");
+    }
+
+    match child.wait() {
+        Ok(status) => exit(status.code().unwrap_or(1)),
+        Err(error) => {
+            eprintln!("diagnostics_wrapper: could not wait for {program:?}: {error}");
+            exit(1);
+        }
+    }
+}
+
+/// Returns the message, level, and primary span of the JSON diagnostic on `line`, or `None` if
+/// `line` is not a diagnostic with a primary span.
+fn key(line: &str) -> Option<String> {
+    let fields = members(line.trim())?;
+    if field(&fields, "$message_type").is_some_and(|ty| ty != "\"diagnostic\"") {
+        return None;
+    }
+    let message = field(&fields, "message")?;
+    let level = field(&fields, "level")?;
+    let span = elements(field(&fields, "spans")?)?
+        .into_iter()
+        .filter_map(members)
+        .find(|span| field(span, "is_primary") == Some("true"))?;
+    Some(format!(
+        "{message}\0{level}\0{}\0{}\0{}",
+        field(&span, "file_name")?,
+        field(&span, "byte_start")?,
+        field(&span, "byte_end")?
+    ))
+}
+
+/// Returns true if `line` is a JSON diagnostic whose code is not one of `lints`, e.g., an error
+/// from rustc or a warning from a lint of another library.
+fn is_other_diagnostic(line: &str, lints: &HashSet<String>) -> bool {
+    let Some(fields) = members(line.trim()) else {
+        return false;
+    };
+    if field(&fields, "$message_type").is_some_and(|ty| ty != "\"diagnostic\"") {
+        return false;
+    }
+    let code = field(&fields, "code")
+        .and_then(members)
+        .and_then(|code| unescape(field(&code, "code")?));
+    !code.is_some_and(|code| lints.contains(&code))
+}
+
+/// Returns the rendered text of the JSON diagnostic on `line`, if it has no spans.
+fn spanless_rendered(line: &str) -> Option<String> {
+    let fields = members(line.trim())?;
+    if field(&fields, "$message_type").is_some_and(|ty| ty != "\"diagnostic\"")
+        || !elements(field(&fields, "spans")?)?.is_empty()
+    {
+        return None;
+    }
+    unescape(field(&fields, "rendered")?)
+}
+
+/// Returns `line` with text appended to the messages of its JSON diagnostic, or `None` if nothing
+/// is appended.
+///
+/// If `code`, the diagnostic's code is appended to its message, e.g., `"unused variable
+/// [unused_variables]"`. If `suggestions`, each suggested replacement is appended to the message of
+/// the sub-diagnostic making it, e.g., `"try [SUGGESTION foo() (MachineApplicable)]"`.
+fn annotated(line: &str, code: bool, suggestions: bool) -> Option<String> {
+    let fields = members(line.trim())?;
+    if field(&fields, "$message_type").is_some_and(|ty| ty != "\"diagnostic\"") {
+        return None;
+    }
+
+    // Each insertion is made before the closing quote of a message, which is a slice of `line`.
+    let mut insertions = Vec::new();
+    let end = |message: &str| message.as_ptr() as usize - line.as_ptr() as usize + message.len() - 1;
+    let code = code
+        .then(|| field(&fields, "code").and_then(members))
+        .flatten()
+        .and_then(|code| unescape(field(&code, "code")?));
+    if let (Some(code), Some(message)) = (code, field(&fields, "message")) {
+        insertions.push((end(message), format!(" [{code}]")));
+    }
+    if suggestions {
+        for child in elements(field(&fields, "children").unwrap_or("[]"))
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(members)
+        {
+            let Some(message) = field(&child, "message") else {
+                continue;
+            };
+            for span in elements(field(&child, "spans").unwrap_or("[]"))
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(members)
+            {
+                // The replacement is left escaped, so that the line remains valid JSON.
+                let Some(replacement) = field(&span, "suggested_replacement")
+                    .and_then(|value| value.strip_prefix('"')?.strip_suffix('"'))
+                else {
+                    continue;
+                };
+                let applicability = field(&span, "suggestion_applicability")
+                    .and_then(unescape)
+                    .unwrap_or_else(|| "Unspecified".to_owned());
+                insertions.push((
+                    end(message),
+                    format!(" [SUGGESTION {replacement} ({applicability})]"),
+                ));
+            }
+        }
+    }
+    if insertions.is_empty() {
+        return None;
+    }
+
+    let mut annotated = String::with_capacity(line.len());
+    let mut start = 0;
+    for (end, insertion) in insertions {
+        annotated.push_str(&line[start..end]);
+        annotated.push_str(&insertion);
+        start = end;
+    }
+    annotated.push_str(&line[start..]);
+    Some(annotated)
+}
+
+/// Returns the contents of a JSON string, or `None` if `string` is not one.
+fn unescape(string: &str) -> Option<String> {
+    let inner = string.strip_prefix('"')?.strip_suffix('"')?;
+    let mut unescaped = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next()? {
+            'n' => unescaped.push('\n'),
+            't' => unescaped.push('\t'),
+            'r' => unescaped.push('\r'),
+            'b' => unescaped.push('\u{8}'),
+            'f' => unescaped.push('\u{c}'),
+            'u' => {
+                let code = u32::from_str_radix(&chars.by_ref().take(4).collect::<String>(), 16).ok()?;
+                // Surrogate pairs do not occur in rustc's output, which escapes only control
+                // characters.
+                unescaped.push(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER));
+            }
+            other => unescaped.push(other),
+        }
+    }
+    Some(unescaped)
+}
+
+/// Returns the value of the member named `name`.
+fn field<'a>(fields: &[(&'a str, &'a str)], name: &str) -> Option<&'a str> {
+    fields
+        .iter()
+        .find(|(key, _)| key.trim_matches('"') == name)
+        .map(|(_, value)| *value)
+}
+
+/// Splits a JSON object into its keys and values, without parsing the values.
+fn members(object: &str) -> Option<Vec<(&str, &str)>> {
+    let inner = object.strip_prefix('{')?.strip_suffix('}')?;
+    split_top_level(inner)
+        .into_iter()
+        .map(|member| {
+            let colon = member.find("\":")? + 1;
+            Some((member[..colon].trim(), member[colon + 1..].trim()))
+        })
+        .collect()
+}
+
+/// Splits a JSON array into its elements, without parsing them.
+fn elements(array: &str) -> Option<Vec<&str>> {
+    let inner = array.strip_prefix('[')?.strip_suffix(']')?;
+    Some(split_top_level(inner))
+}
+
+/// Splits `s` at the commas that are not within a string, object, or array.
+fn split_top_level(s: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let (mut depth, mut in_string, mut escaped, mut start) = (0, false, false, 0);
+    for (i, c) in s.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '{' | '[' => depth += 1,
+            '}' | ']' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(s[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if !s[start..].trim().is_empty() {
+        parts.push(s[start..].trim());
+    }
+    parts
+}
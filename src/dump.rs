@@ -0,0 +1,79 @@
+use crate::{
+    diagnostics::{expected_extension, file_revisions},
+    env::{self, is_env_truthy},
+    expanded::unpretty,
+    glob,
+    runtime::Driver,
+    test_runner::source_files,
+    ui,
+};
+use anyhow::{Context, Result};
+use std::{
+    fs::{create_dir_all, write},
+    path::Path,
+};
+
+/// Writes the requested dumps of the files beneath `src_base` to `dump_dir`, if
+/// `DYLINT_TESTING_DUMP` is set.
+///
+/// If the variable is `1` or `true`, every file is dumped. Otherwise, its value is a glob selecting
+/// the files to dump, e.g., `DYLINT_TESTING_DUMP=foo*.rs`. A dump that cannot be produced (e.g.,
+/// MIR for a file that does not type check) is reported but does not fail the test, since dumps
+/// are only a debugging aid.
+pub(crate) fn write_dumps(
+    driver: &Driver,
+    src_base: &Path,
+    dump_dir: &Path,
+    config: &ui::Config,
+) -> Result<()> {
+    let Some(value) = std::env::var(env::DYLINT_TESTING_DUMP)
+        .ok()
+        .filter(|value| !value.is_empty())
+    else {
+        return Ok(());
+    };
+    let pattern = (!is_env_truthy(env::DYLINT_TESTING_DUMP)).then_some(value);
+
+    for file in source_files(src_base, config)? {
+        let relative = file.strip_prefix(src_base).unwrap_or(&file);
+        if pattern
+            .as_ref()
+            .is_some_and(|pattern| !glob::matches(pattern, relative))
+        {
+            continue;
+        }
+        for revision in file_revisions(&file)? {
+            for kind in &config.dumps {
+                let dump = dump_dir
+                    .join(relative)
+                    .with_extension(expected_extension(revision.as_deref(), kind.mode()));
+                match unpretty(driver, &file, revision.as_deref(), config, kind.mode()) {
+                    Ok(output) => {
+                        if let Some(parent) = dump.parent() {
+                            create_dir_all(parent).with_context(|| {
+                                format!("`create_dir_all` failed for `{}`", parent.display())
+                            })?;
+                        }
+                        write(&dump, output)
+                            .with_context(|| format!("Could not write `{}`", dump.display()))?;
+                        eprintln!("Wrote `{}`", dump.display());
+                    }
+                    Err(error) => eprintln!("Could not dump `{}`: {error:?}", file.display()),
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+impl ui::DumpKind {
+    /// The argument passed to `-Zunpretty`, which is also the extension of the dump's file, e.g.,
+    /// `foo.hir-tree`
+    fn mode(self) -> &'static str {
+        match self {
+            Self::Hir => "hir-tree",
+            Self::Mir => "mir",
+        }
+    }
+}
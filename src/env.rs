@@ -1,8 +1,9 @@
 pub use dylint_internal::env::*;
 
+use crate::ui;
 use std::{
-    env::{remove_var, set_var, var_os},
     ffi::{OsStr, OsString},
+    path::{Path, PathBuf},
 };
 
 macro_rules! declare_env_var {
@@ -12,44 +13,307 @@ macro_rules! declare_env_var {
 }
 
 declare_env_var!(BLESS);
+declare_env_var!(BLESS_ANNOTATIONS);
+declare_env_var!(BLESS_FILTER);
+declare_env_var!(CARGO_BUILD_RUSTFLAGS);
+declare_env_var!(CARGO_ENCODED_RUSTFLAGS);
+declare_env_var!(CARGO_MANIFEST_DIR);
+declare_env_var!(CARGO_PKG_NAME);
+declare_env_var!(CARGO_PKG_RUST_VERSION);
+declare_env_var!(CARGO_PROFILE);
+declare_env_var!(CLIPPY_CONF_DIR);
+declare_env_var!(DYLINT_RUSTFLAGS);
+declare_env_var!(DYLINT_TESTING_ALLOW_DIRTY);
+declare_env_var!(DYLINT_TESTING_DRIVER);
+declare_env_var!(DYLINT_TESTING_DUMP);
+declare_env_var!(DYLINT_TESTING_KEEP_TMP);
+declare_env_var!(DYLINT_TESTING_RUSTC_LOG);
+declare_env_var!(LLVM_PROFILE_FILE);
+declare_env_var!(NO_COLOR);
+declare_env_var!(OUT_DIR);
+declare_env_var!(RUSTC);
+declare_env_var!(RUSTFLAGS);
+declare_env_var!(RUSTC_WORKSPACE_WRAPPER);
 declare_env_var!(RUST_BACKTRACE);
 declare_env_var!(RUST_LOG);
 
+/// Variables inherited even when the environment is cleared, because the driver and Cargo cannot
+/// run without them.
+const ESSENTIAL_ENV_VARS: &[&str] = &[
+    "CARGO_HOME",
+    "DYLD_FALLBACK_LIBRARY_PATH",
+    "DYLD_LIBRARY_PATH",
+    "HOME",
+    "LD_LIBRARY_PATH",
+    "PATH",
+    "RUSTUP_HOME",
+    "RUSTUP_TOOLCHAIN",
+    "SYSTEMROOT",
+    "TEMP",
+    "TMP",
+    "TMPDIR",
+    "USERPROFILE",
+];
+
+/// Network configuration inherited by dependency builds (but not the driver) even when the
+/// environment is cleared, so that dependencies can be fetched behind a proxy.
+const NETWORK_ENV_VARS: &[&str] = &[
+    "ALL_PROXY",
+    "CARGO_HTTP_CAINFO",
+    "CARGO_HTTP_PROXY",
+    "CARGO_HTTP_TIMEOUT",
+    "CARGO_NET_GIT_FETCH_WITH_CLI",
+    "CARGO_NET_OFFLINE",
+    "CARGO_NET_RETRY",
+    "HTTPS_PROXY",
+    "HTTP_PROXY",
+    "NO_PROXY",
+    "all_proxy",
+    "https_proxy",
+    "http_proxy",
+    "no_proxy",
+];
+
+/// Debugging aids inherited by the driver even when the environment is cleared, so that ICEs and
+/// errors are actionable under the harness.
+const DEBUG_ENV_VARS: &[&str] = &[RUST_BACKTRACE, RUST_LOG];
+
+/// Returns the variables to remove from the environment of the driver (or, if `build` is true, of a
+/// dependency build), according to `config`'s environment policy.
+///
+/// Nothing is removed unless the environment is to be cleared. Otherwise, every variable is
+/// removed except the essential ones, those passed through by the user, and the debugging aids
+/// (for the driver) or network configuration (for builds).
+pub(crate) fn cleared_vars(config: &ui::Config, build: bool) -> Vec<OsString> {
+    if !config.env_clear && !config.hermetic {
+        return Vec::new();
+    }
+    let inherited = if build {
+        NETWORK_ENV_VARS
+    } else {
+        DEBUG_ENV_VARS
+    };
+    std::env::vars_os()
+        .map(|(key, _)| key)
+        .filter(|key| {
+            !ESSENTIAL_ENV_VARS
+                .iter()
+                .chain(inherited)
+                .any(|var| key == *var)
+                && !config.env_passthrough.iter().any(|var| key == var.as_str())
+        })
+        .collect()
+}
+
+/// Returns the variables that point the driver's home, Cargo home, and temporary directories
+/// beneath `config.hermetic_dir`, if the test is hermetic.
+///
+/// `RUSTUP_HOME` is set to the real one, which would otherwise be looked for in the new home
+/// directory. The driver only reads from it.
+pub(crate) fn hermetic_vars(config: &ui::Config) -> Vec<(&'static str, PathBuf)> {
+    let Some(dir) = &config.hermetic_dir else {
+        return Vec::new();
+    };
+    let mut vars = vec![
+        ("CARGO_HOME", dir.join("cargo")),
+        ("HOME", dir.join("home")),
+        ("TEMP", dir.join("tmp")),
+        ("TMP", dir.join("tmp")),
+        ("TMPDIR", dir.join("tmp")),
+        ("USERPROFILE", dir.join("home")),
+    ];
+    let rustup_home = std::env::var_os("RUSTUP_HOME")
+        .map(PathBuf::from)
+        .or_else(|| {
+            std::env::var_os("HOME")
+                .or_else(|| std::env::var_os("USERPROFILE"))
+                .map(|home| PathBuf::from(home).join(".rustup"))
+        });
+    if let Some(rustup_home) = rustup_home {
+        vars.push(("RUSTUP_HOME", rustup_home));
+    }
+    vars
+}
+
+/// Returns the flags given by the environment: those in `CARGO_ENCODED_RUSTFLAGS` (separated by
+/// `0x1f`) or else `RUSTFLAGS` (separated by whitespace), as Cargo reads them, followed by those in
+/// `DYLINT_RUSTFLAGS` (separated by whitespace).
+pub(crate) fn rustc_flags_from_env() -> Vec<String> {
+    rustc_flags_from(|key| std::env::var(key).ok())
+}
+
+fn rustc_flags_from(var: impl Fn(&str) -> Option<String>) -> Vec<String> {
+    let mut flags = match var(CARGO_ENCODED_RUSTFLAGS).filter(|flags| !flags.is_empty()) {
+        Some(encoded) => encoded.split('\x1f').map(ToOwned::to_owned).collect(),
+        None => split_flags(var(RUSTFLAGS)),
+    };
+    flags.extend(split_flags(var(DYLINT_RUSTFLAGS)));
+    flags
+}
+
+fn split_flags(flags: Option<String>) -> Vec<String> {
+    flags
+        .unwrap_or_default()
+        .split_whitespace()
+        .map(ToOwned::to_owned)
+        .collect()
+}
+
+/// Returns the name and value of the first variable requesting blessing that is set: one of those
+/// given to [`ui::Test::bless_var`], or else `BLESS`. A value set in the config takes precedence
+/// over the environment.
+pub(crate) fn bless_var(config: &ui::Config) -> Option<(&str, String)> {
+    if let Some(value) = &config.bless_value {
+        return bless_vars(config).next().map(|var| (var, value.clone()));
+    }
+    bless_vars(config).find_map(|var| {
+        std::env::var(var)
+            .ok()
+            .filter(|value| !value.is_empty())
+            .map(|value| (var, value))
+    })
+}
+
+fn bless_vars(config: &ui::Config) -> impl Iterator<Item = &str> {
+    config
+        .bless_vars
+        .iter()
+        .map(String::as_str)
+        .chain(config.bless_vars.is_empty().then_some(BLESS))
+}
+
+/// Returns the name of the variable that requests blessing, as shown in messages.
+pub(crate) fn bless_var_name(config: &ui::Config) -> &str {
+    bless_var(config)
+        .map(|(var, _)| var)
+        .or_else(|| bless_vars(config).next())
+        .unwrap_or(BLESS)
+}
+
+/// Returns true if the bless variable is `dry-run`, in which case the expected files that blessing
+/// would change are reported, but not written.
+pub fn is_bless_dry_run(config: &ui::Config) -> bool {
+    bless_var(config).is_some_and(|(_, value)| value.eq_ignore_ascii_case("dry-run"))
+}
+
+/// Returns true if the bless variable is `new-only`, in which case missing expected files are
+/// created, but existing ones are left unchanged.
+pub fn is_bless_new_only(config: &ui::Config) -> bool {
+    bless_var(config).is_some_and(|(_, value)| value.eq_ignore_ascii_case("new-only"))
+}
+
+/// Returns true if the bless variable is `1`, `true`, `overwrite`, or `new-only`, i.e., if expected
+/// files should be written.
+pub fn is_bless_truthy(config: &ui::Config) -> bool {
+    bless_var(config).is_some_and(|(_, value)| {
+        is_truthy(&value)
+            || value.eq_ignore_ascii_case("overwrite")
+            || value.eq_ignore_ascii_case("new-only")
+    })
+}
+
+/// Returns true if expected files should be blessed, or if blessing should be previewed.
+pub fn is_blessing(config: &ui::Config) -> bool {
+    is_bless_truthy(config) || is_bless_dry_run(config)
+}
+
+/// Returns the command that failure messages suggest for blessing: the one given to
+/// [`ui::Test::bless_command`], or else one that runs just the current test target.
+pub(crate) fn bless_command(config: &ui::Config) -> String {
+    config.bless_command.clone().unwrap_or_else(|| {
+        default_bless_command(
+            bless_var_name(config),
+            std::env::var(CARGO_PKG_NAME).ok().as_deref(),
+            std::env::current_exe()
+                .ok()
+                .as_deref()
+                .and_then(Path::file_stem)
+                .and_then(OsStr::to_str),
+        )
+    })
+}
+
+/// Cargo runs a test binary with `CARGO_PKG_NAME` set to its package's name, and names the binary
+/// after its target followed by a hash, e.g., `ui-0123456789abcdef`.
+fn default_bless_command(var: &str, package: Option<&str>, executable: Option<&str>) -> String {
+    let mut command = format!("{var}=1 cargo test");
+    if let Some(package) = package {
+        command.push_str(&format!(" -p {package}"));
+    }
+    let target = executable.and_then(|stem| {
+        let (target, hash) = stem.rsplit_once('-')?;
+        (hash.len() == 16 && hash.chars().all(|c| c.is_ascii_hexdigit())).then_some(target)
+    });
+    match target {
+        // A library's unit tests are named after the crate.
+        Some(target) if package.is_some_and(|package| package.replace('-', "_") == target) => {
+            command.push_str(" --lib");
+        }
+        Some(target) => command.push_str(&format!(" --test {target}")),
+        None => {}
+    }
+    command
+}
+
 pub fn is_env_truthy(var: &str) -> bool {
-    ["true", "1"].contains(
+    is_truthy(
         &std::env::var_os(var)
-            .map(|s| s.to_string_lossy().to_lowercase())
-            .unwrap_or_default()
-            .as_str(),
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default(),
     )
 }
 
-/// Restores an env var on drop
-// smoelius: `VarGuard` was copied from:
-// https://github.com/rust-lang/rust-clippy/blob/9cc8da222b3893bc13bc13c8827e93f8ea246854/tests/compile-test.rs
-// smoelius: Clippy dropped `VarGuard` when it switched to `ui_test`:
-// https://github.com/rust-lang/rust-clippy/commit/77d10ac63dae6ef0a691d9acd63d65de9b9bf88e
-#[must_use]
-pub struct VarGuard {
-    key: &'static str,
-    value: Option<OsString>,
+fn is_truthy(value: &str) -> bool {
+    ["true", "1"].contains(&value.to_lowercase().as_str())
 }
 
-impl VarGuard {
-    pub fn set(key: &'static str, val: impl AsRef<OsStr>) -> Self {
-        let value = var_os(key);
-        unsafe {
-            set_var(key, val);
-        }
-        Self { key, value }
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rustc_flags_are_read_as_cargo_reads_them() {
+        let vars = |vars: &'static [(&str, &str)]| {
+            move |key: &str| {
+                vars.iter()
+                    .find(|(name, _)| *name == key)
+                    .map(|(_, value)| (*value).to_owned())
+            }
+        };
+        assert_eq!(
+            rustc_flags_from(vars(&[
+                (RUSTFLAGS, " -D  warnings "),
+                (DYLINT_RUSTFLAGS, "--cfg dylint")
+            ])),
+            ["-D", "warnings", "--cfg", "dylint"]
+        );
+        assert_eq!(
+            rustc_flags_from(vars(&[
+                (CARGO_ENCODED_RUSTFLAGS, "--cfg\x1ffoo bar"),
+                (RUSTFLAGS, "-D warnings")
+            ])),
+            ["--cfg", "foo bar"]
+        );
+        assert!(rustc_flags_from(vars(&[])).is_empty());
     }
-}
 
-impl Drop for VarGuard {
-    fn drop(&mut self) {
-        match self.value.as_deref() {
-            None => unsafe { remove_var(self.key) },
-            Some(value) => unsafe { set_var(self.key, value) },
-        }
+    #[test]
+    fn bless_command_selects_the_test_target() {
+        assert_eq!(
+            default_bless_command(BLESS, Some("my-lint"), Some("ui-0123456789abcdef")),
+            "BLESS=1 cargo test -p my-lint --test ui"
+        );
+        assert_eq!(
+            default_bless_command(
+                "DYLINT_BLESS",
+                Some("my-lint"),
+                Some("my_lint-0123456789abcdef")
+            ),
+            "DYLINT_BLESS=1 cargo test -p my-lint --lib"
+        );
+        assert_eq!(
+            default_bless_command(BLESS, None, Some("ui")),
+            "BLESS=1 cargo test"
+        );
     }
 }
@@ -0,0 +1,147 @@
+use crate::{
+    diagnostics::{compile_json, file_revisions, header},
+    runtime::Driver,
+    test_runner::source_files,
+    ui,
+};
+use anyhow::{Context, Result};
+use log::debug;
+use std::{
+    fs::{read_to_string, write},
+    path::Path,
+};
+
+/// The header, understood by ui_test, that sets a file's expected exit status
+const HEADER: &str = "exit-status:";
+
+/// Returns the exit status expected of a file without headers, given the program that runs it.
+///
+/// rustc normally exits 1 on error, whereas dylint-driver defaults to 101 (configurable).
+pub(crate) fn default_exit_status(driver: &Driver, config: &ui::Config) -> i32 {
+    match config.mode {
+        ui::Mode::CheckPass | ui::Mode::RunPass => 0,
+        ui::Mode::Fail { .. } | ui::Mode::BuildFail => {
+            let is_dylint_driver = driver
+                .path
+                .file_name()
+                .and_then(|s| s.to_str())
+                .is_some_and(|s| s.contains("dylint-driver"));
+            if is_dylint_driver {
+                config.expected_exit_status
+            } else {
+                1
+            }
+        }
+    }
+}
+
+/// Records the exit status of each file beneath `src_base` (and each of its revisions) in an
+/// `//@exit-status:` header, where it differs from the one the file would otherwise be expected to
+/// exit with. Headers that have become unnecessary are removed.
+pub(crate) fn bless_exit_statuses(
+    driver: &Driver,
+    src_base: &Path,
+    config: &ui::Config,
+) -> Result<()> {
+    let default = default_exit_status(driver, config);
+    for file in source_files(src_base, config)? {
+        let source = read_to_string(&file)
+            .with_context(|| format!("Could not read `{}`", file.display()))?;
+        let mut updated = source.clone();
+        for revision in file_revisions(&file)? {
+            let (Some(status), _) = compile_json(driver, &file, revision.as_deref(), config)?
+            else {
+                // The driver was killed, e.g., by a timeout, so there is no status to record.
+                continue;
+            };
+            updated = with_exit_status(&updated, revision.as_deref(), status, default);
+        }
+        if updated != source {
+            debug!("bless_exit_statuses: Updating {}", file.display());
+            write(&file, updated)
+                .with_context(|| format!("Could not write `{}`", file.display()))?;
+        }
+    }
+    Ok(())
+}
+
+/// Returns `source` with an `//@exit-status:` header for `revision` recording `status`, or without
+/// one if `status` is what the file would be expected to exit with anyway. The header is scoped to
+/// the revision, e.g., `//@[foo] exit-status: 1`, if there is one.
+fn with_exit_status(source: &str, revision: Option<&str>, status: i32, default: i32) -> String {
+    let is_own_header = |line: &str| {
+        let scoped = line.trim_start().starts_with("//@[");
+        scoped == revision.is_some()
+            && header(line, revision).is_some_and(|header| header.starts_with(HEADER))
+    };
+    // `//@check-pass` and `//@run-pass` expect success, whatever the default. A revision is
+    // expected to exit like the file, if the file has a header of its own.
+    let expected = if source.lines().any(|line| {
+        header(line, revision)
+            .is_some_and(|header| matches!(header.trim(), "check-pass" | "run-pass"))
+    }) {
+        0
+    } else {
+        revision
+            .and_then(|_| {
+                source.lines().find_map(|line| {
+                    header(line, None)?
+                        .strip_prefix(HEADER)?
+                        .trim()
+                        .parse()
+                        .ok()
+                })
+            })
+            .unwrap_or(default)
+    };
+
+    let mut lines = source
+        .lines()
+        .filter(|line| !is_own_header(line))
+        .map(ToOwned::to_owned)
+        .collect::<Vec<_>>();
+    if status != expected {
+        // The header goes after the leading headers, or else at the top of the file.
+        let index = lines
+            .iter()
+            .take_while(|line| line.trim_start().starts_with("//@"))
+            .count();
+        let scope = revision
+            .map(|revision| format!("[{revision}] "))
+            .unwrap_or_default();
+        lines.insert(index, format!("//@{scope}{HEADER} {status}"));
+    }
+
+    let mut updated = lines.join("\n");
+    if source.ends_with('\n') {
+        updated.push('\n');
+    }
+    updated
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn exit_status_headers_are_blessed() {
+        let source = "//@edition: 2021\nfn main() {}\n";
+        let blessed = with_exit_status(source, None, 1, 101);
+        assert_eq!(
+            blessed,
+            "//@edition: 2021\n//@exit-status: 1\nfn main() {}\n"
+        );
+        assert_eq!(with_exit_status(&blessed, None, 101, 101), source);
+        assert_eq!(
+            with_exit_status("//@check-pass\n", None, 0, 101),
+            "//@check-pass\n"
+        );
+
+        let source = "//@revisions: a b\n//@exit-status: 1\n";
+        assert_eq!(with_exit_status(source, Some("a"), 1, 101), source);
+        assert_eq!(
+            with_exit_status(source, Some("b"), 0, 101),
+            "//@revisions: a b\n//@exit-status: 1\n//@[b] exit-status: 0\n"
+        );
+    }
+}
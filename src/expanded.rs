@@ -0,0 +1,103 @@
+use crate::{
+    diagnostics::{driver_command, expected_extension, file_revisions},
+    env, report,
+    runtime::Driver,
+    test_runner::source_files,
+    ui,
+};
+use anyhow::{Context, Result, bail, ensure};
+use log::debug;
+use std::{
+    fs::{read_to_string, write},
+    path::Path,
+};
+
+/// Extension of the files holding a fixture's expanded source, e.g., `foo.expanded.rs`
+pub(crate) const EXTENSION: &str = "expanded.rs";
+
+/// Compares each file's macro-expanded source (as printed by `-Zunpretty=expanded`) against its
+/// `.expanded.rs` file, or writes the file when blessing.
+pub(crate) fn check_expanded_snapshots(
+    driver: &Driver,
+    src_base: &Path,
+    config: &ui::Config,
+    bless: bool,
+) -> Result<()> {
+    let mut mismatches = Vec::new();
+
+    for file in source_files(src_base, config)? {
+        for revision in file_revisions(&file)? {
+            let actual = unpretty(driver, &file, revision.as_deref(), config, "expanded")?;
+            let snapshot = file.with_extension(expected_extension(revision.as_deref(), EXTENSION));
+
+            if bless {
+                write(&snapshot, &actual)
+                    .with_context(|| format!("Could not write `{}`", snapshot.display()))?;
+                continue;
+            }
+
+            let expected = if snapshot.exists() {
+                read_to_string(&snapshot)
+                    .with_context(|| format!("Could not read `{}`", snapshot.display()))?
+            } else {
+                String::new()
+            };
+
+            if expected != actual {
+                mismatches.push(format!(
+                    "`{}` differs from the expanded source:\n{}",
+                    snapshot.display(),
+                    report::unified_diff(
+                        &snapshot.display().to_string(),
+                        "actual",
+                        &expected,
+                        &actual,
+                        config.diff_context_lines,
+                        report::color_enabled(),
+                    )
+                ));
+            }
+        }
+    }
+
+    if !mismatches.is_empty() {
+        bail!(
+            "{}\n\nRun `{}` to update the `.{EXTENSION}` files.",
+            mismatches.join("\n\n"),
+            env::bless_command(config)
+        );
+    }
+
+    Ok(())
+}
+
+/// Returns `file` as printed by the driver with `-Zunpretty=<mode>`, e.g., its source after macro
+/// expansion (`expanded`).
+pub(crate) fn unpretty(
+    driver: &Driver,
+    file: &Path,
+    revision: Option<&str>,
+    config: &ui::Config,
+    mode: &str,
+) -> Result<String> {
+    let out_dir = tempfile::tempdir().with_context(|| "`tempdir` failed")?;
+    let mut command = driver_command(driver, file, revision, config, out_dir.path())?;
+    command.arg(format!("-Zunpretty={mode}"));
+
+    debug!("unpretty: {:?}", command);
+    let output = command
+        .output()
+        .with_context(|| format!("Could not run `{}`", driver.path.display()))?;
+    ensure!(
+        output.status.success(),
+        "Could not print `{}` with `-Zunpretty={mode}`:\n{}",
+        file.display(),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).with_context(|| {
+        format!(
+            "Output of `-Zunpretty={mode}` for `{}` is not UTF-8",
+            file.display()
+        )
+    })
+}
@@ -0,0 +1,59 @@
+use regex::Regex;
+use std::path::Path;
+
+/// Returns true if `path` matches the glob `pattern`.
+///
+/// `*` matches any run of characters other than `/`, `?` matches any one such character, and `**`
+/// matches any run of characters, including `/`. A pattern without a `/` is matched against the
+/// file name only, so that, e.g., `foo*.rs` selects files in every subdirectory.
+pub(crate) fn matches(pattern: &str, path: &Path) -> bool {
+    let path = path.to_string_lossy().replace('\\', "/");
+    let subject = if pattern.contains('/') {
+        path.as_str()
+    } else {
+        path.rsplit('/').next().unwrap_or_default()
+    };
+    to_regex(pattern).is_match(subject)
+}
+
+fn to_regex(pattern: &str) -> Regex {
+    let mut regex = String::from("^");
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                // `**/` also matches no directories at all.
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    regex.push_str("(?:.*/)?");
+                } else {
+                    regex.push_str(".*");
+                }
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            _ => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+    Regex::new(&regex).unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn globs_match_paths() {
+        assert!(matches("foo.rs", Path::new("foo.rs")));
+        assert!(matches("foo*.rs", Path::new("dir/foo_bar.rs")));
+        assert!(!matches("foo*.rs", Path::new("bar.rs")));
+        assert!(matches("dir/*.rs", Path::new("dir/foo.rs")));
+        assert!(!matches("dir/*.rs", Path::new("dir/sub/foo.rs")));
+        assert!(matches("dir/**/*.rs", Path::new("dir/foo.rs")));
+        assert!(matches("dir/**/*.rs", Path::new("dir/sub/foo.rs")));
+        assert!(matches("f?o.rs", Path::new("fao.rs")));
+        assert!(!matches("f.o.rs", Path::new("fao.rs")));
+    }
+}
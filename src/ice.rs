@@ -0,0 +1,125 @@
+use crate::{
+    diagnostics::{driver_command, file_revisions},
+    env,
+    repro::command_line,
+    runtime::Driver,
+    test_runner::source_files,
+    ui,
+};
+use anyhow::{Context, Result};
+use log::debug;
+use std::path::Path;
+
+/// Text by which rustc's output identifies an internal compiler error
+const SIGNATURES: &[&str] = &[
+    "error: internal compiler error",
+    "error: the compiler unexpectedly panicked",
+    "thread 'rustc' panicked at",
+];
+
+/// Lines of the panic shown in a report
+const SUMMARY_LINES: usize = 3;
+
+/// Returns true if `stderr` shows that the compiler (or a lint) panicked.
+pub(crate) fn is_ice(stderr: &str) -> bool {
+    SIGNATURES
+        .iter()
+        .any(|signature| stderr.contains(signature))
+}
+
+/// Compiles each file beneath `src_base` (and each of its revisions) again, and returns a report of
+/// those on which the driver panicked, or `None` if it panicked on none.
+///
+/// Each file's report includes the start of the panic message and a command line that reproduces
+/// the panic outside the harness. With [`ui::Test::ice_backtrace`], the file is compiled once more
+/// with `RUST_BACKTRACE=full`, and the backtrace is included.
+pub(crate) fn report(
+    driver: &Driver,
+    src_base: &Path,
+    config: &ui::Config,
+) -> Result<Option<String>> {
+    let mut reports = Vec::new();
+
+    for file in source_files(src_base, config)? {
+        let relative = file.strip_prefix(src_base).unwrap_or(&file);
+        for revision in file_revisions(&file)? {
+            let out_dir = tempfile::tempdir().with_context(|| "`tempdir` failed")?;
+            let mut command =
+                driver_command(driver, &file, revision.as_deref(), config, out_dir.path())?;
+            debug!("ice::report: {:?}", command);
+            let output = command
+                .output()
+                .with_context(|| format!("Could not run `{}`", driver.path.display()))?;
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            if !is_ice(&stderr) {
+                continue;
+            }
+
+            let revision = revision
+                .as_deref()
+                .map(|revision| format!(" (revision `{revision}`)"))
+                .unwrap_or_default();
+            let mut report = format!(
+                "The driver panicked on `{}`{revision}:\n{}\nReproduce with:\n    {}",
+                relative.display(),
+                summary(&stderr),
+                command_line(&command)
+            );
+            if config.ice_backtrace {
+                command.env(env::RUST_BACKTRACE, "full");
+                let output = command
+                    .output()
+                    .with_context(|| format!("Could not run `{}`", driver.path.display()))?;
+                report.push_str(&format!(
+                    "\nWith `{}=full`:\n{}",
+                    env::RUST_BACKTRACE,
+                    String::from_utf8_lossy(&output.stderr)
+                ));
+            }
+            reports.push(report);
+        }
+    }
+
+    Ok((!reports.is_empty()).then(|| {
+        format!(
+            "Internal compiler error(s) in `{}`:\n\n{}",
+            src_base.display(),
+            reports.join("\n\n")
+        )
+    }))
+}
+
+/// Returns the first lines of the panic in `stderr`, indented.
+fn summary(stderr: &str) -> String {
+    let lines = stderr.lines().collect::<Vec<_>>();
+    let start = lines
+        .iter()
+        .position(|line| SIGNATURES.iter().any(|signature| line.contains(signature)))
+        .unwrap_or_default();
+    lines
+        .iter()
+        .skip(start)
+        .take(SUMMARY_LINES)
+        .map(|line| format!("    {line}"))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ices_are_summarized() {
+        let stderr = "warning: unused variable\n\nthread 'rustc' panicked at src/lib.rs:1:1:\n\
+                      oops\nnote: run with `RUST_BACKTRACE=1`\n\nerror: the compiler \
+                      unexpectedly panicked. this is a bug.\n";
+        assert!(is_ice(stderr));
+        assert!(!is_ice("error: unused variable\n"));
+        assert_eq!(
+            summary(stderr),
+            "    thread 'rustc' panicked at src/lib.rs:1:1:\n    oops\n    note: run with \
+             `RUST_BACKTRACE=1`"
+        );
+    }
+}
@@ -0,0 +1,160 @@
+use crate::{cache, runtime::Driver, test_runner::files, ui};
+use anyhow::{Context, Result, anyhow};
+use cargo_metadata::camino::Utf8Path;
+use serde_json::{Map, Value};
+use std::{
+    fs::{read, read_to_string, write},
+    hash::{DefaultHasher, Hash, Hasher},
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+/// Returns the paths, relative to `src_base`, of those of `files` whose inputs are unchanged since
+/// they last passed, according to `cache_path`.
+pub(crate) fn unchanged_files(
+    driver: &Driver,
+    src_base: &Path,
+    files: &[PathBuf],
+    config: &ui::Config,
+    cache_path: &Path,
+) -> Result<Vec<PathBuf>> {
+    let _lock = lock(cache_path)?;
+    let passed = load(cache_path);
+    let mut unchanged = Vec::new();
+    for file in files {
+        let key = file.display().to_string();
+        if passed.get(&key).and_then(Value::as_str)
+            == Some(fingerprint(driver, file, config)?.as_str())
+        {
+            unchanged.push(file.strip_prefix(src_base).unwrap_or(file).to_owned());
+        }
+    }
+    Ok(unchanged)
+}
+
+/// Records in `cache_path` that `files` passed with their current inputs.
+pub(crate) fn record(
+    driver: &Driver,
+    files: &[PathBuf],
+    config: &ui::Config,
+    cache_path: &Path,
+) -> Result<()> {
+    let _lock = lock(cache_path)?;
+    let mut passed = load(cache_path);
+    for file in files {
+        passed.insert(
+            file.display().to_string(),
+            Value::String(fingerprint(driver, file, config)?),
+        );
+    }
+    write(cache_path, Value::Object(passed).to_string())
+        .with_context(|| format!("Could not write `{}`", cache_path.display()))
+}
+
+/// Returns the files passed, keyed by path, with the fingerprints of their inputs. A missing or
+/// corrupt cache is treated as empty, so that every file is run.
+fn load(cache_path: &Path) -> Map<String, Value> {
+    read_to_string(cache_path)
+        .ok()
+        .and_then(|contents| serde_json::from_str(&contents).ok())
+        .unwrap_or_default()
+}
+
+fn lock(cache_path: &Path) -> Result<std::fs::File> {
+    let lock_path = cache_path.with_extension("lock");
+    let lock_path = Utf8Path::from_path(&lock_path)
+        .ok_or_else(|| anyhow!("Non-UTF-8 path: {}", lock_path.display()))?;
+    cache::lock(lock_path)
+}
+
+/// Hashes the inputs of `file`: its contents and those of its expected files (e.g., `foo.stderr`
+/// for `foo.rs`), the library's size and modification time, and the flags and variables the driver
+/// is given.
+fn fingerprint(driver: &Driver, file: &Path, config: &ui::Config) -> Result<String> {
+    let mut hasher = DefaultHasher::new();
+
+    let library = driver
+        .library
+        .metadata()
+        .with_context(|| format!("Could not get metadata of `{}`", driver.library.display()))?;
+    driver.library.hash(&mut hasher);
+    library.len().hash(&mut hasher);
+    library
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .unwrap_or_default()
+        .hash(&mut hasher);
+    config.rustc_flags.hash(&mut hasher);
+    config.envs.hash(&mut hasher);
+    config.dylint_toml.hash(&mut hasher);
+
+    for input in inputs(file)? {
+        input.hash(&mut hasher);
+        read(&input)
+            .with_context(|| format!("Could not read `{}`", input.display()))?
+            .hash(&mut hasher);
+    }
+
+    Ok(format!("{:016x}", hasher.finish()))
+}
+
+/// Returns `file` and the files beside it whose names start with its stem and a `.`, in order.
+fn inputs(file: &Path) -> Result<Vec<PathBuf>> {
+    let (Some(dir), Some(stem)) = (file.parent(), file.file_stem()) else {
+        return Ok(vec![file.to_owned()]);
+    };
+    let prefix = format!("{}.", stem.to_string_lossy());
+    let mut inputs = files(dir)?
+        .into_iter()
+        .filter(|path| {
+            path.parent() == Some(dir)
+                && path
+                    .file_name()
+                    .is_some_and(|name| name.to_string_lossy().starts_with(&prefix))
+        })
+        .collect::<Vec<_>>();
+    inputs.sort();
+    Ok(inputs)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn passed_files_are_unchanged_until_their_inputs_change() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let dir = tempdir.path();
+        let library = dir.join("libmy_lint.so");
+        write(&library, "").unwrap();
+        let driver = Driver {
+            path: PathBuf::from("rustc"),
+            library,
+            envs: Vec::new(),
+        };
+        let config = ui::Config::default();
+        let cache_path = dir.join("cache.json");
+        let (foo, bar) = (dir.join("foo.rs"), dir.join("bar.rs"));
+        write(&foo, "fn main() {}\n").unwrap();
+        write(&bar, "fn main() {}\n").unwrap();
+        let files = [foo.clone(), bar];
+
+        assert!(
+            unchanged_files(&driver, dir, &files, &config, &cache_path)
+                .unwrap()
+                .is_empty()
+        );
+        record(&driver, &files, &config, &cache_path).unwrap();
+        assert_eq!(
+            unchanged_files(&driver, dir, &files, &config, &cache_path).unwrap(),
+            [PathBuf::from("foo.rs"), PathBuf::from("bar.rs")]
+        );
+
+        write(foo.with_extension("stderr"), "error\n").unwrap();
+        assert_eq!(
+            unchanged_files(&driver, dir, &files, &config, &cache_path).unwrap(),
+            [PathBuf::from("bar.rs")]
+        );
+    }
+}
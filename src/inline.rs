@@ -0,0 +1,109 @@
+use crate::{
+    env,
+    runtime::Driver,
+    test_runner::{files, run_tests},
+    ui,
+};
+use anyhow::{Context, Result, ensure};
+use std::{
+    fs::{create_dir_all, read_to_string, write},
+    path::{Component, Path},
+};
+
+/// Writes the fixtures passed to [`ui::Test::inline_source`] and [`ui::Test::inline_expected`] to a
+/// temporary directory, and runs them like the files of a source directory.
+///
+/// Blessed output has no file to be written to, so when blessing, the expected files that changed
+/// are printed instead, to be copied into the test.
+pub(crate) fn run(driver: &Driver, config: &ui::Config) -> Result<()> {
+    let tempdir = tempfile::tempdir().with_context(|| "`tempdir` failed")?;
+    let src_base = tempdir.path();
+
+    for (name, contents) in config.inline_sources.iter().chain(&config.inline_expected) {
+        ensure!(
+            Path::new(name)
+                .components()
+                .all(|component| matches!(component, Component::Normal(_))),
+            "Inline fixture names must be relative paths without `..`: `{name}`"
+        );
+        let path = src_base.join(name);
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent)
+                .with_context(|| format!("`create_dir_all` failed for `{}`", parent.display()))?;
+        }
+        write(&path, contents).with_context(|| format!("Could not write `{}`", path.display()))?;
+    }
+
+    let result = run_tests(driver, src_base, config);
+    if env::is_blessing(config) {
+        for message in blessed_messages(src_base, config)? {
+            eprintln!("{message}");
+        }
+    }
+    result
+}
+
+/// Returns a message for each expected file beneath `src_base` that differs from the one passed
+/// to [`ui::Test::inline_expected`], and for each one passed that no longer exists.
+fn blessed_messages(src_base: &Path, config: &ui::Config) -> Result<Vec<String>> {
+    let mut messages = Vec::new();
+    for file in files(src_base)? {
+        let relative = file.strip_prefix(src_base)?;
+        if relative
+            .extension()
+            .is_some_and(|extension| extension == "rs")
+        {
+            continue;
+        }
+        let actual = read_to_string(&file)
+            .with_context(|| format!("Could not read `{}`", file.display()))?;
+        let expected = config
+            .inline_expected
+            .iter()
+            .find(|(name, _)| Path::new(name) == relative)
+            .map(|(_, contents)| contents);
+        if expected != Some(&actual) {
+            messages.push(format!(
+                "Pass this to `Test::inline_expected(\"{}\", ...)`:\n{actual}",
+                relative.display()
+            ));
+        }
+    }
+    for (name, _) in &config.inline_expected {
+        if !src_base.join(name).exists() {
+            messages.push(format!(
+                "`{name}` is no longer produced; remove its `Test::inline_expected` call"
+            ));
+        }
+    }
+    Ok(messages)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn changed_inline_expected_files_are_reported() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let src_base = tempdir.path();
+        write(src_base.join("foo.rs"), "fn main() {}\n").unwrap();
+        write(src_base.join("foo.stderr"), "warning: new\n").unwrap();
+        write(src_base.join("bar.stderr"), "warning: same\n").unwrap();
+        let config = ui::Config {
+            inline_expected: vec![
+                ("foo.stderr".to_owned(), "warning: old\n".to_owned()),
+                ("bar.stderr".to_owned(), "warning: same\n".to_owned()),
+                ("baz.stderr".to_owned(), "warning: gone\n".to_owned()),
+            ],
+            ..ui::Config::default()
+        };
+        assert_eq!(
+            blessed_messages(src_base, &config).unwrap(),
+            [
+                "Pass this to `Test::inline_expected(\"foo.stderr\", ...)`:\nwarning: new\n",
+                "`baz.stderr` is no longer produced; remove its `Test::inline_expected` call",
+            ]
+        );
+    }
+}
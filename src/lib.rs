@@ -1,14 +1,19 @@
 //! This crate provides convenient access to the [`compiletest_rs`] package for testing [Dylint]
 //! libraries.
 //!
-//! **Note: If your test has dependencies, you must use `ui_test_example` or `ui_test_examples`.**
+//! **Note: If your test has dependencies, either declare them with `Test::dependencies` (or
+//! `Test::dependencies_manifest`), or use `ui_test_example`, `ui_test_examples`, or `ui_test_bin`.**
 //! See the [`question_mark_in_expression`] example in this repository.
 //!
-//! This crate provides the following three functions:
+//! This crate provides the following four functions:
 //!
 //! - [`ui_test`] - test a library on all source files in a directory
 //! - [`ui_test_example`] - test a library on one example target
 //! - [`ui_test_examples`] - test a library on all example targets
+//! - [`ui_test_bin`] - test a library on one binary target
+//!
+//! [`ui_test_fix`] is like [`ui_test`], but also verifies the library's suggestions: they are applied,
+//! compared against `.fixed` files, and the fixed code is re-linted to check that no diagnostics remain.
 //!
 //! For most situations, you can add the following to your library's `lib.rs` file:
 //!
@@ -24,30 +29,164 @@
 //!
 //! # Test builder
 //!
-//! In addition to the above three functions, [`ui::Test`] is a test "builder." Currently, the main
+//! In addition to the above four functions, [`ui::Test`] is a test "builder." Currently, the main
 //! advantage of using `Test` over the above functions is that `Test` allows flags to be passed to
 //! `rustc`. For an example of its use, see [`non_thread_safe_call_in_test`] in this repository.
 //!
-//! `Test` has three constructors, which correspond to the above three functions as follows:
+//! `Test` has four constructors, which correspond to the above four functions as follows:
 //!
 //! - [`ui::Test::src_base`] <-> [`ui_test`]
 //! - [`ui::Test::example`] <-> [`ui_test_example`]
 //! - [`ui::Test::examples`] <-> [`ui_test_examples`]
+//! - [`ui::Test::bin`] <-> [`ui_test_bin`]
 //!
 //! In each case, the constructor's arguments are exactly those of the corresponding function.
 //!
+//! `Test::src_bases` is like `Test::src_base`, but takes several directories, e.g., `ui` and `ui_toml`.
+//! Each has its own expected files, but the library and driver are built only once for all of them.
+//!
+//! `Test::example_in_package` is like `Test::example`, but looks up the example in another package of
+//! the workspace, so that dependency-bearing fixtures can live outside the library's package.
+//!
+//! `Test::integration_test` tests a library on a target in the package's `tests` directory. The target
+//! is compiled with `--test`, so fixtures can use `#[cfg(test)]` code and dev-dependencies.
+//!
+//! An example, binary, or integration test target is compiled with the flags Cargo used to build it. If
+//! its package has a build script, the script's `OUT_DIR`, cfgs, and environment variables are passed
+//! along too, so that fixtures can `include!` generated code.
+//!
+//! `ui_test_workspace` (or `Test::workspace`) tests a library on a fixture Cargo workspace, for lints
+//! whose behavior depends on crate-level attributes or workspace metadata. The workspace is checked with
+//! the driver as `RUSTC_WORKSPACE_WRAPPER`, and each member's diagnostics are compared against the
+//! `<package>.stderr` file next to its `Cargo.toml`. The fixture must declare its own `[workspace]`.
+//!
 //! A `Test` instance has the following methods:
 //!
 //! - `dylint_toml` - set the `dylint.toml` file's contents (for testing [configurable libraries])
+//! - `clippy_toml` - set the `clippy.toml` file's contents (for libraries configured through `clippy_utils`)
 //! - `rustc_flags` - pass flags to the compiler when running the test
-//! - `expected_exit_status` - set the expected driver exit status (default 101 for dylint_driver)
+//! - `retain_flags` - keep additional flags from Cargo's build of an example, binary, or integration test target
+//! - `rustc_flags_from_env` - also pass the flags in `RUSTFLAGS` and `DYLINT_RUSTFLAGS` to the compiler
+//! - `expected_exit_status` - set the expected driver exit status (default 101 for dylint_driver) (overridden per file by an `//@exit-status:` header)
+//! - `bless_exit_status` - when blessing, record each file's exit status in an `//@exit-status:` header where it differs from the expected one
+//! - `ice_backtrace` - include a full backtrace in the report of a file on which the driver panicked, which otherwise shows the panic and a command line reproducing it
+//! - `repro_scripts` - on failure, write `.sh` and `.ps1` scripts that run the driver on each file as the test did to `target/dylint_testing/repro` (default `true`)
+//! - `target_dir` - build artifacts into a dedicated target directory
+//! - `require_annotations` - require `//~` annotations for every diagnostic (default `true`)
+//! - `check_stdout` - compare stdout against `.stdout` files (default `true`)
+//! - `stderr_filter`, `stdout_filter`, `path_filter` - replace regex matches or a path in the driver's output before it is compared, e.g., to scrub version numbers or machine-specific paths
+//! - `normalize_rustc_noise` - normalize rustc phrasing that varies between toolchains, e.g., error counts and codes (default `false`)
+//! - `color` - set whether the driver colors its diagnostics (default `ColorPolicy::Never`, i.e., `--color=never`)
+//! - `check_expanded` - compare each file's macro-expanded source (`-Zunpretty=expanded`) against `.expanded.rs` files
+//! - `verify_fixes` - apply suggestions, check `.fixed` files, and re-lint the fixed code
+//! - `diagnostics_format` - snapshot diagnostics as rendered text (`.stderr`) or normalized JSON (`.json`)
+//! - `assert_diagnostics` - assert on parsed diagnostics in a closure instead of comparing against expected files
+//! - `toolchain` - run the test with a specific rustup toolchain
+//! - `toolchains` / `run_matrix` - run the test with each of several toolchains, honoring per-toolchain expected files (e.g., `foo.stderr.nightly-2025-01-01`)
+//! - `editions` - run each file under each of several editions, honoring per-edition expected files (e.g., `foo.stderr.e2024`)
+//! - `env` - set an environment variable for the driver (repeatable)
+//! - `env_clear` / `env_passthrough` - stop the driver and example builds from inheriting the environment, except for essential, network, and listed variables
+//! - `hermetic` - clear the environment, and point the driver's `HOME`, `CARGO_HOME`, and temporary directory to fresh directories, for reproducible `.stderr` files
+//! - `collect` - split the test into one `libtest_mimic::Trial` per file or example, for use with `harness = false`
+//! - `timeout` - kill the driver if compiling any one file takes too long
+//! - `diagnostic_level_filter` / `require_annotations_for_level` - drop diagnostics at some levels (e.g., notes) and choose which levels need annotations
+//! - `dylint_toml_path` / `discover_dylint_toml` - read `dylint.toml` from a file, by default the package root's `dylint.toml` if it exists
+//! - `mode` - expect files to fail (default), check cleanly, fail to build, or build and run successfully
+//! - `dependencies` / `dependencies_manifest` - make third-party crates available to `src_base` fixtures
+//! - `try_run` - run the test and return a `TestSummary` of passed, failed, skipped, and blessed files, with timings, instead of panicking
+//! - `watch` - run the test, then re-run the files affected by each change to the fixtures or the library, reusing the driver
+//! - `incremental` - skip the files whose contents, expected files, and library are unchanged since they last passed
+//! - `expect_failure` - expect the files matching a glob to fail, like an `//@known-bug` header; the test fails once such a file passes
+//! - `inline_source`, `inline_expected` - add a fixture and its expected files as strings, run with the other files (`Test::inline(name)` runs only inline fixtures)
+//! - `aux_dir` - copy a data directory next to the source files and examples, so that they can `include_str!` its files
+//! - `keep_going_artifacts` - keep the temporary directory of a failed example test and print its path (or set `DYLINT_TESTING_KEEP_TMP=1`)
+//! - `shuffle` - run files in a seeded random order instead of lexicographic order, printing the seed for reproduction
+//! - `diff_context_lines` - set the context shown around changes in (optionally colored) failure diffs
+//! - `wrap_command` - modify or replace the command that runs the driver, e.g., to interpose `strace`
+//! - `coverage` - build the library with `-C instrument-coverage` and write a `.profraw` file per test and driver process
+//! - `timings` / `self_profile` - print how long each file takes, slowest first, and optionally pass `-Z self-profile` to the driver
+//! - `dump` - write HIR or MIR dumps of the files selected by `DYLINT_TESTING_DUMP` to `target/dylint_testing/dumps`, for debugging
+//! - `max_time_per_file` - fail the test if any file takes longer than a time budget
+//! - `workspace_metadata` - set the `[workspace.metadata]` table seen by the library, for lints configured through workspace metadata
+//! - `deny_warnings` / `emit` - choose whether `-D warnings` is passed and what the driver emits (default `metadata`)
+//! - `lint_level` / `cap_lints` - set a lint's level (`-A`, `-W`, `--force-warn`, `-D`, or `-F`) or cap all lints, for every file
+//! - `target` - compile fixtures, examples, and auxiliary crates for another target, e.g., `wasm32-unknown-unknown`
+//! - `no_std` - compile fixtures as `no_std` libraries with `panic=abort`, emitting only metadata
+//! - `features` / `all_features` - enable package features, so that feature-gated code is linted
+//! - `driver_path` - use a prebuilt driver rather than having Dylint find or build one (also settable with `DYLINT_TESTING_DRIVER`)
+//! - `offline` - pass `--offline` to the Cargo commands the test runs
+//! - `profile` - build the library with a Cargo profile other than `dev` (also settable with `CARGO_PROFILE`)
+//! - `skip_build` - do not build the library, only look for it in `DYLINT_LIBRARY_PATH` and the target directory
+//! - `bless_filter` - when blessing, only run and bless the files matching a glob (also settable with `BLESS_FILTER`)
+//! - `bless_command` - change the blessing command suggested in failure messages (default derived from the package and test target)
+//! - `bless_var` - read whether to bless from another environment variable than `BLESS`, e.g., `DYLINT_BLESS`
+//! - `bless_guard` - refuse to bless fixtures that have unstaged Git changes (override with `DYLINT_TESTING_ALLOW_DIRTY=1`)
+//! - `prune_orphans` - remove expected files without a source file when blessing, and fail while any remain
+//! - `dedup_diagnostics` - collapse diagnostics with the same message, level, and primary span before comparing or blessing
+//! - `check_suggestions` - let annotations check suggested replacements, e.g., `//~| HELP: SUGGESTION foo()`
+//! - `only_lint_diagnostics` - compare only the diagnostics of the library's own lints, dropping rustc's and other lints' diagnostics
+//! - `require_coverage` - fail if any of the library's lints fires in none of the source files (default `false`)
+//! - `expect_lint_count` - expect a lint to emit exactly a given number of diagnostics across the source files; the counts are written to `target/dylint_testing/lint_counts`
+//! - `require_spanless_annotations` - require `//@error-in-other-file:` annotations for diagnostics without a span (default `true`)
+//! - `examples_filter` - test only the example targets whose names satisfy a predicate
+//! - `min_rust_version` - skip fixtures if the toolchain is older than the given Rust version (a `//@min-rust-version:` header takes precedence)
+//! - `bless_output_dir` - when blessing, write changed expected files beneath a directory (e.g., `target/ui-new`) instead of next to the fixtures
+//! - `accept` - copy the files written beneath `bless_output_dir` back to the fixtures, and remove them from the output directory
+//! - `on_complete` - call a closure with the `TestSummary` (passed, failed, skipped, and blessed files, and timings) once the test has run
+//! - `msrv` - test MSRV-aware lints with the given MSRV, passed as `CARGO_PKG_RUST_VERSION` (a `//@msrv:` header overrides it per file or revision)
 //! - `run` - run the test
 //!
+//! By default, a whole directory is one `#[test]`, so one failure hides the rest. `Test::collect` instead
+//! returns one [`libtest_mimic::Trial`] per source file (or per example, for `Test::examples`), which is
+//! reported with its own name and status. Run the trials from an integration test with `harness = false`:
+//!
+//! ```rust,ignore
+//! fn main() {
+//!     let trials = dylint_uitesting::ui::Test::src_base(env!("CARGO_PKG_NAME"), "ui").collect();
+//!     let args = dylint_uitesting::libtest_mimic::Arguments::from_args();
+//!     dylint_uitesting::libtest_mimic::run(&args, trials).exit();
+//! }
+//! ```
+//!
+//! # Attribute macro
+//!
+//! With the `macros` feature enabled, `#[dylint_ui_test]` expands to a `src_base` test of the current
+//! package's library:
+//!
+//! ```rust,ignore
+//! #[dylint_uitesting::dylint_ui_test(dir = "ui", rustc_flags = ["--cfg", "foo"])]
+//! fn ui() {}
+//! ```
+//!
+//! The attribute also accepts `name`, `dylint_toml`, and `shard = true`. The latter generates one test
+//! per subdirectory of `dir`, in a module named after the function, so that each subdirectory passes
+//! or fails separately.
+//!
 //! # Blessing expected files
 //!
 //! - Default run: `cargo test` verifies annotations and diffs against `.stderr/.stdout`. It never writes fixtures.
 //! - Bless: `BLESS=1 cargo test` performs a two-pass run:
 //!   1) Verify (no writes). 2) If verification succeeds, update `.stderr/.stdout`.
+//! - Annotate: `BLESS_ANNOTATIONS=1 cargo test` first injects `//~` annotations into source files that
+//!   have none, based on the diagnostics actually emitted, and then blesses. This eases migrating
+//!   `compiletest` suites. Review the injected annotations before committing them.
+//! - Policies: `BLESS=overwrite` is the same as `BLESS=1`. `BLESS=new-only` creates the expected files
+//!   that are missing, e.g., for a new fixture, and leaves existing ones unchanged, even if they drifted
+//!   after a compiler update.
+//! - Preview: `BLESS=dry-run cargo test` blesses a copy of the fixtures and prints a diff for each
+//!   `.stderr`, `.stdout`, `.fixed`, or other file that blessing would create, update, or remove.
+//!   Nothing is written.
+//! - Partial bless: `BLESS_FILTER='foo*.rs' BLESS=1 cargo test` (or `Test::bless_filter`) runs and blesses
+//!   only the files matching the glob, leaving the others untouched.
+//! - Guard: with `Test::bless_guard(true)`, blessing fails if the fixtures have unstaged Git changes,
+//!   unless `DYLINT_TESTING_ALLOW_DIRTY=1` is set.
+//! - Orphans: blessing warns about expected files whose source file was renamed or removed. With
+//!   `Test::prune_orphans(true)`, blessing removes them, and a normal run fails while any remain.
+//! - Review: with `Test::bless_output_dir("target/ui-new")`, blessing writes the changed files beneath
+//!   that directory instead, leaving the fixtures untouched. After reviewing them, `Test::accept` copies
+//!   them into place.
+//!   Alternatively, `cargo dylint-ui review [target/ui-new]` shows each file's diff and asks whether to
+//!   accept, reject, or skip it. Install it with `cargo install dylint_uitesting --features review-cli`.
 //!
 //! Exit status: when using the Dylint driver, we accept either exit code `101` (current behavior)
 //! or `1` (future-compatible), so your tests remain stable if upstream changes. Diagnostics are
@@ -55,15 +194,106 @@
 //!
 //! This keeps diffs in `target/ui` during normal runs and only touches fixtures when you explicitly bless.
 //!
+//! Both stderr and stdout are compared against expected files, in every mode. A lint that prints
+//! machine-readable output to stdout can check it with a `.stdout` file; call `check_stdout(false)` to
+//! ignore stdout instead. For example targets, blessed files are written next to the example's source.
+//! An example is tested in a copy of its whole directory, so that its `mod` files and `include!`d
+//! assets are found at the same relative paths.
+//!
+//! Expected files can be overridden per toolchain and per edition. When a test runs with a toolchain
+//! (via `toolchain` or `run_matrix`), `foo.stderr.<toolchain>` is used in place of `foo.stderr` if it
+//! exists. Likewise, under `editions`, `foo.stderr.e2024` is used for edition 2024. A toolchain
+//! override takes precedence over an edition override. Blessing writes an override only when the
+//! output differs from the base file.
+//!
+//! Revisions declared with `//@revisions: a b` are supported as in ui_test. Each revision is compiled with
+//! `--cfg=<revision>` and has its own expected files, e.g., `foo.a.stderr`, which are blessed and, for
+//! example targets, copied back like any others. Headers and annotations can be restricted to revisions,
+//! e.g., `//@[a] compile-flags: ...` and `//~[a]^ ERROR: ...`.
+//!
+//! Files beneath a directory named `clean` (e.g., `ui/clean/ok.rs`) are clean-pass fixtures: the driver
+//! must exit successfully and emit no diagnostics. A single file can opt in with ui_test's `//@check-pass`
+//! header instead.
+//!
+//! A file can override the test's `mode` with a header: `//@check-pass` (no diagnostics, exit status 0),
+//! `//@build-fail` (checking succeeds, but codegen fails), or `//@run-pass` (the file is built and run,
+//! and its output is compared against `.run.stdout` and `.run.stderr` files).
+//!
+//! A file can depend on a small helper crate with an `//@aux-build: helper.rs` header, as in compiletest
+//! and Clippy's test suite. The helper is looked up in an `auxiliary` (or `aux`) directory next to the
+//! file, compiled with plain `rustc` rather than the driver, so that the library's lints do not fire on
+//! it, and passed to the driver with `--extern`. Files in auxiliary directories are not tested themselves.
+//! A helper with a `//@proc-macro` header (or a `#![crate_type = "proc-macro"]` attribute) is compiled as a
+//! procedural macro, so that lints can be tested on macro-generated code.
+//!
+//! Diagnostics without a span, e.g., crate-level diagnostics, cannot be annotated with `//~` on a line.
+//! Annotate them with ui_test's file-level `//@error-in-other-file: <message>` header instead, which
+//! also matches diagnostics whose span lies in another file. `BLESS_ANNOTATIONS=1` injects these headers
+//! after a file's leading headers. Call `require_spanless_annotations(false)` to allow such diagnostics
+//! without annotations; they are still compared against the `.stderr` file.
+//!
+//! A fixture that only makes sense on some platforms can say so with `compiletest`-style headers:
+//! `//@only-<name>` skips it unless the platform is `<name>`, and `//@ignore-<name>` skips it if the
+//! platform is `<name>`. A name can be an operating system (e.g., `linux`, `macos`, `windows`, or
+//! `unix`), an architecture (e.g., `x86_64` or `aarch64`), a pointer width (`32bit` or `64bit`), or
+//! an environment (`gnu`, `msvc`, or `musl`). The platform is the one given to `target`, or the host
+//! otherwise. Skipped files are reported on stderr, in `TestSummary::skipped`, and as ignored trials by
+//! `collect`, rather than passing silently.
+//!
+//! Similarly, a fixture using newer language features can be skipped on older toolchains with a
+//! `//@min-rust-version: 1.78` header, or every fixture with `min_rust_version`. This helps when
+//! testing across pinned nightlies of different ages. Such files are reported in the same way.
+//!
+//! With `diagnostics_format(DiagnosticsFormat::Json)`, annotations can also check which lint emitted a
+//! diagnostic, and at what level: each diagnostic's code (its lint's name) is appended to its message in
+//! brackets, e.g., `message [my_lint]`, before ui_test matches annotations. So `//~ ERROR my_lint` fails
+//! if the right message is emitted under the wrong lint name or level, which a rendered `.stderr`
+//! comparison can miss. The `.json` files are unaffected.
+//!
+//! With `check_suggestions(true)`, suggestions can be pinned without a full rustfix run. Each suggested
+//! replacement is appended to the message of the help (or other sub-diagnostic) making it, along with its
+//! applicability, e.g., `try [SUGGESTION foo() (MachineApplicable)]`. So `//~| HELP: SUGGESTION foo()`
+//! checks the replacement, and `//~| HELP: SUGGESTION foo() (MachineApplicable)` checks its applicability
+//! too. Only ui_test's matching sees the added text; `.stderr` and `.json` files are unaffected.
+//!
+//! A negative annotation guards against a diagnostic reappearing, e.g., a lint that used to
+//! false-positive on a line. `//~! NOT my_lint` fails the test if a diagnostic whose code is `my_lint`
+//! (or whose message contains `my_lint`) appears on its line, and `//~!^ NOT my_lint` applies to the
+//! line above, one line per `^`. A bare `//~! NOT` forbids any diagnostic on the line. ui_test is run
+//! on a copy of the fixtures in which these annotations are hidden from it.
+//!
+//! # Caching
+//!
+//! The library build and driver path are cached under `target/dylint_testing/`, keyed by library name
+//! and toolchain. The cache is invalidated when any input Cargo would rebuild the library for changes:
+//! its sources and those of its path dependencies, the manifests and lockfile, Cargo's configuration
+//! files, or `RUSTFLAGS`. A lock file ensures that concurrent test binaries wait on a single build
+//! rather than racing in the same target directory.
+//!
+//! Custom harnesses can reuse the build with `dylint_uitesting::init(name)`, which returns the
+//! [`Driver`]: the driver's path, the library's path, and the environment the driver must be invoked
+//! with. `Driver::command` returns a command with that environment applied. `init` builds the library
+//! at most once per process, so it can be called from several tests.
+//!
+//! To test a lint's helpers without fixture files, `dylint_uitesting::compile_snippet(name, code)` compiles
+//! `code` with the driver, as a library crate, and returns its exit code and parsed diagnostics. For
+//! example, `compile_snippet("my_lint", code).lint_diagnostics("my_lint").count()` counts the lint's
+//! diagnostics.
+//!
 //! [Dylint]: https://github.com/trailofbits/dylint/tree/master
+//! [`Driver`]: https://docs.rs/dylint_testing/latest/dylint_testing/struct.Driver.html
 //! [`ui_test`]: https://crates.io/crates/ui_test
+//! [`libtest_mimic::Trial`]: https://docs.rs/libtest-mimic/latest/libtest_mimic/struct.Trial.html
 //! [`non_thread_safe_call_in_test`]: https://github.com/trailofbits/dylint/tree/master/examples/general/non_thread_safe_call_in_test/src/lib.rs
 //! [`question_mark_in_expression`]: https://github.com/trailofbits/dylint/tree/master/examples/restriction/question_mark_in_expression/Cargo.toml
+//! [`ui::Test::bin`]: https://docs.rs/dylint_testing/latest/dylint_testing/ui/struct.Test.html#method.bin
 //! [`ui::Test::example`]: https://docs.rs/dylint_testing/latest/dylint_testing/ui/struct.Test.html#method.example
 //! [`ui::Test::examples`]: https://docs.rs/dylint_testing/latest/dylint_testing/ui/struct.Test.html#method.examples
 //! [`ui::Test::src_base`]: https://docs.rs/dylint_testing/latest/dylint_testing/ui/struct.Test.html#method.src_base
 //! [`ui::Test`]: https://docs.rs/dylint_testing/latest/dylint_testing/ui/struct.Test.html
+//! [`ui_test_bin`]: https://docs.rs/dylint_testing/latest/dylint_testing/fn.ui_test_bin.html
 //! [`ui_test_example`]: https://docs.rs/dylint_testing/latest/dylint_testing/fn.ui_test_example.html
+//! [`ui_test_fix`]: https://docs.rs/dylint_testing/latest/dylint_testing/fn.ui_test_fix.html
 //! [`ui_test_examples`]: https://docs.rs/dylint_testing/latest/dylint_testing/fn.ui_test_examples.html
 //! [`ui_test`]: https://docs.rs/dylint_testing/latest/dylint_testing/fn.ui_test.html
 //! [configurable libraries]: https://github.com/trailofbits/dylint/tree/master#configurable-libraries
@@ -73,11 +303,77 @@
 
 use std::path::Path;
 
+mod annotations;
+mod aux;
+mod cache;
 mod cargo_integration;
+mod dependencies;
+pub mod diagnostics;
+mod diagnostics_wrapper;
+mod dump;
 mod env;
+mod exit_status;
+mod expanded;
+mod glob;
+mod ice;
+mod incremental;
+mod inline;
+mod lint_counts;
+mod lints;
+mod metadata;
+mod negative;
+mod noise;
+mod platform;
+mod report;
+mod repro;
+#[cfg(feature = "review-cli")]
+pub mod review;
 mod runtime;
+mod rust_version;
+mod rustc_wrapper;
+mod snippet;
 mod test_runner;
+mod timeout_wrapper;
 pub mod ui;
+mod workspace;
+
+pub use libtest_mimic;
+pub use runtime::Driver;
+pub use snippet::CompiledCrate;
+
+#[cfg(feature = "macros")]
+pub use dylint_uitesting_macros::dylint_ui_test;
+
+/// Build a library and get the driver that loads it, for use in custom harnesses.
+///
+/// The library is built at most once per process, and the build is shared with the tests run by
+/// this crate's other functions, so calling `init` from several tests is cheap.
+///
+/// - `name` is the name of a Dylint library to be built.
+pub fn init(name: &str) -> anyhow::Result<&'static Driver> {
+    let metadata = dylint_internal::cargo::current_metadata()?;
+    runtime::initialize(name, &metadata.target_directory, &ui::Config::default())
+}
+
+/// Compile `code` with the driver for a library, and return the diagnostics emitted, for quick
+/// assertions without fixture files.
+///
+/// - `name` is the name of a Dylint library to be built (see [`init`]).
+/// - `code` is compiled as a fixture would be, except as a library crate, so that it needs no `main`
+///   function.
+///
+/// ```rust,ignore
+/// let compiled = dylint_uitesting::compile_snippet("my_lint", "pub fn f() -> i32 { 1 + 1 }");
+/// assert!(compiled.lint_diagnostics("my_lint").count() == 1);
+/// ```
+///
+/// # Panics
+///
+/// Panics if the library cannot be built or the driver cannot be run.
+pub fn compile_snippet(name: &str, code: &str) -> CompiledCrate {
+    let driver = init(name).unwrap_or_else(|error| panic!("{error:?}"));
+    snippet::compile(driver, code).unwrap_or_else(|error| panic!("{error:?}"))
+}
 
 /// Test a library on all source files in a directory.
 ///
@@ -90,6 +386,14 @@ pub fn ui_test(name: &str, src_base: impl AsRef<Path>) {
     ui::Test::src_base(name, src_base).run();
 }
 
+/// Test a library on all source files in a directory, and verify its suggestions.
+///
+/// In addition to what [`ui_test`] does, machine-applicable suggestions are applied and compared
+/// against `.fixed` files, and the fixed code is re-linted to check that no diagnostics remain.
+pub fn ui_test_fix(name: &str, src_base: impl AsRef<Path>) {
+    ui::Test::src_base(name, src_base).verify_fixes(true).run();
+}
+
 /// Test a library on one example target.
 ///
 /// - `name` is the name of a Dylint library to be tested.
@@ -98,6 +402,24 @@ pub fn ui_test_example(name: &str, example: &str) {
     ui::Test::example(name, example).run();
 }
 
+/// Test a library on one binary target.
+///
+/// - `name` is the name of a Dylint library to be tested.
+/// - `bin` is a binary target on which to test the library.
+pub fn ui_test_bin(name: &str, bin: &str) {
+    ui::Test::bin(name, bin).run();
+}
+
+/// Test a library on a fixture Cargo workspace.
+///
+/// - `name` is the name of a Dylint library to be tested.
+/// - `workspace` is a directory containing a Cargo workspace. The workspace is checked with the
+///   library, and each member's diagnostics are compared against the `<package>.stderr` file next
+///   to its `Cargo.toml`.
+pub fn ui_test_workspace(name: &str, workspace: impl AsRef<Path>) {
+    ui::Test::workspace(name, workspace).run();
+}
+
 /// Test a library on all example targets.
 ///
 /// - `name` is the name of a Dylint library to be tested.
@@ -0,0 +1,133 @@
+use crate::{
+    diagnostics::{compile_json, file_revisions},
+    runtime::Driver,
+    test_runner::source_files,
+    ui,
+};
+use anyhow::{Context, Result, ensure};
+use std::{
+    collections::BTreeMap,
+    fs::{create_dir_all, write},
+    path::PathBuf,
+};
+
+/// Counts, after the files beneath `src_bases` have passed, the diagnostics each lint emits, and
+/// writes the counts to the config's report. Then checks that each of the library's lints fires in
+/// at least one file, if [`ui::Test::require_coverage`] asks for it, and that the lints passed to
+/// [`ui::Test::expect_lint_count`] fire as often as expected.
+///
+/// A single file run on its own (e.g., by [`ui::Test::collect`]) is not checked, since it is not
+/// expected to cover every lint.
+pub(crate) fn check(driver: &Driver, src_bases: &[PathBuf], config: &ui::Config) -> Result<()> {
+    let Some(path) = &config.lint_counts_path else {
+        return Ok(());
+    };
+    if config.only_file.is_some() || src_bases.is_empty() {
+        return Ok(());
+    }
+
+    let counts = count(driver, src_bases, config)?;
+    if let Some(parent) = path.parent() {
+        create_dir_all(parent)
+            .with_context(|| format!("`create_dir_all` failed for `{}`", parent.display()))?;
+    }
+    write(path, serde_json::to_string_pretty(&counts)?)
+        .with_context(|| format!("Could not write `{}`", path.display()))?;
+    eprintln!("Wrote `{}`", path.display());
+
+    if config.require_coverage {
+        check_coverage(&config.library_lints, &counts)?;
+    }
+    check_counts(&config.expected_lint_counts, &counts)
+}
+
+/// Returns the number of diagnostics each lint emits across the files beneath `src_bases` (and each
+/// of their revisions), keyed by the lint's name.
+fn count(
+    driver: &Driver,
+    src_bases: &[PathBuf],
+    config: &ui::Config,
+) -> Result<BTreeMap<String, usize>> {
+    let mut counts = BTreeMap::new();
+    for src_base in src_bases {
+        for file in source_files(src_base, config)? {
+            for revision in file_revisions(&file)? {
+                let (_, diagnostics) = compile_json(driver, &file, revision.as_deref(), config)?;
+                for lint in diagnostics
+                    .iter()
+                    .filter_map(|diagnostic| diagnostic.pointer("/code/code")?.as_str())
+                {
+                    *counts.entry(lint.to_owned()).or_default() += 1;
+                }
+            }
+        }
+    }
+    Ok(counts)
+}
+
+/// Fails if any of `lints` is missing from `counts`, i.e., fires in no file.
+fn check_coverage(lints: &[String], counts: &BTreeMap<String, usize>) -> Result<()> {
+    let untested = lints
+        .iter()
+        .filter(|lint| !counts.contains_key(*lint))
+        .map(|lint| format!("    {lint}"))
+        .collect::<Vec<_>>();
+    ensure!(
+        untested.is_empty(),
+        "Lints that fire in no file:\n{}\nAdd a file that triggers each of them.",
+        untested.join("\n")
+    );
+    Ok(())
+}
+
+/// Fails if a lint in `expected` emits a different number of diagnostics in `counts`.
+fn check_counts(expected: &[(String, usize)], counts: &BTreeMap<String, usize>) -> Result<()> {
+    let mismatches = expected
+        .iter()
+        .filter_map(|(lint, expected)| {
+            let actual = counts.get(lint).copied().unwrap_or_default();
+            (actual != *expected)
+                .then(|| format!("    {lint}: expected {expected}, found {actual}"))
+        })
+        .collect::<Vec<_>>();
+    ensure!(
+        mismatches.is_empty(),
+        "Lints emitted an unexpected number of diagnostics:\n{}",
+        mismatches.join("\n")
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn lints_that_never_fire_are_reported() {
+        let lints = ["my_lint".to_owned(), "other_lint".to_owned()];
+        let mut counts = BTreeMap::from([("my_lint".to_owned(), 2), ("unused".to_owned(), 1)]);
+        let error = check_coverage(&lints, &counts).unwrap_err().to_string();
+        assert!(error.contains("    other_lint\n"), "{error}");
+        assert!(!error.contains("my_lint"), "{error}");
+
+        counts.insert("other_lint".to_owned(), 1);
+        assert!(check_coverage(&lints, &counts).is_ok());
+    }
+
+    #[test]
+    fn lint_counts_are_checked() {
+        let counts = BTreeMap::from([("my_lint".to_owned(), 12)]);
+        assert!(check_counts(&[("my_lint".to_owned(), 12)], &counts).is_ok());
+        let error = check_counts(
+            &[("my_lint".to_owned(), 6), ("other_lint".to_owned(), 1)],
+            &counts,
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(
+            error
+                .contains("    my_lint: expected 6, found 12\n    other_lint: expected 1, found 0"),
+            "{error}"
+        );
+    }
+}
@@ -0,0 +1,58 @@
+use crate::{env, runtime::Driver};
+use anyhow::{Context, Result, ensure};
+use log::debug;
+use std::process::Stdio;
+
+/// Returns the names of the lints the library under test registers, as listed by the driver when
+/// `DYLINT_LIST` is set.
+pub(crate) fn library_lints(driver: &Driver) -> Result<Vec<String>> {
+    let out_dir = tempfile::tempdir().with_context(|| "`tempdir` failed")?;
+    let mut command = driver.command();
+    command
+        .env(env::DYLINT_LIST, "1")
+        .args(["--crate-type=lib", "--emit=metadata", "--out-dir"])
+        .arg(out_dir.path())
+        .arg("-")
+        .stdin(Stdio::null());
+
+    debug!("library_lints: {:?}", command);
+    let output = command
+        .output()
+        .with_context(|| format!("Could not run `{}`", driver.path.display()))?;
+    ensure!(
+        output.status.success(),
+        "Could not list the lints of `{}`:\n{}",
+        driver.library.display(),
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let lints = parse_list(&String::from_utf8_lossy(&output.stdout));
+    ensure!(
+        !lints.is_empty(),
+        "The driver listed no lints for `{}`",
+        driver.library.display()
+    );
+    Ok(lints)
+}
+
+/// Returns the lint names in the driver's listing, each line of which has the form
+/// `    <name>    <level>    <description>`.
+fn parse_list(stdout: &str) -> Vec<String> {
+    stdout
+        .lines()
+        .filter_map(|line| line.split_whitespace().next())
+        .map(ToOwned::to_owned)
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn listed_lints_are_parsed() {
+        let stdout = "    my_lint       warn    checks for my thing\n    other_lint    deny    \
+                      checks for another thing\n\n";
+        assert_eq!(parse_list(stdout), ["my_lint", "other_lint"]);
+    }
+}
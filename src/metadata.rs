@@ -0,0 +1,102 @@
+use anyhow::{Context, Result, bail};
+use cargo_metadata::camino::Utf8Path;
+use serde_json::Value;
+use std::{
+    fmt::Write as _,
+    fs::{create_dir_all, read_to_string, write},
+    path::PathBuf,
+};
+
+/// Returns the directory of a package whose manifest has `metadata` as its
+/// `[workspace.metadata]` table, writing it into `target_directory` if necessary.
+///
+/// The driver is pointed at the package with `CARGO_MANIFEST_DIR`, so that a lint that looks up its
+/// workspace's metadata finds this table. Like the manifest written for `Test::dependencies`, the
+/// package is keyed by library name, and rewritten only when its contents change.
+pub fn package(target_directory: &Utf8Path, name: &str, metadata: &Value) -> Result<PathBuf> {
+    let dir = target_directory
+        .join("dylint_testing/workspace_metadata")
+        .join(name);
+    let src = dir.join("src");
+    create_dir_all(&src).with_context(|| format!("`create_dir_all` failed for `{src}`"))?;
+
+    let lib_rs = src.join("lib.rs");
+    if !lib_rs.exists() {
+        write(&lib_rs, "").with_context(|| format!("Could not write `{lib_rs}`"))?;
+    }
+
+    let contents = manifest_contents(metadata)?;
+    let manifest_path = dir.join("Cargo.toml");
+    if read_to_string(&manifest_path).ok().as_deref() != Some(&contents) {
+        write(&manifest_path, contents)
+            .with_context(|| format!("Could not write `{manifest_path}`"))?;
+    }
+
+    Ok(dir.into_std_path_buf())
+}
+
+fn manifest_contents(metadata: &Value) -> Result<String> {
+    let Value::Object(map) = metadata else {
+        bail!("Workspace metadata must be an object");
+    };
+    let mut contents = String::from(
+        "[package]\nname = \"dylint_testing_workspace\"\nversion = \"0.0.0\"\nedition = \"2021\"\npublish = false\n\n[workspace]\n\n[workspace.metadata]\n",
+    );
+    for (key, value) in map {
+        writeln!(contents, "{} = {}", Value::from(key.as_str()), toml(value)?).unwrap();
+    }
+    Ok(contents)
+}
+
+/// Formats `value` as an inline TOML value.
+///
+/// JSON strings are valid TOML basic strings, so strings and keys are formatted as JSON.
+fn toml(value: &Value) -> Result<String> {
+    Ok(match value {
+        Value::Null => bail!("TOML has no null value"),
+        Value::Bool(_) | Value::Number(_) | Value::String(_) => value.to_string(),
+        Value::Array(values) => format!(
+            "[{}]",
+            values
+                .iter()
+                .map(toml)
+                .collect::<Result<Vec<_>>>()?
+                .join(", ")
+        ),
+        Value::Object(map) => {
+            format!(
+                "{{ {} }}",
+                map.iter()
+                    .map(|(key, value)| Ok(format!(
+                        "{} = {}",
+                        Value::from(key.as_str()),
+                        toml(value)?
+                    )))
+                    .collect::<Result<Vec<_>>>()?
+                    .join(", ")
+            )
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn metadata_is_formatted_as_toml() {
+        let contents = manifest_contents(&json!({
+            "dylint": { "libraries": [{ "path": "lints/*" }], "max": 3, "strict": true }
+        }))
+        .unwrap();
+        assert!(
+            contents.ends_with(
+                "[workspace.metadata]\n\"dylint\" = { \"libraries\" = [{ \"path\" = \"lints/*\" }], \"max\" = 3, \"strict\" = true }\n"
+            ),
+            "{contents}"
+        );
+        assert!(manifest_contents(&json!({ "dylint": null })).is_err());
+        assert!(manifest_contents(&json!([])).is_err());
+    }
+}
@@ -0,0 +1,164 @@
+use crate::{
+    diagnostics::{compile_diagnostics, file_revisions},
+    runtime::Driver,
+    test_runner::source_files,
+    ui,
+};
+use anyhow::{Context, Result, bail, ensure};
+use cargo_metadata::diagnostic::Diagnostic;
+use std::{fs::read_to_string, path::Path};
+
+/// Starts a negative annotation, e.g., `//~! NOT my_lint`.
+const MARKER: &str = "//~!";
+
+/// What [`MARKER`] is replaced with in the copies of the files that ui_test reads, because ui_test
+/// rejects annotations it cannot parse.
+const NEUTRALIZED: &str = "// ~!";
+
+/// A line on which no diagnostic matching `pattern` may appear.
+#[derive(Debug, PartialEq, Eq)]
+struct Assertion {
+    line: usize,
+    pattern: String,
+}
+
+pub(crate) fn has_negative_annotations(source: &str) -> bool {
+    source.contains(MARKER)
+}
+
+pub(crate) fn neutralize(source: &str) -> String {
+    source.replace(MARKER, NEUTRALIZED)
+}
+
+pub(crate) fn restore(source: &str) -> String {
+    source.replace(NEUTRALIZED, MARKER)
+}
+
+/// Parses the negative annotations in `source`, which may have been neutralized.
+///
+/// `//~! NOT pattern` applies to its own line, and `//~!^ NOT pattern` to the line above it (one
+/// line per `^`). A diagnostic matches if its code (e.g., its lint's name) equals `pattern` or its
+/// message contains `pattern`. An empty pattern matches any diagnostic.
+fn assertions(source: &str) -> Result<Vec<Assertion>> {
+    let mut assertions = Vec::new();
+    for (i, line) in source.lines().enumerate() {
+        let Some((_, rest)) = line
+            .split_once(MARKER)
+            .or_else(|| line.split_once(NEUTRALIZED))
+        else {
+            continue;
+        };
+        let carets = rest.chars().take_while(|&c| c == '^').count();
+        let Some(pattern) = rest[carets..]
+            .trim_start()
+            .strip_prefix("NOT")
+            .filter(|pattern| pattern.is_empty() || pattern.starts_with(char::is_whitespace))
+        else {
+            bail!("Line {}: expected `NOT` after `{MARKER}`", i + 1);
+        };
+        ensure!(
+            carets <= i,
+            "Line {}: `{MARKER}` refers to a line before the first",
+            i + 1
+        );
+        assertions.push(Assertion {
+            line: i + 1 - carets,
+            pattern: pattern.trim().to_owned(),
+        });
+    }
+    Ok(assertions)
+}
+
+impl Assertion {
+    fn is_violated_by(&self, diagnostic: &Diagnostic, file_name: &str) -> bool {
+        let on_line = diagnostic.spans.iter().any(|span| {
+            span.is_primary
+                && span.file_name == file_name
+                && (span.line_start..=span.line_end).contains(&self.line)
+        });
+        on_line
+            && (self.pattern.is_empty()
+                || diagnostic
+                    .code
+                    .as_ref()
+                    .is_some_and(|code| code.code == self.pattern)
+                || diagnostic.message.contains(&self.pattern))
+    }
+}
+
+/// Compiles each file beneath `src_base` that has negative annotations (and each of its revisions),
+/// and fails if a diagnostic appears where one forbids it.
+pub(crate) fn check(driver: &Driver, src_base: &Path, config: &ui::Config) -> Result<()> {
+    let mut violations = Vec::new();
+
+    for file in source_files(src_base, config)? {
+        let source = read_to_string(&file)
+            .with_context(|| format!("Could not read `{}`", file.display()))?;
+        let assertions = assertions(&source).with_context(|| format!("In `{}`", file.display()))?;
+        if assertions.is_empty() {
+            continue;
+        }
+        let file_name = file
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let relative = file.strip_prefix(src_base).unwrap_or(&file);
+
+        for revision in file_revisions(&file)? {
+            let diagnostics = compile_diagnostics(driver, &file, revision.as_deref(), config)?;
+            for assertion in &assertions {
+                let annotation = format!("{MARKER} NOT {}", assertion.pattern);
+                for diagnostic in diagnostics
+                    .iter()
+                    .filter(|diagnostic| assertion.is_violated_by(diagnostic, &file_name))
+                {
+                    let revision = revision
+                        .as_deref()
+                        .map(|revision| format!(" (revision `{revision}`)"))
+                        .unwrap_or_default();
+                    violations.push(format!(
+                        "{}:{}{revision}: `{}` forbids `{}`",
+                        relative.display(),
+                        assertion.line,
+                        annotation.trim_end(),
+                        diagnostic.message
+                    ));
+                }
+            }
+        }
+    }
+
+    ensure!(
+        violations.is_empty(),
+        "Diagnostics appeared where negative annotations forbid them:\n{}",
+        violations.join("\n")
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn negative_annotations_are_parsed() {
+        let source = "fn main() {\n    let x = 1; //~! NOT unused_variables\n    //~!^^ NOT\n}\n";
+        assert_eq!(
+            assertions(source).unwrap(),
+            [
+                Assertion {
+                    line: 2,
+                    pattern: "unused_variables".to_owned()
+                },
+                Assertion {
+                    line: 1,
+                    pattern: String::new()
+                },
+            ]
+        );
+        assert_eq!(assertions(&neutralize(source)).unwrap().len(), 2);
+        assert_eq!(restore(&neutralize(source)), source);
+        assert!(assertions("//~! unused_variables\n").is_err());
+        assert!(assertions("//~!^ NOT\n").is_err());
+    }
+}
@@ -0,0 +1,65 @@
+/// Filters applied to stderr by [`crate::ui::Test::normalize_rustc_noise`], for rustc phrasing
+/// that changes between toolchains while the diagnostics stay the same. Each replacement is a fixed
+/// form, so that the older and newer phrasing normalize to the same text.
+pub(crate) const FILTERS: &[(&str, &[u8])] = &[
+    // Newer toolchains count a single error ("aborting due to 1 previous error" rather than
+    // "aborting due to previous error"). The count also changes whenever an unrelated error is
+    // added or removed.
+    (
+        r"(?m)^error: aborting due to (?:\d+ )?previous errors?(?:; \d+ warnings? emitted)?$",
+        b"error: aborting due to N previous errors",
+    ),
+    (
+        r"(?m)^warning: \d+ warnings? emitted$",
+        b"warning: N warnings emitted",
+    ),
+    // Error codes are added to, and removed from, errors over time, and the explanations listed
+    // change with them.
+    (r"(?m)^error\[E\d{4}\]:", b"error:"),
+    (r"(?m)^Some errors have detailed explanations: .*\n", b""),
+    (
+        r"(?m)^For more information about (?:this|an) error, try `rustc --explain E\d{4}`\.\n",
+        b"",
+    ),
+    // Newer toolchains name the lint group that enables a lint ("`#[warn(unused_variables)]` (part
+    // of `#[warn(unused)]`) on by default" rather than "`#[warn(unused_variables)]` on by
+    // default").
+    (
+        r" \(part of `#\[\w+\([\w:]+\)\]`\) on by default",
+        b" on by default",
+    ),
+];
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use regex::bytes::Regex;
+
+    fn normalize(stderr: &str) -> String {
+        let mut stderr = stderr.as_bytes().to_vec();
+        for (pattern, replacement) in FILTERS {
+            stderr = Regex::new(pattern)
+                .unwrap()
+                .replace_all(&stderr, *replacement)
+                .into_owned();
+        }
+        String::from_utf8(stderr).unwrap()
+    }
+
+    #[test]
+    fn phrasing_of_different_toolchains_is_normalized() {
+        let old = "error[E0425]: cannot find value `x` in this scope\n  = note: \
+                   `#[warn(unused_variables)]` on by default\n\nerror: aborting due to previous \
+                   error; 1 warning emitted\n\nFor more information about this error, try `rustc \
+                   --explain E0425`.\n";
+        let new = "error: cannot find value `x` in this scope\n  = note: \
+                   `#[warn(unused_variables)]` (part of `#[warn(unused)]`) on by default\n\nerror: \
+                   aborting due to 1 previous error; 1 warning emitted\n\n";
+        assert_eq!(normalize(old), normalize(new));
+        assert_eq!(
+            normalize(new),
+            "error: cannot find value `x` in this scope\n  = note: `#[warn(unused_variables)]` on \
+             by default\n\nerror: aborting due to N previous errors\n\n"
+        );
+    }
+}
@@ -0,0 +1,194 @@
+use crate::diagnostics::header;
+use std::{env::consts, sync::LazyLock};
+
+/// Operating systems, architectures, pointer widths, and environments that `//@only-<name>` and
+/// `//@ignore-<name>` headers can name, as in `compiletest`.
+const NAMES: &[&str] = &[
+    // Operating systems and families
+    "android",
+    "emscripten",
+    "freebsd",
+    "fuchsia",
+    "illumos",
+    "ios",
+    "linux",
+    "macos",
+    "netbsd",
+    "openbsd",
+    "solaris",
+    "unix",
+    "wasi",
+    "windows",
+    // Architectures
+    "aarch64",
+    "arm",
+    "powerpc64",
+    "riscv64",
+    "s390x",
+    "wasm32",
+    "x86",
+    "x86_64",
+    // Pointer widths
+    "32bit",
+    "64bit",
+    // Environments
+    "gnu",
+    "msvc",
+    "musl",
+];
+
+const UNIX_OSES: &[&str] = &[
+    "android", "freebsd", "illumos", "ios", "linux", "macos", "netbsd", "openbsd", "solaris",
+];
+
+/// The `only-<name>` and `ignore-<name>` headers, which ui_test must be told about.
+pub(crate) static HEADERS: LazyLock<Vec<&'static str>> = LazyLock::new(|| {
+    NAMES
+        .iter()
+        .flat_map(|name| [format!("only-{name}"), format!("ignore-{name}")])
+        .map(|header| &*Box::leak(header.into_boxed_str()))
+        .collect()
+});
+
+/// Returns the header that excludes `source` from the platform, if any: an `//@only-<name>` header
+/// naming something the platform is not, or an `//@ignore-<name>` header naming something it is.
+///
+/// The platform is `target` if given, and the host otherwise.
+pub(crate) fn skip_reason(source: &str, target: Option<&str>) -> Option<String> {
+    let platform = platform(target);
+    source.lines().find_map(|line| {
+        let header = header(line, None)?.trim();
+        let (name, skips) = if let Some(name) = header.strip_prefix("only-") {
+            (name, !platform.contains(&name))
+        } else if let Some(name) = header.strip_prefix("ignore-") {
+            (name, platform.contains(&name))
+        } else {
+            return None;
+        };
+        (NAMES.contains(&name) && skips).then(|| header.to_owned())
+    })
+}
+
+/// Returns the names in [`NAMES`] that describe `target`, or the host if `target` is `None`.
+fn platform(target: Option<&str>) -> Vec<&'static str> {
+    let Some(target) = target else {
+        let width = if cfg!(target_pointer_width = "64") {
+            "64bit"
+        } else {
+            "32bit"
+        };
+        let env = if cfg!(target_env = "gnu") {
+            "gnu"
+        } else if cfg!(target_env = "msvc") {
+            "msvc"
+        } else if cfg!(target_env = "musl") {
+            "musl"
+        } else {
+            ""
+        };
+        let mut platform = vec![consts::ARCH, width, consts::OS, consts::FAMILY, env];
+        platform.retain(|name| NAMES.contains(name));
+        return platform;
+    };
+
+    let mut components = target.split('-');
+    let arch = components.next().unwrap_or_default();
+    let rest = components.collect::<Vec<_>>();
+    let mut platform = Vec::new();
+
+    let arch = match arch {
+        "i386" | "i586" | "i686" => "x86",
+        _ if arch.starts_with("arm") || arch.starts_with("thumb") => "arm",
+        _ if arch.starts_with("riscv64") => "riscv64",
+        _ if arch.starts_with("powerpc64") => "powerpc64",
+        _ => arch,
+    };
+    platform.extend(NAMES.iter().copied().find(|&name| name == arch));
+    platform.push(
+        if matches!(
+            arch,
+            "aarch64" | "powerpc64" | "riscv64" | "s390x" | "x86_64"
+        ) {
+            "64bit"
+        } else {
+            "32bit"
+        },
+    );
+
+    // Android targets also contain `linux`, so the more specific names are checked first.
+    let has = |name: &str| rest.iter().any(|component| component.starts_with(name));
+    let os = [
+        ("android", "android"),
+        ("ios", "ios"),
+        ("darwin", "macos"),
+        ("windows", "windows"),
+        ("linux", "linux"),
+        ("freebsd", "freebsd"),
+        ("netbsd", "netbsd"),
+        ("openbsd", "openbsd"),
+        ("illumos", "illumos"),
+        ("solaris", "solaris"),
+        ("fuchsia", "fuchsia"),
+        ("wasi", "wasi"),
+        ("emscripten", "emscripten"),
+    ]
+    .into_iter()
+    .find(|(component, _)| has(component))
+    .map(|(_, os)| os);
+    if let Some(os) = os {
+        platform.push(os);
+        if UNIX_OSES.contains(&os) {
+            platform.push("unix");
+        }
+    }
+
+    if let Some(env) = rest.last() {
+        platform.extend(
+            ["gnu", "msvc", "musl"]
+                .into_iter()
+                .find(|name| env.starts_with(name)),
+        );
+    }
+    platform.retain(|name| NAMES.contains(name));
+    platform
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn targets_are_described() {
+        assert_eq!(
+            platform(Some("x86_64-unknown-linux-gnu")),
+            ["x86_64", "64bit", "linux", "unix", "gnu"]
+        );
+        assert_eq!(
+            platform(Some("aarch64-linux-android")),
+            ["aarch64", "64bit", "android", "unix"]
+        );
+        assert_eq!(
+            platform(Some("i686-pc-windows-msvc")),
+            ["x86", "32bit", "windows", "msvc"]
+        );
+        assert_eq!(platform(Some("thumbv7em-none-eabihf")), ["arm", "32bit"]);
+    }
+
+    #[test]
+    fn only_and_ignore_headers_skip_files() {
+        let target = Some("x86_64-pc-windows-msvc");
+        assert_eq!(
+            skip_reason("//@only-linux\nfn main() {}\n", target),
+            Some("only-linux".to_owned())
+        );
+        assert_eq!(
+            skip_reason("//@ignore-windows\n", target),
+            Some("ignore-windows".to_owned())
+        );
+        assert_eq!(
+            skip_reason("//@only-x86_64\n//@ignore-32bit\n", target),
+            None
+        );
+        assert_eq!(skip_reason("//@only-target: x86_64\n", target), None);
+    }
+}
@@ -0,0 +1,227 @@
+use crate::env;
+use std::io::IsTerminal;
+
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const CYAN: &str = "\x1b[36m";
+const RESET: &str = "\x1b[0m";
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// Returns true if failure output should be colored.
+///
+/// `NO_COLOR` disables color. Otherwise, `CARGO_TERM_COLOR=always` and `CARGO_TERM_COLOR=never`
+/// are honored, and color is used if stderr is a terminal.
+pub(crate) fn color_enabled() -> bool {
+    if std::env::var_os(env::NO_COLOR).is_some_and(|value| !value.is_empty()) {
+        return false;
+    }
+    match std::env::var(env::CARGO_TERM_COLOR).as_deref() {
+        Ok("always") => true,
+        Ok("never") => false,
+        _ => std::io::stderr().is_terminal(),
+    }
+}
+
+/// Returns a unified diff from `expected` to `actual`, showing `context` unchanged lines around
+/// each change, or an empty string if they are equal.
+pub(crate) fn unified_diff(
+    expected_name: &str,
+    actual_name: &str,
+    expected: &str,
+    actual: &str,
+    context: usize,
+    color: bool,
+) -> String {
+    let expected = expected.lines().collect::<Vec<_>>();
+    let actual = actual.lines().collect::<Vec<_>>();
+    let ops = diff(&expected, &actual);
+    if ops.iter().all(|&op| op == Op::Equal) {
+        return String::new();
+    }
+
+    let paint = |color_code: &str, line: String| {
+        if color {
+            format!("{color_code}{line}{RESET}\n")
+        } else {
+            format!("{line}\n")
+        }
+    };
+
+    let mut output = paint(RED, format!("--- {expected_name}"));
+    output.push_str(&paint(GREEN, format!("+++ {actual_name}")));
+
+    // Each op's position in `expected` and `actual`, before the op is applied
+    let mut positions = Vec::with_capacity(ops.len());
+    let (mut old, mut new) = (0, 0);
+    for &op in &ops {
+        positions.push((old, new));
+        match op {
+            Op::Equal => {
+                old += 1;
+                new += 1;
+            }
+            Op::Delete => old += 1,
+            Op::Insert => new += 1,
+        }
+    }
+
+    let changes = ops
+        .iter()
+        .enumerate()
+        .filter(|&(_, &op)| op != Op::Equal)
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+    let mut i = 0;
+    while i < changes.len() {
+        // Extend the hunk while the next change is close enough for the contexts to overlap.
+        let mut j = i;
+        while j + 1 < changes.len() && changes[j + 1] - changes[j] <= 2 * context + 1 {
+            j += 1;
+        }
+        let start = changes[i].saturating_sub(context);
+        let end = (changes[j] + context + 1).min(ops.len());
+        let (old_start, new_start) = positions[start];
+        let old_len = ops[start..end]
+            .iter()
+            .filter(|&&op| op != Op::Insert)
+            .count();
+        let new_len = ops[start..end]
+            .iter()
+            .filter(|&&op| op != Op::Delete)
+            .count();
+        output.push_str(&paint(
+            CYAN,
+            format!(
+                "@@ -{},{old_len} +{},{new_len} @@",
+                old_start + 1,
+                new_start + 1
+            ),
+        ));
+        for (&op, &(old, new)) in ops[start..end].iter().zip(&positions[start..end]) {
+            match op {
+                Op::Equal => output.push_str(&format!(" {}\n", expected[old])),
+                Op::Delete => output.push_str(&paint(RED, format!("-{}", expected[old]))),
+                Op::Insert => output.push_str(&paint(GREEN, format!("+{}", actual[new]))),
+            }
+        }
+        i = j + 1;
+    }
+    output
+}
+
+/// Computes a line diff from the longest common subsequence of `expected` and `actual`.
+fn diff(expected: &[&str], actual: &[&str]) -> Vec<Op> {
+    let (n, m) = (expected.len(), actual.len());
+    // lcs[i][j] is the length of the longest common subsequence of `expected[i..]` and
+    // `actual[j..]`.
+    let mut lcs = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if expected[i] == actual[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut ops = Vec::with_capacity(n + m);
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && expected[i] == actual[j] {
+            ops.push(Op::Equal);
+            i += 1;
+            j += 1;
+        } else if i < n && (j == m || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            ops.push(Op::Delete);
+            i += 1;
+        } else {
+            ops.push(Op::Insert);
+            j += 1;
+        }
+    }
+    ops
+}
+
+/// Describes the change blessing would make to the expected file at `path`, for `BLESS=dry-run`,
+/// or returns an empty string if there is none.
+pub(crate) fn bless_preview(
+    path: &str,
+    old: Option<&str>,
+    new: Option<&str>,
+    context: usize,
+    color: bool,
+) -> String {
+    let action = match (old, new) {
+        (None, Some(_)) => "create",
+        (Some(_), None) => "remove",
+        (Some(old), Some(new)) if old != new => "update",
+        _ => return String::new(),
+    };
+    format!(
+        "Would {action} `{path}`:\n{}",
+        unified_diff(
+            path,
+            "blessed",
+            old.unwrap_or_default(),
+            new.unwrap_or_default(),
+            context,
+            color
+        )
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn unified_diff_shows_context() {
+        let expected = "a\nb\nc\nd\ne\nf\ng\n";
+        let actual = "a\nb\nc\nD\ne\nf\ng\n";
+        assert_eq!(
+            unified_diff("expected", "actual", expected, actual, 1, false),
+            "--- expected\n+++ actual\n@@ -3,3 +3,3 @@\n c\n-d\n+D\n e\n"
+        );
+        assert_eq!(
+            unified_diff("expected", "actual", expected, expected, 1, false),
+            ""
+        );
+    }
+
+    #[test]
+    fn distant_changes_are_separate_hunks() {
+        let expected = "1\n2\n3\n4\n5\n6\n7\n8\n";
+        let actual = "one\n2\n3\n4\n5\n6\n7\neight\n";
+        let diff = unified_diff("expected", "actual", expected, actual, 1, false);
+        assert_eq!(diff.matches("@@ -").count(), 2, "{diff}");
+        assert!(diff.contains("@@ -1,2 +1,2 @@\n-1\n+one\n 2\n"), "{diff}");
+        assert!(diff.contains("@@ -7,2 +7,2 @@\n 7\n-8\n+eight\n"), "{diff}");
+    }
+
+    #[test]
+    fn bless_preview_names_the_action() {
+        assert_eq!(
+            bless_preview("a.stderr", Some("x\n"), Some("x\n"), 3, false),
+            ""
+        );
+        assert!(
+            bless_preview("a.stderr", None, Some("x\n"), 3, false)
+                .starts_with("Would create `a.stderr`:\n")
+        );
+        assert!(
+            bless_preview("a.stderr", Some("x\n"), None, 3, false)
+                .starts_with("Would remove `a.stderr`:\n")
+        );
+        assert_eq!(
+            bless_preview("a.stderr", Some("x\n"), Some("y\n"), 0, false),
+            "Would update `a.stderr`:\n--- a.stderr\n+++ blessed\n@@ -1,1 +1,1 @@\n-x\n+y\n"
+        );
+    }
+}
@@ -0,0 +1,178 @@
+use crate::{
+    diagnostics::{driver_command, file_revisions},
+    runtime::Driver,
+    test_runner::source_files,
+    ui,
+};
+use anyhow::{Context, Result};
+use std::{
+    fs::{create_dir_all, write},
+    path::Path,
+    process::Command,
+};
+
+/// Writes a `.sh` and a `.ps1` script beneath `repro_dir` for each file beneath `src_base`, which
+/// run the driver on the file (and each of its revisions) as the harness did.
+///
+/// This is done when a run fails. The run is not broken down by file, so every file in it gets
+/// scripts; when the files are run one at a time (e.g., by [`ui::Test::collect`]), that is just the
+/// failing one. Since the harness's output directory is temporary, the scripts pass `--out-dir` the
+/// `out` directory beneath `repro_dir`.
+pub(crate) fn write_scripts(
+    driver: &Driver,
+    src_base: &Path,
+    repro_dir: &Path,
+    config: &ui::Config,
+) -> Result<()> {
+    let out_dir = repro_dir.join("out");
+
+    for file in source_files(src_base, config)? {
+        let relative = file.strip_prefix(src_base).unwrap_or(&file);
+        let mut commands = Vec::new();
+        for revision in file_revisions(&file)? {
+            commands.push(driver_command(
+                driver,
+                &file,
+                revision.as_deref(),
+                config,
+                &out_dir,
+            )?);
+        }
+        for (extension, contents) in [
+            ("sh", sh_script(relative, &commands)),
+            ("ps1", ps1_script(relative, &commands)),
+        ] {
+            let script = repro_dir.join(relative).with_extension(extension);
+            if let Some(parent) = script.parent() {
+                create_dir_all(parent).with_context(|| {
+                    format!("`create_dir_all` failed for `{}`", parent.display())
+                })?;
+            }
+            write(&script, contents)
+                .with_context(|| format!("Could not write `{}`", script.display()))?;
+        }
+    }
+    create_dir_all(&out_dir)
+        .with_context(|| format!("`create_dir_all` failed for `{}`", out_dir.display()))?;
+
+    Ok(())
+}
+
+fn sh_script(relative: &Path, commands: &[Command]) -> String {
+    let mut script = format!(
+        "#!/bin/sh\n# Runs the driver on `{}` as the harness did.\n",
+        relative.display()
+    );
+    for command in commands {
+        script.push_str(&format!("({})\n", command_line(command)));
+    }
+    script
+}
+
+fn ps1_script(relative: &Path, commands: &[Command]) -> String {
+    let mut script = format!(
+        "# Runs the driver on `{}` as the harness did.\n",
+        relative.display()
+    );
+    for command in commands {
+        if let Some(dir) = command.get_current_dir() {
+            script.push_str(&format!(
+                "Push-Location {}\n",
+                ps1_quote(&dir.to_string_lossy())
+            ));
+        }
+        for (key, value) in command.get_envs() {
+            if let Some(value) = value {
+                script.push_str(&format!(
+                    "$env:{} = {}\n",
+                    key.to_string_lossy(),
+                    ps1_quote(&value.to_string_lossy())
+                ));
+            }
+        }
+        script.push_str(&format!(
+            "& {}",
+            ps1_quote(&command.get_program().to_string_lossy())
+        ));
+        for arg in command.get_args() {
+            script.push_str(&format!(" {}", ps1_quote(&arg.to_string_lossy())));
+        }
+        script.push('\n');
+        if command.get_current_dir().is_some() {
+            script.push_str("Pop-Location\n");
+        }
+    }
+    script
+}
+
+/// Returns a shell command line that runs `command` in its directory with the variables it sets.
+/// Variables it removes are not shown, so that the line stays short.
+pub(crate) fn command_line(command: &Command) -> String {
+    let mut words = Vec::new();
+    if let Some(dir) = command.get_current_dir() {
+        words.push(format!("cd {} &&", quote(&dir.to_string_lossy())));
+    }
+    for (key, value) in command.get_envs() {
+        if let Some(value) = value {
+            words.push(format!(
+                "{}={}",
+                key.to_string_lossy(),
+                quote(&value.to_string_lossy())
+            ));
+        }
+    }
+    words.push(quote(&command.get_program().to_string_lossy()));
+    words.extend(command.get_args().map(|arg| quote(&arg.to_string_lossy())));
+    words.join(" ")
+}
+
+/// Quotes `word` for a POSIX shell, if necessary.
+fn quote(word: &str) -> String {
+    if !word.is_empty()
+        && word
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "%+,-./:=@_".contains(c))
+    {
+        return word.to_owned();
+    }
+    format!("'{}'", word.replace('\'', r"'\''"))
+}
+
+/// Quotes `word` for PowerShell.
+fn ps1_quote(word: &str) -> String {
+    format!("'{}'", word.replace('\'', "''"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn scripts_run_the_driver_as_the_harness_did() {
+        let mut command = Command::new("dylint-driver");
+        command
+            .current_dir("/tmp/ui dir")
+            .env("DYLINT_TOML", "[lint]\nkey = 'value'")
+            .args(["--crate-type=lib", "main.rs"]);
+        assert_eq!(
+            command_line(&command),
+            r"cd '/tmp/ui dir' && DYLINT_TOML='[lint]
+key = '\''value'\''' dylint-driver --crate-type=lib main.rs"
+        );
+
+        let commands = [command];
+        assert_eq!(
+            sh_script(Path::new("main.rs"), &commands),
+            format!(
+                "#!/bin/sh\n# Runs the driver on `main.rs` as the harness did.\n({})\n",
+                command_line(&commands[0])
+            )
+        );
+        assert_eq!(
+            ps1_script(Path::new("main.rs"), &commands),
+            "# Runs the driver on `main.rs` as the harness did.\nPush-Location '/tmp/ui dir'\n\
+             $env:DYLINT_TOML = '[lint]\nkey = ''value'''\n& 'dylint-driver' '--crate-type=lib' \
+             'main.rs'\nPop-Location\n"
+        );
+    }
+}
@@ -0,0 +1,226 @@
+//! Interactive review of blessed files written beneath a [`Test::bless_output_dir`].
+//!
+//! Built with the `review-cli` feature, the `cargo-dylint-ui` binary runs [`main`] as
+//! `cargo dylint-ui review [DIR]`.
+//!
+//! [`Test::bless_output_dir`]: crate::ui::Test::bless_output_dir
+
+use crate::{report, test_runner::files};
+use anyhow::{Context, Result, bail};
+use std::{
+    fs::{copy, create_dir_all, read_to_string, remove_file},
+    io::{BufRead, Write},
+    path::{Path, PathBuf},
+};
+
+/// The directory reviewed if none is given.
+pub const DEFAULT_DIR: &str = "target/ui-new";
+
+const USAGE: &str = "Usage: cargo dylint-ui review [DIR]
+
+Walks the blessed files beneath DIR (default `target/ui-new`), shows how each would change the file
+at the same path relative to the current directory, and asks whether to accept it.";
+
+/// How the files were dispositioned in a review.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Outcome {
+    /// Files copied into place
+    pub accepted: Vec<PathBuf>,
+    /// Files removed from the output directory without being copied
+    pub rejected: Vec<PathBuf>,
+    /// Files left in the output directory, to be reviewed later
+    pub skipped: Vec<PathBuf>,
+}
+
+/// Parses the command line (as passed to `cargo-dylint-ui`) and reviews the files interactively.
+pub fn main(args: impl IntoIterator<Item = String>) -> Result<()> {
+    let mut args = args.into_iter().skip(1).peekable();
+    // Cargo passes the subcommand's name as the first argument.
+    if args.peek().map(String::as_str) == Some("dylint-ui") {
+        args.next();
+    }
+    let args = args.collect::<Vec<_>>();
+    let dir = match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        ["review"] => DEFAULT_DIR,
+        ["review", dir] if !dir.starts_with('-') => dir,
+        ["-h" | "--help"] | ["review", "-h" | "--help"] => {
+            println!("{USAGE}");
+            return Ok(());
+        }
+        _ => bail!("{USAGE}"),
+    };
+
+    let current_dir = std::env::current_dir().with_context(|| "Could not get current directory")?;
+    let outcome = review(
+        Path::new(dir),
+        &current_dir,
+        &mut std::io::stdin().lock(),
+        &mut std::io::stderr(),
+        report::color_enabled(),
+    )?;
+    eprintln!(
+        "Accepted {}, rejected {}, skipped {}",
+        outcome.accepted.len(),
+        outcome.rejected.len(),
+        outcome.skipped.len()
+    );
+    Ok(())
+}
+
+/// Shows each file beneath `output_dir` as a diff against the file at the same relative path beneath
+/// `root`, and reads from `input` whether to accept, reject, or skip it, or to quit. Files not
+/// reached before quitting are skipped.
+pub fn review(
+    output_dir: &Path,
+    root: &Path,
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+    color: bool,
+) -> Result<Outcome> {
+    let mut outcome = Outcome::default();
+    if !output_dir.exists() {
+        writeln!(output, "Nothing to review in `{}`", output_dir.display())?;
+        return Ok(outcome);
+    }
+
+    let pending = files(output_dir)?
+        .into_iter()
+        .map(|file| {
+            let relative = file.strip_prefix(output_dir)?.to_owned();
+            Ok((file, relative))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    for (i, (file, relative)) in pending.iter().enumerate() {
+        let dest = root.join(relative);
+        let blessed =
+            read_to_string(file).with_context(|| format!("Could not read `{}`", file.display()))?;
+        let current = dest.exists().then(|| read_to_string(&dest)).transpose()?;
+
+        writeln!(
+            output,
+            "[{}/{}] `{}`",
+            i + 1,
+            pending.len(),
+            relative.display()
+        )?;
+        let diff = report::unified_diff(
+            &relative.display().to_string(),
+            "blessed",
+            current.as_deref().unwrap_or_default(),
+            &blessed,
+            3,
+            color,
+        );
+        if diff.is_empty() {
+            writeln!(output, "(unchanged)")?;
+        } else {
+            write!(output, "{diff}")?;
+        }
+
+        match prompt(input, output)? {
+            Answer::Accept => {
+                if let Some(parent) = dest.parent() {
+                    create_dir_all(parent).with_context(|| {
+                        format!("`create_dir_all` failed for `{}`", parent.display())
+                    })?;
+                }
+                copy(file, &dest).with_context(|| {
+                    format!(
+                        "Could not copy `{}` to `{}`",
+                        file.display(),
+                        dest.display()
+                    )
+                })?;
+                remove_file(file)
+                    .with_context(|| format!("Could not remove `{}`", file.display()))?;
+                outcome.accepted.push(relative.clone());
+            }
+            Answer::Reject => {
+                remove_file(file)
+                    .with_context(|| format!("Could not remove `{}`", file.display()))?;
+                outcome.rejected.push(relative.clone());
+            }
+            Answer::Skip => outcome.skipped.push(relative.clone()),
+            Answer::Quit => {
+                outcome
+                    .skipped
+                    .extend(pending[i..].iter().map(|(_, relative)| relative.clone()));
+                break;
+            }
+        }
+    }
+    Ok(outcome)
+}
+
+enum Answer {
+    Accept,
+    Reject,
+    Skip,
+    Quit,
+}
+
+/// Asks until `input` gives a valid answer. The end of `input` means quit.
+fn prompt(input: &mut impl BufRead, output: &mut impl Write) -> Result<Answer> {
+    loop {
+        write!(output, "Accept? [y]es, [n]o (reject), [s]kip, [q]uit: ")?;
+        output.flush()?;
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            writeln!(output)?;
+            return Ok(Answer::Quit);
+        }
+        match line.trim().to_lowercase().as_str() {
+            "y" | "yes" => return Ok(Answer::Accept),
+            "n" | "no" => return Ok(Answer::Reject),
+            "s" | "skip" => return Ok(Answer::Skip),
+            "q" | "quit" => return Ok(Answer::Quit),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs::write;
+
+    #[test]
+    fn answers_disposition_files() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let root = tempdir.path();
+        let output_dir = root.join("target/ui-new");
+        for name in ["a", "b", "c", "d"] {
+            create_dir_all(output_dir.join("ui")).unwrap();
+            write(output_dir.join(format!("ui/{name}.stderr")), "new\n").unwrap();
+        }
+        create_dir_all(root.join("ui")).unwrap();
+        write(root.join("ui/a.stderr"), "old\n").unwrap();
+
+        let mut input = "y\nwhat\nn\ns\nq\n".as_bytes();
+        let mut output = Vec::new();
+        let outcome = review(&output_dir, root, &mut input, &mut output, false).unwrap();
+
+        assert_eq!(
+            outcome,
+            Outcome {
+                accepted: vec![PathBuf::from("ui/a.stderr")],
+                rejected: vec![PathBuf::from("ui/b.stderr")],
+                skipped: vec![PathBuf::from("ui/c.stderr"), PathBuf::from("ui/d.stderr")],
+            }
+        );
+        assert_eq!(read_to_string(root.join("ui/a.stderr")).unwrap(), "new\n");
+        assert!(!root.join("ui/b.stderr").exists());
+        assert_eq!(
+            files(&output_dir).unwrap(),
+            [
+                output_dir.join("ui/c.stderr"),
+                output_dir.join("ui/d.stderr")
+            ]
+        );
+        assert!(
+            String::from_utf8(output)
+                .unwrap()
+                .contains("[1/4] `ui/a.stderr`\n--- ui/a.stderr\n+++ blessed\n")
+        );
+    }
+}
@@ -1,75 +1,353 @@
-use anyhow::Result;
+use anyhow::{Context, Result, anyhow, ensure};
+use cargo_metadata::camino::{Utf8Path, Utf8PathBuf};
 use dylint_internal::{CommandExt, library_filename};
 use log::debug;
 use std::{
-    env::set_var,
+    collections::BTreeMap,
+    env::current_dir,
     path::{Path, PathBuf},
-    sync::OnceLock,
+    process::Command,
+    sync::Mutex,
 };
 
-use crate::env;
+use crate::{
+    cache::{Cache, Entry},
+    env, rustc_wrapper, ui,
+};
+
+/// The dylint driver and the environment it must be invoked with.
+///
+/// The environment is never applied to the current process. It is attached to each driver
+/// invocation instead, so that concurrent tests cannot interfere with one another.
+#[derive(Debug)]
+pub struct Driver {
+    /// Path of the driver executable
+    pub path: PathBuf,
+    /// Path of the built library
+    pub library: PathBuf,
+    /// Environment variables the driver must be invoked with, including `DYLINT_LIBS`
+    pub envs: Vec<(&'static str, String)>,
+}
+
+impl Driver {
+    /// Returns a command that invokes the driver with its environment. The driver takes the same
+    /// arguments as `rustc`.
+    #[must_use]
+    pub fn command(&self) -> Command {
+        let mut command = Command::new(&self.path);
+        command.envs(self.envs.iter().map(|(key, val)| (*key, val)));
+        command
+    }
+}
+
+/// Initialized drivers, keyed by library name, target directory, toolchain, profile, prebuilt
+/// driver, and whether the library was built.
+//
+// The mutex is held while initializing, so that a library is built at most once per
+// process, target directory, and toolchain.
+#[allow(clippy::type_complexity)]
+static DRIVERS: Mutex<
+    BTreeMap<(String, Utf8PathBuf, String, String, Option<PathBuf>, bool), &'static Driver>,
+> = Mutex::new(BTreeMap::new());
+
+/// Forgets the drivers initialized for the library `name`, so that the next call to [`initialize`]
+/// rebuilds the library if its sources changed. The forgotten drivers are leaked.
+pub fn forget(name: &str) {
+    DRIVERS
+        .lock()
+        .unwrap()
+        .retain(|(library, ..), _| library != name);
+}
+
+/// Builds the library and gets the driver.
+///
+/// If `config.toolchain` is `None`, the toolchain in `RUSTUP_TOOLCHAIN` is used. If
+/// `config.coverage` is true, the library is built with `-C instrument-coverage`. Since the flag
+/// changes the build, callers should use a separate target directory for it.
+///
+/// The driver is the one given by `config.driver_path` or `DYLINT_TESTING_DRIVER`, if either is
+/// set, and is otherwise obtained (and, if necessary, built) by Dylint. If `config.skip_build` is
+/// true, the library is not built, only looked for.
+pub fn initialize(
+    name: &str,
+    target_directory: &Utf8Path,
+    config: &ui::Config,
+) -> Result<&'static Driver> {
+    let toolchain = config.toolchain.as_deref();
+    debug!(
+        "initialize: initialize() called with name: '{}', target_directory: {}, toolchain: {:?}",
+        name, target_directory, toolchain
+    );
+
+    let driver_override = prebuilt_driver(config)?;
+    let profile = profile(config.profile.as_deref());
 
-pub static DRIVER: OnceLock<PathBuf> = OnceLock::new();
+    let rustup_toolchain = match toolchain {
+        Some(toolchain) => toolchain.to_owned(),
+        None => env::var(env::RUSTUP_TOOLCHAIN)?,
+    };
 
-pub fn initialize(name: &str) -> Result<&Path> {
-    debug!("initialize: initialize() called with name: '{}'", name);
+    let mut drivers = DRIVERS.lock().unwrap();
+    let key = (
+        name.to_owned(),
+        target_directory.to_owned(),
+        rustup_toolchain.clone(),
+        profile.clone(),
+        driver_override.clone(),
+        config.skip_build,
+    );
 
-    if let Some(path) = DRIVER.get() {
+    if let Some(driver) = drivers.get(&key) {
         debug!(
             "initialize: Driver already initialized, returning: {}",
-            path.display()
+            driver.path.display()
         );
-        return Ok(path.as_path());
+        return Ok(driver);
     }
 
     debug!("initialize: First time initialization, building library and driver...");
     let _ = env_logger::try_init();
 
+    let Entry {
+        driver: mut path,
+        library,
+    } = if config.skip_build {
+        debug!(
+            "initialize: Skipping build, looking for library '{}'...",
+            name
+        );
+        let library = prebuilt_library(name, target_directory, &rustup_toolchain, &profile)?;
+        let driver = driver(config, &rustup_toolchain, driver_override.as_deref())?;
+        Entry { driver, library }
+    } else {
+        initialize_with_cache(
+            name,
+            target_directory,
+            config,
+            &rustup_toolchain,
+            &profile,
+            driver_override.as_deref(),
+        )?
+    };
+
+    // A cached driver is replaced by the prebuilt one, if any.
+    if let Some(driver) = driver_override {
+        path = driver;
+    }
+
+    debug!("initialize: Getting dylint_libs...");
+    let dylint_libs = serde_json::to_string(&[&library])?;
+    debug!("initialize: dylint_libs result: {}", dylint_libs);
+
+    let envs = vec![
+        (env::CLIPPY_DISABLE_DOCS_LINKS, "true".to_owned()),
+        (env::DYLINT_LIBS, dylint_libs),
+    ];
+
+    // Store driver for future calls
+    let driver = Box::leak(Box::new(Driver {
+        path,
+        library,
+        envs,
+    }));
+    drivers.insert(key, driver);
+    debug!("initialize: Driver stored in static, initialization complete");
+    Ok(driver)
+}
+
+/// Builds the library and gets the driver, unless a previous build's are cached.
+fn initialize_with_cache(
+    name: &str,
+    target_directory: &Utf8Path,
+    config: &ui::Config,
+    rustup_toolchain: &str,
+    profile: &str,
+    driver_override: Option<&Path>,
+) -> Result<Entry> {
+    let cache = Cache::open(
+        target_directory,
+        name,
+        rustup_toolchain,
+        profile,
+        driver_override,
+        &current_dir()?,
+    )?;
+
+    if let Some(entry) = cache.load() {
+        debug!("initialize: Using cached library and driver: {:?}", entry);
+        return Ok(entry);
+    }
+
     // Try to order failures by informativeness: build lib, then find lib, then build/find driver.
     debug!("initialize: Building library '{}'...", name);
-    dylint_internal::cargo::build(&format!("library `{name}`"))
-        .build()
-        .success()?;
+    // Build with the same `RUSTC_WORKSPACE_WRAPPER` used to recover linking flags.
+    // Otherwise, the library's fingerprint would alternate between the two builds, and each
+    // would rebuild the library.
+    let wrapper = rustc_wrapper::path(target_directory)?;
+    let mut command = dylint_internal::cargo::build(&format!("library `{name}`")).build();
+    command
+        .env(env::RUSTC_WORKSPACE_WRAPPER, wrapper)
+        .env(env::RUSTUP_TOOLCHAIN, rustup_toolchain)
+        .args(["--target-dir", target_directory.as_str()])
+        .args(["--profile", profile]);
+    if config.offline {
+        command.arg("--offline");
+    }
+    if config.coverage {
+        let rustflags = std::env::var(env::RUSTFLAGS).unwrap_or_default();
+        command.env(
+            env::RUSTFLAGS,
+            format!("{rustflags} -C instrument-coverage").trim_start(),
+        );
+    }
+    command.success().with_context(|| offline_note(config))?;
     debug!("initialize: Library build completed successfully");
 
-    // `DYLINT_LIBRARY_PATH` must be set before `dylint_libs` is called.
-    let metadata = dylint_internal::cargo::current_metadata().unwrap();
-    let dylint_library_path = metadata.target_directory.join("debug");
-    debug!(
-        "initialize: Setting DYLINT_LIBRARY_PATH to: {}",
-        dylint_library_path
+    let library = library_path(name, target_directory, rustup_toolchain, profile);
+    ensure!(
+        library.exists(),
+        "Could not find library `{}`",
+        library.display()
     );
-    unsafe {
-        set_var(env::DYLINT_LIBRARY_PATH, dylint_library_path);
-    }
 
-    debug!("initialize: Getting dylint_libs...");
-    let dylint_libs = dylint_libs(name)?;
-    debug!("initialize: dylint_libs result: {}", dylint_libs);
+    let driver = driver(config, rustup_toolchain, driver_override)?;
 
-    debug!("initialize: Getting dylint driver...");
-    let driver =
-        dylint::driver_builder::get(&dylint::opts::Dylint::default(), env!("RUSTUP_TOOLCHAIN"))?;
+    let entry = Entry { driver, library };
+    cache.store(&entry)?;
+    Ok(entry)
+}
+
+/// Returns the prebuilt driver, if any, and otherwise has Dylint find or build one.
+fn driver(
+    config: &ui::Config,
+    rustup_toolchain: &str,
+    driver_override: Option<&Path>,
+) -> Result<PathBuf> {
+    let driver = if let Some(driver) = driver_override {
+        driver.to_owned()
+    } else {
+        debug!("initialize: Getting dylint driver...");
+        dylint::driver_builder::get(&dylint::opts::Dylint::default(), rustup_toolchain)
+            .with_context(|| {
+                if config.offline {
+                    "Could not get driver offline; build it beforehand, or set its path with \
+                     `Test::driver_path` or `DYLINT_TESTING_DRIVER`"
+                        .to_owned()
+                } else {
+                    "Could not get driver".to_owned()
+                }
+            })?
+    };
     debug!("initialize: Got driver: {}", driver.display());
+    Ok(driver)
+}
 
-    unsafe {
-        set_var(env::CLIPPY_DISABLE_DOCS_LINKS, "true");
-        set_var(env::DYLINT_LIBS, dylint_libs);
+/// Looks for an already built library in the directories in `DYLINT_LIBRARY_PATH`, and then in the
+/// profile's directory.
+fn prebuilt_library(
+    name: &str,
+    target_directory: &Utf8Path,
+    rustup_toolchain: &str,
+    profile: &str,
+) -> Result<PathBuf> {
+    let filename = library_filename(name, rustup_toolchain);
+    let dylint_library_path = std::env::var_os(env::DYLINT_LIBRARY_PATH).unwrap_or_default();
+    let mut candidates = std::env::split_paths(&dylint_library_path)
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .map(|dir| dir.join(&filename))
+        .collect::<Vec<_>>();
+    candidates.push(library_path(
+        name,
+        target_directory,
+        rustup_toolchain,
+        profile,
+    ));
+    candidates
+        .iter()
+        .find(|path| path.is_file())
+        .cloned()
+        .ok_or_else(|| {
+            anyhow!(
+                "Could not find library `{filename}` in any of:\n{}\nBuild the library, or add the \
+                 directory containing it to `DYLINT_LIBRARY_PATH`",
+                candidates
+                    .iter()
+                    .map(|path| format!("    {}", path.display()))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            )
+        })
+}
+
+/// Returns a note for a failed build, mentioning offline mode if it is enabled.
+pub(crate) fn offline_note(config: &ui::Config) -> &'static str {
+    if config.offline {
+        "Build failed in offline mode; a crate may need to be downloaded (e.g., with `cargo fetch`)"
+    } else {
+        "Build failed"
     }
-    debug!("initialize: Environment variables set");
+}
 
-    // Store driver path for future calls
-    let _ = DRIVER.set(driver);
-    debug!("initialize: Driver stored in static, initialization complete");
-    Ok(DRIVER.get().unwrap().as_path())
+/// Returns the prebuilt driver given by `config.driver_path` or `DYLINT_TESTING_DRIVER`, if any.
+fn prebuilt_driver(config: &ui::Config) -> Result<Option<PathBuf>> {
+    let Some(path) = config
+        .driver_path
+        .clone()
+        .or_else(|| std::env::var_os(env::DYLINT_TESTING_DRIVER).map(PathBuf::from))
+    else {
+        return Ok(None);
+    };
+    let path = current_dir()?.join(path);
+    ensure!(
+        path.is_file(),
+        "Could not find prebuilt driver `{}`",
+        path.display()
+    );
+    Ok(Some(path))
 }
 
 #[doc(hidden)]
 pub fn dylint_libs(name: &str) -> Result<String> {
-    let metadata = dylint_internal::cargo::current_metadata().unwrap();
+    let metadata = dylint_internal::cargo::current_metadata()?;
     let rustup_toolchain = env::var(env::RUSTUP_TOOLCHAIN)?;
-    let filename = library_filename(name, &rustup_toolchain);
-    let path = metadata.target_directory.join("debug").join(filename);
-    let paths = vec![path];
+    let paths = vec![library_path(
+        name,
+        &metadata.target_directory,
+        &rustup_toolchain,
+        &profile(None),
+    )];
     serde_json::to_string(&paths).map_err(Into::into)
 }
+
+fn library_path(
+    name: &str,
+    target_directory: &Utf8Path,
+    rustup_toolchain: &str,
+    profile: &str,
+) -> PathBuf {
+    let filename = library_filename(name, rustup_toolchain);
+    target_directory
+        .join(profile_dir(profile))
+        .join(filename)
+        .into_std_path_buf()
+}
+
+/// Returns the Cargo profile to build the library with: `profile` if given, then `CARGO_PROFILE`,
+/// then `dev`.
+fn profile(profile: Option<&str>) -> String {
+    profile
+        .map(ToOwned::to_owned)
+        .or_else(|| std::env::var(env::CARGO_PROFILE).ok())
+        .filter(|profile| !profile.is_empty())
+        .unwrap_or_else(|| "dev".to_owned())
+}
+
+/// Returns the name of the directory (relative to the target directory) holding `profile`'s
+/// artifacts.
+fn profile_dir(profile: &str) -> &str {
+    match profile {
+        "dev" | "test" => "debug",
+        "bench" => "release",
+        _ => profile,
+    }
+}
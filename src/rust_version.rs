@@ -0,0 +1,95 @@
+use crate::{diagnostics::header, env, ui};
+use anyhow::{Context, Result, anyhow, ensure};
+use std::{collections::BTreeMap, process::Command, sync::Mutex};
+
+/// A Rust version, e.g., `(1, 78, 0)`.
+type Version = (u64, u64, u64);
+
+/// Versions of the toolchains queried so far, keyed by toolchain (`None` for the active one).
+static VERSIONS: Mutex<BTreeMap<Option<String>, Version>> = Mutex::new(BTreeMap::new());
+
+/// Returns the `//@min-rust-version:` header excluding `source` from the toolchain, if any.
+///
+/// The header's version, or else `config.min_rust_version`, is compared with the version of
+/// `config.toolchain`. Pre-release suffixes are ignored, so a `1.78.0-nightly` toolchain satisfies a
+/// minimum of `1.78`.
+pub(crate) fn skip_reason(source: &str, config: &ui::Config) -> Result<Option<String>> {
+    let minimum = source
+        .lines()
+        .find_map(|line| header(line, None)?.strip_prefix("min-rust-version:"))
+        .map(str::trim)
+        .or(config.min_rust_version.as_deref());
+    let Some(minimum) = minimum else {
+        return Ok(None);
+    };
+    let required =
+        parse(minimum).ok_or_else(|| anyhow!("Could not parse Rust version `{minimum}`"))?;
+    let version = version(config.toolchain.as_deref())?;
+    if version >= required {
+        return Ok(None);
+    }
+    let (major, minor, patch) = version;
+    Ok(Some(format!(
+        "min-rust-version: {minimum}, have {major}.{minor}.{patch}"
+    )))
+}
+
+/// Returns the version of `toolchain`, or of the active toolchain if `toolchain` is `None`.
+fn version(toolchain: Option<&str>) -> Result<Version> {
+    let mut versions = VERSIONS.lock().unwrap();
+    let key = toolchain.map(ToOwned::to_owned);
+    if let Some(&version) = versions.get(&key) {
+        return Ok(version);
+    }
+
+    let rustc = std::env::var_os(env::RUSTC).unwrap_or_else(|| "rustc".into());
+    let mut command = Command::new(rustc);
+    if let Some(toolchain) = toolchain {
+        command.env(env::RUSTUP_TOOLCHAIN, toolchain);
+    }
+    let output = command
+        .arg("--version")
+        .output()
+        .with_context(|| "Could not get `rustc` version")?;
+    ensure!(
+        output.status.success(),
+        "`rustc --version` failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    // E.g., `rustc 1.80.0-nightly (7c52d2db6 2024-06-03)`
+    let version = stdout
+        .split_whitespace()
+        .nth(1)
+        .and_then(parse)
+        .ok_or_else(|| anyhow!("Could not parse `rustc --version` output: {stdout}"))?;
+    versions.insert(key, version);
+    Ok(version)
+}
+
+/// Parses a version such as `1.78` or `1.78.0-nightly`. Missing components are zero.
+fn parse(version: &str) -> Option<Version> {
+    let version = version
+        .split_once('-')
+        .map_or(version, |(version, _)| version);
+    let mut components = version.split('.').map(str::parse::<u64>);
+    let major = components.next()?.ok()?;
+    let minor = components.next().transpose().ok()?.unwrap_or_default();
+    let patch = components.next().transpose().ok()?.unwrap_or_default();
+    components.next().is_none().then_some((major, minor, patch))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn versions_are_parsed() {
+        assert_eq!(parse("1.78"), Some((1, 78, 0)));
+        assert_eq!(parse("1.78.1"), Some((1, 78, 1)));
+        assert_eq!(parse("1.80.0-nightly"), Some((1, 80, 0)));
+        assert_eq!(parse("1"), Some((1, 0, 0)));
+        assert_eq!(parse("1.x"), None);
+        assert_eq!(parse("1.2.3.4"), None);
+    }
+}
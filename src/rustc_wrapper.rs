@@ -0,0 +1,59 @@
+use crate::env;
+use anyhow::{Context, Result, ensure};
+use cargo_metadata::camino::Utf8Path;
+use log::debug;
+use std::{
+    env::consts,
+    fs::{create_dir_all, read_to_string, rename, write},
+    path::PathBuf,
+    process::Command,
+};
+
+/// Source of the `RUSTC_WORKSPACE_WRAPPER` used to record `rustc` invocations.
+const SOURCE: &str = include_str!("rustc_wrapper/main.rs");
+
+/// Returns the path of the wrapper, compiling it into `target_directory` if necessary.
+pub fn path(target_directory: &Utf8Path) -> Result<PathBuf> {
+    compile(target_directory, "rustc_wrapper", SOURCE)
+}
+
+/// Returns the path of the `std`-only program `name` with the given source, compiling it into
+/// `target_directory` if necessary.
+///
+/// The program is recompiled only when its source changes. It is compiled to a temporary file and
+/// then renamed, so concurrent test binaries never observe a partially written executable.
+pub fn compile(target_directory: &Utf8Path, name: &str, contents: &str) -> Result<PathBuf> {
+    let dir = target_directory.join("dylint_testing");
+    create_dir_all(&dir).with_context(|| format!("`create_dir_all` failed for `{dir}`"))?;
+
+    let source = dir.join(format!("{name}.rs"));
+    let wrapper = dir.join(format!("{name}{}", consts::EXE_SUFFIX));
+
+    if wrapper.exists() && read_to_string(&source).is_ok_and(|existing| existing == contents) {
+        return Ok(wrapper.into_std_path_buf());
+    }
+
+    debug!("rustc_wrapper: Compiling {}", wrapper);
+    let tempdir = tempfile::tempdir_in(&dir)
+        .with_context(|| format!("Could not create temporary directory in `{dir}`"))?;
+    let temp_source = tempdir.path().join(format!("{name}.rs"));
+    let temp_wrapper = tempdir.path().join(wrapper.file_name().unwrap());
+    write(&temp_source, contents)
+        .with_context(|| format!("Could not write `{}`", temp_source.display()))?;
+
+    let rustc = std::env::var_os(env::RUSTC).unwrap_or_else(|| "rustc".into());
+    let status = Command::new(rustc)
+        .args(["--edition=2021", "-O", "-o"])
+        .arg(&temp_wrapper)
+        .arg(&temp_source)
+        .status()
+        .with_context(|| format!("Could not compile `{name}.rs`"))?;
+    ensure!(status.success(), "Could not compile `{name}.rs`");
+
+    // Rename the wrapper before the source, so that a matching source implies an
+    // up-to-date wrapper.
+    rename(&temp_wrapper, &wrapper).with_context(|| format!("Could not rename to `{wrapper}`"))?;
+    rename(&temp_source, &source).with_context(|| format!("Could not rename to `{source}`"))?;
+
+    Ok(wrapper.into_std_path_buf())
+}
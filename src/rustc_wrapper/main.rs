@@ -0,0 +1,54 @@
+// This file is compiled on first use and used as `RUSTC_WORKSPACE_WRAPPER`. It records
+// each `rustc` invocation for a workspace member, then runs `rustc`. It must not depend on anything
+// other than `std`.
+
+use std::{
+    env::{args_os, var_os},
+    fs::{create_dir_all, write},
+    path::PathBuf,
+    process::{Command, exit},
+};
+
+fn main() {
+    let mut args = args_os().skip(1);
+    let Some(rustc) = args.next() else {
+        eprintln!("rustc_wrapper: missing `rustc` argument");
+        exit(1);
+    };
+    let args = args.collect::<Vec<_>>();
+
+    if let Some(dir) = var_os("DYLINT_TESTING_RUSTC_LOG") {
+        let strs = args
+            .iter()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        if let Some(crate_name) = value_of(&strs, "--crate-name") {
+            let kind = if strs.iter().any(|arg| arg == "--test") {
+                "test"
+            } else {
+                value_of(&strs, "--crate-type").unwrap_or("bin")
+            };
+            let dir = PathBuf::from(dir);
+            // One argument per line, so that arguments containing spaces survive.
+            let result = create_dir_all(&dir)
+                .and_then(|()| write(dir.join(format!("{crate_name}-{kind}")), strs.join("\n")));
+            if let Err(error) = result {
+                eprintln!("rustc_wrapper: could not record invocation: {error}");
+            }
+        }
+    }
+
+    match Command::new(rustc).args(args).status() {
+        Ok(status) => exit(status.code().unwrap_or(1)),
+        Err(error) => {
+            eprintln!("rustc_wrapper: could not run `rustc`: {error}");
+            exit(1);
+        }
+    }
+}
+
+fn value_of<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    args.windows(2)
+        .find(|window| window[0] == flag)
+        .map(|window| window[1].as_str())
+}
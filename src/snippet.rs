@@ -0,0 +1,81 @@
+use crate::{
+    diagnostics::{Diagnostic, compile_json, is_summary},
+    runtime::Driver,
+    ui,
+};
+use anyhow::{Context, Result};
+use std::fs::write;
+
+/// A snippet compiled by [`crate::compile_snippet`]
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct CompiledCrate {
+    /// The driver's exit code, or `None` if it was killed, e.g., by a signal
+    pub exit_code: Option<i32>,
+    /// The diagnostics emitted, excluding summaries such as `aborting due to 2 previous errors`
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl CompiledCrate {
+    /// Returns true if the driver exited successfully, i.e., no errors were emitted.
+    #[must_use]
+    pub fn is_success(&self) -> bool {
+        self.exit_code == Some(0)
+    }
+
+    /// Returns the diagnostics emitted by the lint named `lint`, e.g., `my_lint`.
+    pub fn lint_diagnostics(&self, lint: &str) -> impl Iterator<Item = &Diagnostic> {
+        self.diagnostics.iter().filter(move |diagnostic| {
+            diagnostic
+                .code
+                .as_ref()
+                .is_some_and(|code| code.code == lint)
+        })
+    }
+}
+
+/// Compiles `code` with `driver` as a library crate, as a fixture would be compiled.
+pub(crate) fn compile(driver: &Driver, code: &str) -> Result<CompiledCrate> {
+    let tempdir = tempfile::tempdir().with_context(|| "`tempdir` failed")?;
+    let file = tempdir.path().join("snippet.rs");
+    write(&file, code).with_context(|| format!("Could not write `{}`", file.display()))?;
+
+    // A snippet is compiled as a library, so that it needs no `main` function.
+    let config = ui::Config {
+        rustc_flags: vec!["--crate-type=lib".to_owned()],
+        ..ui::Config::default()
+    };
+    let (exit_code, values) = compile_json(driver, &file, None, &config)?;
+    let diagnostics = values
+        .into_iter()
+        .filter(|value| !is_summary(value))
+        .map(serde_json::from_value::<Diagnostic>)
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| "Could not parse the snippet's diagnostics")?;
+    Ok(CompiledCrate {
+        exit_code,
+        diagnostics,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn snippets_are_compiled_as_libraries() {
+        // Plain rustc stands in for the driver, since the snippet is compiled like any fixture.
+        let driver = Driver {
+            path: PathBuf::from("rustc"),
+            library: PathBuf::new(),
+            envs: Vec::new(),
+        };
+        let compiled = compile(&driver, "pub fn f() {\n    let x = 1;\n}\n").unwrap();
+        assert!(!compiled.is_success());
+        assert_eq!(compiled.lint_diagnostics("unused_variables").count(), 1);
+        assert!(compiled.lint_diagnostics("my_lint").next().is_none());
+
+        assert!(compile(&driver, "pub fn f() {}\n").unwrap().is_success());
+    }
+}
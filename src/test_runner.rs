@@ -1,88 +1,1303 @@
 use crate::{
-    cargo_integration::linking_flags,
-    env::{self, VarGuard, is_env_truthy},
-    ui,
+    annotations::inject_annotations,
+    aux,
+    cargo_integration::{linking, unsatisfiable_features},
+    diagnostics::{
+        assert_diagnostics, check_json_snapshots, expected_extension, file_revisions, header,
+        revisions,
+    },
+    diagnostics_wrapper, dump,
+    env::{self, is_env_truthy},
+    exit_status::{bless_exit_statuses, default_exit_status},
+    expanded, glob, ice, incremental, negative, noise, platform, report, repro,
+    runtime::Driver,
+    rust_version, ui,
 };
-use anyhow::{Context, Result, anyhow};
+use anyhow::{Context, Result, anyhow, bail, ensure};
 use cargo_metadata::{Metadata, Package, Target};
 use log::debug;
-use std::{ffi::OsString, fs::copy, path::Path, sync::Mutex};
+use regex::Regex;
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet},
+    ffi::OsString,
+    fs::{copy, create_dir_all, read, read_dir, read_to_string, remove_file, rename, write},
+    path::{Path, PathBuf},
+    process::Command,
+    sync::LazyLock,
+    time::{Duration, Instant},
+};
+use ui_test::{
+    custom_flags::{run::Run, rustfix::RustfixMode},
+    dependencies::DependencyBuilder,
+};
+
+/// Matches `//~` annotations, including any whitespace preceding them on the line.
+static ANNOTATION_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?m)[ \t]*//~.*$").unwrap());
+
+/// Extensions of the files holding a fixture's expected output.
+const EXPECTED_EXTENSIONS: [&str; 7] = [
+    expanded::EXTENSION,
+    "fixed",
+    "json",
+    "run.stderr",
+    "run.stdout",
+    "stderr",
+    "stdout",
+];
+
+thread_local! {
+    /// The files blessing wrote or removed on this thread, so that [`ui::Test::try_run`] can tell
+    /// which files or targets it changed.
+    static BLESSED: RefCell<Vec<PathBuf>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Records that blessing wrote or removed `path`.
+pub(crate) fn record_blessed(path: &Path) {
+    BLESSED.with_borrow_mut(|blessed| blessed.push(path.to_owned()));
+}
+
+/// Returns the files recorded by [`record_blessed`] on this thread since the last call.
+pub(crate) fn take_blessed() -> Vec<PathBuf> {
+    BLESSED.take()
+}
+
+pub(crate) fn run_tests(driver: &Driver, src_base: &Path, config: &ui::Config) -> Result<()> {
+    if env::is_bless_dry_run(config) {
+        return bless_dry_run(driver, src_base, config);
+    }
+    if let Some(output_dir) = bless_output_dir(config) {
+        let mut changes = bless_overlay(driver, src_base, config)?;
+        if env::is_bless_new_only(config) {
+            changes.retain(|(_, old, _)| old.is_none());
+        }
+        return write_bless_output(&changes, output_dir);
+    }
+    guard_bless(src_base, config)?;
+    if env::is_bless_new_only(config) {
+        let changes = bless_overlay(driver, src_base, config)?;
+        return write_new_files(&changes, config);
+    }
+    // ui_test blesses in place, so the files it changes are found by comparing before and after.
+    let before = (env::is_bless_truthy(config) || is_env_truthy(env::BLESS_ANNOTATIONS))
+        .then(|| snapshot(src_base))
+        .transpose()?;
+    check_orphans(src_base, config)?;
+    let result = if let Some(cache_path) = &config.incremental_cache {
+        run_incrementally(driver, src_base, config, cache_path)
+    } else {
+        run_editions(driver, src_base, config)
+    };
+    if let Some(before) = before {
+        record_changes(src_base, &before)?;
+    }
+    result
+}
+
+/// Returns the contents of each file beneath `dir`.
+fn snapshot(dir: &Path) -> Result<BTreeMap<PathBuf, Vec<u8>>> {
+    files(dir)?
+        .into_iter()
+        .map(|file| {
+            let contents =
+                read(&file).with_context(|| format!("Could not read `{}`", file.display()))?;
+            Ok((file, contents))
+        })
+        .collect()
+}
+
+/// Records the files beneath `dir` that were written or removed since `before` was taken (see
+/// [`snapshot`]).
+fn record_changes(dir: &Path, before: &BTreeMap<PathBuf, Vec<u8>>) -> Result<()> {
+    let after = snapshot(dir)?;
+    for path in before.keys().chain(after.keys()).collect::<BTreeSet<_>>() {
+        if before.get(path) != after.get(path) {
+            record_blessed(path);
+        }
+    }
+    Ok(())
+}
+
+/// Runs the files beneath `src_base` from a temporary copy with the directories passed to
+/// [`ui::Test::aux_dir`] beside them, so that the files can `include_str!` what is in them. Blessed
+/// files are copied back. Nothing is written beside the files themselves, so that runs sharing
+/// `src_base` cannot interfere, and an interrupted run leaves nothing behind.
+fn run_with_aux_dirs(driver: &Driver, src_base: &Path, config: &ui::Config) -> Result<()> {
+    let tempdir = tempfile::tempdir().with_context(|| "`tempdir` failed")?;
+    let overlay = tempdir.path();
+    copy_files(src_base, overlay)?;
+    let copied = copy_aux_dirs(overlay, config)?;
+
+    // The copied files are neither run nor blessed.
+    let mut config = config.clone();
+    config.aux_dirs.clear();
+    config.excluded_files.extend(copied.iter().cloned());
+    let result = run_editions(driver, overlay, &config);
+
+    let bless_annotations = is_env_truthy(env::BLESS_ANNOTATIONS);
+    if bless_annotations || env::is_blessing(&config) {
+        copy_back_neutralized(overlay, src_base, bless_annotations, &copied)?;
+    }
+
+    result
+}
+
+/// Copies each directory passed to [`ui::Test::aux_dir`] into `dir` under its own name, e.g.,
+/// `tests/data` to `dir/data`, and returns the paths of the files copied, relative to `dir`. A
+/// directory already there is an error, rather than being overwritten.
+fn copy_aux_dirs(dir: &Path, config: &ui::Config) -> Result<Vec<PathBuf>> {
+    let mut copied = Vec::new();
+    for aux_dir in &config.aux_dirs {
+        let name = aux_dir
+            .file_name()
+            .ok_or_else(|| anyhow!("Could not get file name of `{}`", aux_dir.display()))?;
+        let dest = dir.join(name);
+        ensure!(
+            !dest.exists(),
+            "Could not copy `{}` to `{}`, because it already exists",
+            aux_dir.display(),
+            dest.display()
+        );
+        copy_files(aux_dir, &dest)?;
+        for file in files(aux_dir)? {
+            copied.push(Path::new(name).join(file.strip_prefix(aux_dir)?));
+        }
+    }
+    Ok(copied)
+}
+
+/// Runs the files beneath `src_base` whose inputs changed since they last passed, and records them
+/// once they pass. A failing run records nothing, since it is not known which files failed.
+fn run_incrementally(
+    driver: &Driver,
+    src_base: &Path,
+    config: &ui::Config,
+    cache_path: &Path,
+) -> Result<()> {
+    let files = source_files(src_base, config)?;
+    let unchanged = incremental::unchanged_files(driver, src_base, &files, config, cache_path)?;
+    if !unchanged.is_empty() {
+        eprintln!(
+            "Skipping {} of {} file(s) in `{}`, unchanged since they last passed",
+            unchanged.len(),
+            files.len(),
+            src_base.display()
+        );
+    }
+    if unchanged.len() < files.len() {
+        let mut config = config.clone();
+        config.excluded_files = unchanged;
+        run_editions(driver, src_base, &config)?;
+    }
+    incremental::record(driver, &files, config, cache_path)
+}
+
+/// Removes or reports the expected files beneath `src_base` that belong to no source file.
+///
+/// When blessing, orphans are removed if the config asks for it, and reported otherwise. When not
+/// blessing, orphans are an error if the config asks for them to be removed.
+fn check_orphans(src_base: &Path, config: &ui::Config) -> Result<()> {
+    let orphans = orphans(src_base)?
+        .into_iter()
+        .filter(|orphan| {
+            orphan.strip_prefix(src_base).is_ok_and(|relative| {
+                !config
+                    .excluded_files
+                    .iter()
+                    .any(|excluded| excluded == relative)
+            })
+        })
+        .collect::<Vec<_>>();
+    if orphans.is_empty() {
+        return Ok(());
+    }
+    let list = orphans
+        .iter()
+        .map(|orphan| format!("    {}", orphan.display()))
+        .collect::<Vec<_>>()
+        .join("\n");
+    match (config.prune_orphans, env::is_blessing(config)) {
+        (true, true) => {
+            for orphan in &orphans {
+                remove_file(orphan)
+                    .with_context(|| format!("Could not remove `{}`", orphan.display()))?;
+                eprintln!("Removed orphaned expected file `{}`", orphan.display());
+            }
+        }
+        (true, false) => bail!(
+            "Found expected files without a source file:\n{list}\nRun `{}` to remove them.",
+            env::bless_command(config)
+        ),
+        (false, true) => eprintln!(
+            "warning: found expected files without a source file:\n{list}\nUse \
+             `Test::prune_orphans(true)` to remove them when blessing."
+        ),
+        (false, false) => {}
+    }
+    Ok(())
+}
+
+/// Returns the expected files beneath `src_base` that belong to no source file, e.g., because the
+/// source file was renamed or removed.
+fn orphans(src_base: &Path) -> Result<Vec<PathBuf>> {
+    let sources = files_with_extension(src_base, "rs")?;
+    Ok(files(src_base)?
+        .into_iter()
+        .filter(|path| {
+            is_expected_file(path)
+                && !aux::is_aux(src_base, path)
+                && !sources
+                    .iter()
+                    .any(|source| is_expected_file_of(path, source))
+        })
+        .collect())
+}
+
+/// Returns true if `path` looks like an expected file, e.g., `foo.stderr`, `foo.rev.fixed`,
+/// `foo.stderr.e2024`, or `foo.expanded.rs`.
+fn is_expected_file(path: &Path) -> bool {
+    path.file_name().is_some_and(|file_name| {
+        let file_name = file_name.to_string_lossy();
+        file_name.match_indices('.').any(|(i, _)| {
+            let rest = &file_name[i + 1..];
+            EXPECTED_EXTENSIONS.iter().any(|extension| {
+                rest.strip_prefix(extension)
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+            })
+        })
+    })
+}
+
+/// Refuses to bless if files beneath `dir` have unstaged changes, when the config asks for it, so
+/// that blessing cannot overwrite manual edits.
+pub(crate) fn guard_bless(dir: &Path, config: &ui::Config) -> Result<()> {
+    if !config.bless_guard
+        || !(env::is_bless_truthy(config) || is_env_truthy(env::BLESS_ANNOTATIONS))
+        || is_env_truthy(env::DYLINT_TESTING_ALLOW_DIRTY)
+    {
+        return Ok(());
+    }
+    let output = Command::new("git")
+        .args(["status", "--porcelain", "--untracked-files=all", "--", "."])
+        .current_dir(dir)
+        .output();
+    let output = match output {
+        Ok(output) if output.status.success() => output,
+        _ => {
+            debug!(
+                "guard_bless: `git status` failed for {}, not guarding",
+                dir.display()
+            );
+            return Ok(());
+        }
+    };
+    // The second column of each line is the file's status in the working tree.
+    let dirty = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|line| line.as_bytes().get(1).is_some_and(|&status| status != b' '))
+        .map(|line| format!("    {}", line.get(3..).unwrap_or_default()))
+        .collect::<Vec<_>>();
+    ensure!(
+        dirty.is_empty(),
+        "Refusing to bless, because files in `{}` have unstaged changes:\n{}\nStage or stash them \
+         first, or set `{}=1` to bless anyway.",
+        dir.display(),
+        dirty.join("\n"),
+        env::DYLINT_TESTING_ALLOW_DIRTY
+    );
+    Ok(())
+}
+
+/// Blesses a copy of `src_base`, and reports how the expected files would change, without changing
+/// them.
+fn bless_dry_run(driver: &Driver, src_base: &Path, config: &ui::Config) -> Result<()> {
+    let changes = bless_overlay(driver, src_base, config)?;
+    preview_bless(&changes, config);
+    Ok(())
+}
+
+/// Blesses a copy of `src_base`, and returns each file beneath `src_base` with its current and
+/// blessed contents.
+fn bless_overlay(
+    driver: &Driver,
+    src_base: &Path,
+    config: &ui::Config,
+) -> Result<Vec<(PathBuf, Option<Vec<u8>>, Option<Vec<u8>>)>> {
+    let tempdir = tempfile::tempdir().with_context(|| "`tempdir` failed")?;
+    let overlay = tempdir.path();
+    copy_files(src_base, overlay)?;
+
+    check_orphans(overlay, config)?;
+    run_editions(driver, overlay, config)?;
+
+    let mut relatives = BTreeSet::new();
+    for dir in [src_base, overlay] {
+        for file in files(dir)? {
+            relatives.insert(file.strip_prefix(dir)?.to_owned());
+        }
+    }
+    relatives
+        .iter()
+        .map(|relative| {
+            Ok((
+                src_base.join(relative),
+                read_if_exists(&src_base.join(relative))?,
+                read_if_exists(&overlay.join(relative))?,
+            ))
+        })
+        .collect::<Result<Vec<_>>>()
+}
+
+/// Returns the directory to write blessed files to, if blessing (but not previewing) and the config
+/// sets one.
+fn bless_output_dir(config: &ui::Config) -> Option<&Path> {
+    (!env::is_bless_dry_run(config)
+        && (env::is_bless_truthy(config) || is_env_truthy(env::BLESS_ANNOTATIONS)))
+    .then_some(config.bless_output_dir.as_deref())
+    .flatten()
+}
+
+/// Writes the blessed contents of the changed files, given as in [`preview_bless`], to the same
+/// paths (relative to the current directory) beneath `output_dir`. A file blessing would remove is
+/// reported, but left in place.
+fn write_bless_output(
+    changes: &[(PathBuf, Option<Vec<u8>>, Option<Vec<u8>>)],
+    output_dir: &Path,
+) -> Result<()> {
+    let current_dir = std::env::current_dir().with_context(|| "Could not get current directory")?;
+    let mut count = 0;
+    for (path, old, new) in changes {
+        if old == new {
+            continue;
+        }
+        let relative = path.strip_prefix(&current_dir).unwrap_or(path);
+        ensure!(
+            relative.is_relative(),
+            "Could not write `{}` to `{}`, because it is not beneath the current directory",
+            path.display(),
+            output_dir.display()
+        );
+        let Some(contents) = new else {
+            eprintln!(
+                "Blessing would remove `{}`; remove it by hand if it is no longer needed",
+                path.display()
+            );
+            continue;
+        };
+        let dest = output_dir.join(relative);
+        if let Some(parent) = dest.parent() {
+            create_dir_all(parent)
+                .with_context(|| format!("`create_dir_all` failed for `{}`", parent.display()))?;
+        }
+        write(&dest, contents).with_context(|| format!("Could not write `{}`", dest.display()))?;
+        record_blessed(&dest);
+        count += 1;
+    }
+    eprintln!(
+        "Wrote {count} blessed file(s) to `{}`; review them, then accept them with `Test::accept`",
+        output_dir.display()
+    );
+    Ok(())
+}
+
+/// Writes the blessed contents of the files that do not exist yet, given as in [`preview_bless`],
+/// and reports the existing files that blessing would change, which are left unchanged.
+fn write_new_files(
+    changes: &[(PathBuf, Option<Vec<u8>>, Option<Vec<u8>>)],
+    config: &ui::Config,
+) -> Result<()> {
+    let (mut created, mut kept) = (0, 0);
+    for (path, old, new) in changes {
+        if old == new {
+            continue;
+        }
+        let Some(contents) = new.as_ref().filter(|_| old.is_none()) else {
+            eprintln!("Leaving `{}` unchanged", path.display());
+            kept += 1;
+            continue;
+        };
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent)
+                .with_context(|| format!("`create_dir_all` failed for `{}`", parent.display()))?;
+        }
+        write(path, contents).with_context(|| format!("Could not write `{}`", path.display()))?;
+        record_blessed(path);
+        created += 1;
+    }
+    eprintln!(
+        "{}=new-only: created {created} file(s); left {kept} existing file(s) unchanged",
+        env::bless_var_name(config)
+    );
+    Ok(())
+}
+
+/// Prints the changes blessing would make, each given as a path and its current and blessed
+/// contents.
+fn preview_bless(changes: &[(PathBuf, Option<Vec<u8>>, Option<Vec<u8>>)], config: &ui::Config) {
+    let mut count = 0;
+    for (path, old, new) in changes {
+        let old = old.as_deref().map(String::from_utf8_lossy);
+        let new = new.as_deref().map(String::from_utf8_lossy);
+        let preview = report::bless_preview(
+            &path.display().to_string(),
+            old.as_deref(),
+            new.as_deref(),
+            config.diff_context_lines,
+            report::color_enabled(),
+        );
+        if !preview.is_empty() {
+            eprintln!("{preview}");
+            count += 1;
+        }
+    }
+    eprintln!(
+        "{}=dry-run: {count} file(s) would change; nothing was written",
+        env::bless_var_name(config)
+    );
+}
+
+fn run_editions(driver: &Driver, src_base: &Path, config: &ui::Config) -> Result<()> {
+    if !config.aux_dirs.is_empty() {
+        return run_with_aux_dirs(driver, src_base, config);
+    }
+    if !config.editions.is_empty() {
+        let mut failures = Vec::new();
+        for edition in &config.editions {
+            let mut config = config.clone();
+            config.editions.clear();
+            config.edition = Some(edition.clone());
+            if let Err(error) = run_editions(driver, src_base, &config) {
+                failures.push(format!("edition {edition}: {error:?}"));
+            }
+        }
+        ensure!(failures.is_empty(), "{}", failures.join("\n\n"));
+        return Ok(());
+    }
+
+    // Toolchain overrides take precedence over edition overrides, because they are applied last.
+    let suffixes = config
+        .edition
+        .iter()
+        .map(|edition| format!("e{edition}"))
+        .chain(config.toolchain.clone())
+        .collect::<Vec<_>>();
+    run_tests_with_overrides(driver, src_base, config, &suffixes)
+}
+
+fn run_suite(driver: &Driver, src_base: &Path, config: &ui::Config) -> Result<()> {
+    let skipped = skipped_files(src_base, config)?;
+    for (file, reason) in &skipped {
+        eprintln!("Skipping `{}` ({reason})", file.display());
+    }
+
+    // ui_test decides the order of the files it is given, and runs them in parallel. So to run
+    // them in a shuffled order, or to time each one, run them one at a time.
+    if (config.shuffle_seed.is_some() || config.timings || config.max_time_per_file.is_some())
+        && config.only_file.is_none()
+    {
+        return run_files_one_at_a_time(driver, src_base, config);
+    }
+
+    // Auxiliary crates are built first, because every compilation with the driver needs them.
+    let aux_dir = tempfile::tempdir().with_context(|| "`tempdir` failed")?;
+    let mut config = config.clone();
+    config.rustc_flags.extend(aux::build(
+        &source_files(src_base, &config)?,
+        &config,
+        aux_dir.path(),
+    )?);
+
+    // Known bugs are expected to fail, so they are run one at a time after the others (see
+    // `check_known_bugs`), and excluded from the run and the checks that follow it.
+    let mut known_bugs = Vec::new();
+    for file in source_files(src_base, &config)? {
+        if !skipped.iter().any(|(skipped, _)| *skipped == file)
+            && is_known_bug(src_base, &file, &config)?
+        {
+            known_bugs.push(file);
+        }
+    }
+    let known_bug_cfg = (!known_bugs.is_empty()).then(|| base_config(driver, src_base, &config));
+    config.excluded_files.extend(
+        known_bugs
+            .iter()
+            .filter_map(|file| file.strip_prefix(src_base).ok())
+            .map(ToOwned::to_owned),
+    );
+    let config = &config;
+
+    let mut cfg = base_config(driver, src_base, config);
+    cfg.skip_files.extend(
+        aux::files(src_base)?
+            .iter()
+            .map(|file| file.display().to_string()),
+    );
+    cfg.skip_files
+        .extend(skipped.iter().map(|(file, _)| file.display().to_string()));
+
+    // When only some files are blessed, the others are not run at all, so that they cannot be
+    // rewritten.
+    if let Some(pattern) = bless_filter(config) {
+        let selected = source_files(src_base, config)?;
+        if selected.is_empty() {
+            eprintln!(
+                "No files in `{}` match the bless filter `{pattern}`",
+                src_base.display()
+            );
+            return Ok(());
+        }
+        cfg.filter_files = selected
+            .iter()
+            .map(|file| file.display().to_string())
+            .collect();
+    }
 
-static MUTEX: Mutex<()> = Mutex::new(());
+    let bless_annotations = is_env_truthy(env::BLESS_ANNOTATIONS);
+    if bless_annotations {
+        inject_annotations(driver, src_base, config)?;
+    }
+
+    let bless = bless_annotations || env::is_blessing(config);
+    if bless && config.bless_exit_status {
+        bless_exit_statuses(driver, src_base, config)?;
+    }
+    debug!("run_tests: bless variable = {:?}", env::bless_var(config));
+    debug!("run_tests: bless = {}", bless);
+    debug!("run_tests: src_base = {}", src_base.display());
+    debug!("run_tests: driver = {}", driver.path.display());
+
+    // Dumps are written before the files are checked, so that they are available when a check fails.
+    if let Some(dump_dir) = &config.dump_dir {
+        dump::write_dumps(driver, src_base, dump_dir, config)?;
+    }
+
+    if let Some(assert) = &config.assert_diagnostics {
+        return assert_diagnostics(driver, src_base, config, assert);
+    }
+
+    if config.verify_fixes {
+        cfg.comment_defaults
+            .base()
+            .set_custom("rustfix", RustfixMode::MachineApplicable);
+    }
+
+    // Files in a `clean` directory must compile without diagnostics. They are run separately,
+    // because ui_test has no per-directory defaults.
+    let clean_files = source_files(src_base, config)?
+        .into_iter()
+        .filter(|file| is_clean(src_base, file))
+        .collect::<Vec<_>>();
+    let clean_cfg = (!clean_files.is_empty()).then(|| {
+        let mut clean_cfg = cfg.clone();
+        clean_cfg.filter_files = clean_files
+            .iter()
+            .map(|file| file.display().to_string())
+            .collect();
+        clean_cfg.comment_defaults.base().exit_status = ui_test::spanned::Spanned::dummy(0).into();
+        cfg.skip_files
+            .extend(clean_files.iter().map(|file| file.display().to_string()));
+        clean_cfg
+    });
+
+    // ui_test reports a panicking driver as an output conflict, so a failure is examined for ICEs,
+    // which are reported on their own.
+    if let Err(mut error) = run_with_bless(cfg, bless) {
+        if let Some(repro_dir) = &config.repro_dir {
+            repro::write_scripts(driver, src_base, repro_dir, config)?;
+            error = error.context(format!(
+                "Scripts that run the driver as the test did were written to `{}`",
+                repro_dir.display()
+            ));
+        }
+        if let Some(report) = ice::report(driver, src_base, config)? {
+            error = error.context(report);
+        }
+        return Err(error);
+    }
+    if let Some(clean_cfg) = clean_cfg {
+        debug!("run_tests: Running {} clean files", clean_files.len());
+        run_with_bless(clean_cfg, bless).with_context(|| "clean files produced diagnostics")?;
+    }
+    if let Some(known_bug_cfg) = known_bug_cfg {
+        check_known_bugs(&known_bug_cfg, src_base, &known_bugs)?;
+    }
+
+    negative::check(driver, src_base, config)?;
+
+    if config.diagnostics_format == ui::DiagnosticsFormat::Json {
+        check_json_snapshots(driver, src_base, config, bless)?;
+    }
+
+    if config.check_expanded {
+        expanded::check_expanded_snapshots(driver, src_base, config, bless)?;
+    }
+
+    if config.verify_fixes {
+        verify_fixes(driver, src_base, config)?;
+    }
+
+    Ok(())
+}
+
+/// Returns true if `file` is expected to fail, because it has a `//@known-bug` header or matches a
+/// pattern passed to [`ui::Test::expect_failure`].
+fn is_known_bug(src_base: &Path, file: &Path, config: &ui::Config) -> Result<bool> {
+    let relative = file.strip_prefix(src_base).unwrap_or(file);
+    if config
+        .expected_failures
+        .iter()
+        .any(|pattern| glob::matches(pattern, relative))
+    {
+        return Ok(true);
+    }
+    let source =
+        read_to_string(file).with_context(|| format!("Could not read `{}`", file.display()))?;
+    Ok(source
+        .lines()
+        .any(|line| header(line, None).is_some_and(|header| header.starts_with("known-bug"))))
+}
+
+/// Runs each of `files` on its own, and fails if any of them passes, so that a fixed bug is not
+/// left marked as known. Known bugs are never blessed, since their output is not expected to last.
+fn check_known_bugs(cfg: &ui_test::Config, src_base: &Path, files: &[PathBuf]) -> Result<()> {
+    let mut passing = Vec::new();
+    for file in files {
+        let relative = file.strip_prefix(src_base).unwrap_or(file);
+        let mut cfg = cfg.clone();
+        cfg.filter_files = vec![file.display().to_string()];
+        cfg.output_conflict_handling = ui_test::ignore_output_conflict;
+        debug!("check_known_bugs: Running {}", file.display());
+        if ui_test::run_tests(cfg).is_ok() {
+            passing.push(format!("    {}", relative.display()));
+        } else {
+            eprintln!(
+                "`{}` failed, as expected of a known bug",
+                relative.display()
+            );
+        }
+    }
+    ensure!(
+        passing.is_empty(),
+        "Files expected to fail now pass:\n{}\nRemove their `//@known-bug` headers or \
+         `Test::expect_failure` patterns, and bless them.",
+        passing.join("\n")
+    );
+    Ok(())
+}
+
+fn run_files_one_at_a_time(driver: &Driver, src_base: &Path, config: &ui::Config) -> Result<()> {
+    let mut files = source_files(src_base, config)?;
+    if let Some(seed) = config.shuffle_seed {
+        eprintln!("Shuffling files with seed {seed}");
+        shuffle(&mut files, seed);
+    }
+
+    let mut failures = Vec::new();
+    let mut timings = Vec::new();
+    for file in files {
+        let relative = file.strip_prefix(src_base)?.to_owned();
+        let mut config = config.clone();
+        config.only_file = Some(relative.clone());
+        let start = Instant::now();
+        let result = run_suite(driver, src_base, &config);
+        let elapsed = start.elapsed();
+        if let Err(error) = result {
+            failures.push(format!("{}: {error:?}", file.display()));
+        } else if let Some(max_time) = config
+            .max_time_per_file
+            .filter(|&max_time| elapsed > max_time)
+        {
+            failures.push(format!(
+                "{}: took {:.3}s, exceeding the maximum of {:.3}s",
+                file.display(),
+                elapsed.as_secs_f64(),
+                max_time.as_secs_f64()
+            ));
+        }
+        timings.push((elapsed, relative));
+    }
+
+    if config.timings {
+        eprint!("{}", timing_table(src_base, &mut timings));
+    }
+
+    let seed_note = config
+        .shuffle_seed
+        .map(|seed| format!("\n\nShuffled with seed {seed}"))
+        .unwrap_or_default();
+    ensure!(failures.is_empty(), "{}{seed_note}", failures.join("\n\n"));
+    Ok(())
+}
 
-pub(crate) fn run_tests(driver: &Path, src_base: &Path, config: &ui::Config) -> Result<()> {
-    let _lock = MUTEX.lock().unwrap();
+/// Formats the time taken by each file, slowest first.
+fn timing_table(src_base: &Path, timings: &mut [(Duration, PathBuf)]) -> String {
+    timings.sort_by(|(a, a_path), (b, b_path)| b.cmp(a).then_with(|| a_path.cmp(b_path)));
+    let total = timings
+        .iter()
+        .map(|(duration, _)| *duration)
+        .sum::<Duration>();
+    let mut table = format!("Timings for `{}` (slowest first):\n", src_base.display());
+    for (duration, path) in timings.iter() {
+        table.push_str(&format!(
+            "{:>10.3}s  {}\n",
+            duration.as_secs_f64(),
+            path.display()
+        ));
+    }
+    table.push_str(&format!("{:>10.3}s  total\n", total.as_secs_f64()));
+    table
+}
+
+/// Runs the tests with the expected files overridden by those ending in `.{suffix}`, for each of
+/// `suffixes` in turn.
+///
+/// For example, with the suffix `nightly-2025-01-01`, `foo.stderr.nightly-2025-01-01` is used in
+/// place of `foo.stderr` if it exists. The fixtures are copied to a temporary directory in which
+/// the overrides replace the base files. When blessing, output matching the base file is not
+/// duplicated in an override, and output differing from an existing base file is written to an
+/// override, so that blessing under one suffix does not disturb the others.
+fn run_tests_with_overrides(
+    driver: &Driver,
+    src_base: &Path,
+    config: &ui::Config,
+    suffixes: &[String],
+) -> Result<()> {
+    let Some((suffix, suffixes)) = suffixes.split_first() else {
+        return run_suite_with_negative_annotations(driver, src_base, config);
+    };
+
+    let tempdir = tempfile::tempdir().with_context(|| "`tempdir` failed")?;
+    let overlay = tempdir.path();
 
-    // Temporarily set DYLINT_TOML if provided
-    let _var = config
-        .dylint_toml
-        .as_ref()
-        .map(|value| VarGuard::set(env::DYLINT_TOML, value));
+    copy_files(src_base, overlay)?;
+    for file in files(overlay)? {
+        if let Some(base) = override_base(&file, suffix) {
+            debug!("run_tests_with_overrides: Using {}", file.display());
+            rename(&file, &base).with_context(|| {
+                format!(
+                    "Could not rename `{}` to `{}`",
+                    file.display(),
+                    base.display()
+                )
+            })?;
+        }
+    }
+
+    let result = run_tests_with_overrides(driver, overlay, config, suffixes);
+
+    // Copy back whatever was blessed, even if the run failed, so that injected annotations are not
+    // lost. A failed verification pass writes no expected files.
+    let bless_annotations = is_env_truthy(env::BLESS_ANNOTATIONS);
+    if bless_annotations || env::is_blessing(config) {
+        copy_back_overrides(overlay, src_base, suffix, bless_annotations)?;
+    }
+
+    result
+}
 
+/// Runs the suite, hiding any negative annotations (`//~! NOT ...`) from ui_test, which would reject
+/// them. The fixtures are then copied to a temporary directory in which the annotations are
+/// neutralized, and whatever is blessed there is copied back with the annotations restored.
+fn run_suite_with_negative_annotations(
+    driver: &Driver,
+    src_base: &Path,
+    config: &ui::Config,
+) -> Result<()> {
+    let mut has_negative_annotations = false;
+    for file in files_with_extension(src_base, "rs")? {
+        let source = read_to_string(&file)
+            .with_context(|| format!("Could not read `{}`", file.display()))?;
+        has_negative_annotations |= negative::has_negative_annotations(&source);
+    }
+    if !has_negative_annotations {
+        return run_suite(driver, src_base, config);
+    }
+
+    let tempdir = tempfile::tempdir().with_context(|| "`tempdir` failed")?;
+    let overlay = tempdir.path();
+
+    copy_files(src_base, overlay)?;
+    for file in files_with_extension(overlay, "rs")? {
+        let source = read_to_string(&file)
+            .with_context(|| format!("Could not read `{}`", file.display()))?;
+        if negative::has_negative_annotations(&source) {
+            write(&file, negative::neutralize(&source))
+                .with_context(|| format!("Could not write `{}`", file.display()))?;
+        }
+    }
+
+    let result = run_suite(driver, overlay, config);
+
+    let bless_annotations = is_env_truthy(env::BLESS_ANNOTATIONS);
+    if bless_annotations || env::is_blessing(config) {
+        copy_back_neutralized(overlay, src_base, bless_annotations, &[])?;
+    }
+
+    result
+}
+
+/// Copies the files blessed beneath `overlay` back to `src_base`, restoring the negative annotations
+/// in sources. Sources are copied back only if annotations were blessed. The files in `skip`
+/// (relative to `overlay`) were copied there from elsewhere, and are not copied back.
+fn copy_back_neutralized(
+    overlay: &Path,
+    src_base: &Path,
+    bless_annotations: bool,
+    skip: &[PathBuf],
+) -> Result<()> {
+    let mut relatives = BTreeSet::new();
+    for dir in [src_base, overlay] {
+        for file in files(dir)? {
+            relatives.insert(file.strip_prefix(dir)?.to_owned());
+        }
+    }
+    relatives.retain(|relative| !skip.contains(relative));
+
+    for relative in relatives {
+        let (original, blessed) = (src_base.join(&relative), overlay.join(&relative));
+        let is_source = relative
+            .extension()
+            .is_some_and(|extension| extension == "rs");
+        if is_source && !bless_annotations {
+            continue;
+        }
+        let contents = if is_source {
+            read_if_exists(&blessed)?
+                .map(|contents| negative::restore(&String::from_utf8_lossy(&contents)).into_bytes())
+        } else {
+            read_if_exists(&blessed)?
+        };
+        if contents == read_if_exists(&original)? {
+            continue;
+        }
+        match contents {
+            Some(contents) => {
+                if let Some(parent) = original.parent() {
+                    create_dir_all(parent).with_context(|| {
+                        format!("`create_dir_all` failed for `{}`", parent.display())
+                    })?;
+                }
+                write(&original, contents)
+                    .with_context(|| format!("Could not write `{}`", original.display()))?;
+            }
+            None => remove_file(&original)
+                .with_context(|| format!("Could not remove `{}`", original.display()))?,
+        }
+    }
+    Ok(())
+}
+
+/// Copies the files beneath `from` to the same relative paths beneath `to`.
+fn copy_files(from: &Path, to: &Path) -> Result<()> {
+    for file in files(from)? {
+        let dest = to.join(file.strip_prefix(from)?);
+        if let Some(parent) = dest.parent() {
+            create_dir_all(parent)
+                .with_context(|| format!("`create_dir_all` failed for `{}`", parent.display()))?;
+        }
+        copy(&file, &dest).with_context(|| {
+            format!(
+                "Could not copy `{}` to `{}`",
+                file.display(),
+                dest.display()
+            )
+        })?;
+    }
+    Ok(())
+}
+
+/// Returns the expected file that `path` overrides, if `path` is an override for `suffix`.
+fn override_base(path: &Path, suffix: &str) -> Option<PathBuf> {
+    let file_name = path.file_name()?.to_str()?;
+    let base = file_name.strip_suffix(&format!(".{suffix}"))?;
+    EXPECTED_EXTENSIONS
+        .iter()
+        .any(|expected| base.ends_with(&format!(".{expected}")))
+        .then(|| path.with_file_name(base))
+}
+
+fn copy_back_overrides(
+    overlay: &Path,
+    src_base: &Path,
+    suffix: &str,
+    bless_annotations: bool,
+) -> Result<()> {
+    for file in files_with_extension(overlay, "rs")? {
+        let original = src_base.join(file.strip_prefix(overlay)?);
+        if bless_annotations && read_if_exists(&file)? != read_if_exists(&original)? {
+            copy(&file, &original).with_context(|| {
+                format!(
+                    "Could not copy `{}` to `{}`",
+                    file.display(),
+                    original.display()
+                )
+            })?;
+        }
+
+        for extension in file_revisions(&file)?.iter().flat_map(|revision| {
+            EXPECTED_EXTENSIONS.map(|extension| expected_extension(revision.as_deref(), extension))
+        }) {
+            let blessed = read_if_exists(&file.with_extension(&extension))?;
+            let base_path = original.with_extension(&extension);
+            let override_path = original.with_extension(format!("{extension}.{suffix}"));
+            let base = read_if_exists(&base_path)?;
+
+            let (path, contents) = if blessed == base {
+                if override_path.exists() {
+                    remove_file(&override_path).with_context(|| {
+                        format!("Could not remove `{}`", override_path.display())
+                    })?;
+                }
+                continue;
+            } else if base.is_none() && !override_path.exists() {
+                (base_path, blessed.unwrap_or_default())
+            } else {
+                // An empty override records that the output is empty, which a missing file could
+                // not do, because the base file would be used instead.
+                (override_path, blessed.unwrap_or_default())
+            };
+            write(&path, contents)
+                .with_context(|| format!("Could not write `{}`", path.display()))?;
+        }
+    }
+    Ok(())
+}
+
+fn read_if_exists(path: &Path) -> Result<Option<Vec<u8>>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    read(path)
+        .map(Some)
+        .with_context(|| format!("Could not read `{}`", path.display()))
+}
+
+/// Builds the ui_test config shared by every run: the driver command, its flags and environment,
+/// the expected exit status, and output filters.
+fn base_config(driver: &Driver, src_base: &Path, config: &ui::Config) -> ui_test::Config {
     // Build ui_test config starting from rustc defaults
     let mut cfg = ui_test::Config::rustc(src_base);
 
+    if let Some(target_dir) = &config.target_dir {
+        cfg.out_dir = target_dir.join("ui");
+    }
+
     // Program: overwrite only the binary path to the dylint driver and extend args
-    cfg.program.program = driver.to_path_buf();
-    // Required flags for diagnostics
-    for arg in ["-Dwarnings", "--emit=metadata"] {
-        cfg.program.args.push(OsString::from(arg));
+    cfg.program.program.clone_from(&driver.path);
+    // With a timeout, the driver is run by a wrapper that kills it when the time is up. The
+    // wrapper's arguments precede ui_test's own.
+    if let (Some(timeout), Some(wrapper)) = (config.timeout, &config.timeout_wrapper) {
+        cfg.program.program.clone_from(wrapper);
+        cfg.program.args.splice(
+            0..0,
+            [
+                OsString::from(timeout.as_millis().to_string()),
+                driver.path.clone().into_os_string(),
+            ],
+        );
+    }
+    // The diagnostics wrapper wraps whatever runs the driver, including the timeout wrapper.
+    if let (Some(wrapper), Some(options)) = (
+        &config.diagnostics_wrapper,
+        diagnostics_wrapper::options(config, true),
+    ) {
+        let program = std::mem::replace(&mut cfg.program.program, wrapper.clone());
+        cfg.program
+            .args
+            .splice(0..0, [OsString::from(options), program.into_os_string()]);
+    }
+    if let Some(wrap_command) = &config.wrap_command {
+        let mut command = Command::new(&cfg.program.program);
+        command.args(&cfg.program.args);
+        wrap_command(&mut command);
+        cfg.program.program = command.get_program().into();
+        cfg.program.args = command.get_args().map(ToOwned::to_owned).collect();
+        cfg.program.envs.extend(
+            command
+                .get_envs()
+                .map(|(key, val)| (key.to_owned(), val.map(ToOwned::to_owned))),
+        );
+    }
+    cfg.program
+        .args
+        .extend(default_args(config).into_iter().map(OsString::from));
+    // ui_test reads the rendered diagnostics from rustc's JSON output, which is uncolored unless
+    // asked otherwise.
+    if config.color == ui::ColorPolicy::Always {
+        cfg.program
+            .args
+            .push(OsString::from("--json=diagnostic-rendered-ansi"));
     }
     // User-provided rustc flags (and example linking flags already merged upstream)
     for arg in &config.rustc_flags {
+        // An edition under test replaces the one the example target was built with.
+        if config.edition.is_some() && arg.starts_with("--edition=") {
+            continue;
+        }
         cfg.program.args.push(OsString::from(arg));
     }
+    if let Some(edition) = &config.edition {
+        cfg.comment_defaults.base().edition =
+            ui_test::spanned::Spanned::dummy(edition.clone()).into();
+    }
 
     // Ensure our temporary test files are not filtered out by ui_test's CLI filters.
     // ui_test will call `with_args(Args::test())` internally and append filter strings
     // derived from `cargo test` CLI; those often do not match our temp file paths.
     // Adding the `src_base` directory as a filter guarantees every file beneath it
     // matches `default_any_file_filter` (substring match when `filter_exact` is false).
-    cfg.filter_files.push(src_base.display().to_string());
-
-    // Propagate relevant env vars to the driver
-    for key in [
-        env::DYLINT_LIBS,
-        env::CLIPPY_DISABLE_DOCS_LINKS,
-        env::DYLINT_TOML,
-        // Forward debugging aids so compiler ICEs/errors are actionable under the harness
-        env::RUST_BACKTRACE,
-        env::RUST_LOG,
-    ] {
-        let val = std::env::var_os(key);
+    // When a single file is selected, only that file is matched.
+    match &config.only_file {
+        Some(file) => cfg
+            .filter_files
+            .push(src_base.join(file).display().to_string()),
+        None => cfg.filter_files.push(src_base.display().to_string()),
+    }
+    cfg.bless_command = Some(env::bless_command(config));
+
+    // Expanded sources are snapshots, not tests.
+    cfg.skip_files.push(format!(".{}", expanded::EXTENSION));
+    // Files unchanged since they last passed (see `run_incrementally`) and known bugs (see
+    // `check_known_bugs`) are not run with the others.
+    cfg.skip_files.extend(
+        config
+            .excluded_files
+            .iter()
+            .map(|file| src_base.join(file).display().to_string()),
+    );
+
+    // Removals come first, so that the variables set below are not removed.
+    for key in env::cleared_vars(config, false) {
+        cfg.program.envs.push((key, None));
+    }
+    for (key, val) in env::hermetic_vars(config) {
         cfg.program
             .envs
-            .push((OsString::from(key), val.map(Into::into)));
+            .push((OsString::from(key), Some(val.into_os_string())));
     }
 
-    let bless = is_env_truthy(env::BLESS);
+    // Attach the driver's environment to the command only. The current process's environment is
+    // never modified, so concurrent tests cannot observe each other's settings.
+    for (key, val) in &driver.envs {
+        cfg.program
+            .envs
+            .push((OsString::from(key), Some(OsString::from(val))));
+    }
+    cfg.program.envs.push((
+        OsString::from(env::DYLINT_TOML),
+        config.dylint_toml.as_ref().map(OsString::from),
+    ));
+    for (key, val) in &config.envs {
+        cfg.program
+            .envs
+            .push((OsString::from(key), Some(OsString::from(val))));
+    }
 
-    // Align expected exit status with the selected program.
-    // rustc normally exits 1 on error; dylint-driver defaults to 101 (configurable).
-    let is_dylint_driver = driver
-        .file_name()
-        .and_then(|s| s.to_str())
-        .map(|s| s.contains("dylint-driver"))
-        .unwrap_or(false);
-    let expected_exit: i32 = if is_dylint_driver {
-        config.expected_exit_status
+    // Forward debugging aids so compiler ICEs/errors are actionable under the harness
+    for key in [env::RUST_BACKTRACE, env::RUST_LOG] {
+        let val = std::env::var_os(key);
+        cfg.program.envs.push((OsString::from(key), val));
+    }
+
+    // Align expected exit status with the selected program and mode. A file's `//@exit-status:`
+    // header overrides it.
+    cfg.comment_defaults.base().exit_status =
+        ui_test::spanned::Spanned::dummy(default_exit_status(driver, config)).into();
+    cfg.comment_defaults.base().require_annotations =
+        ui_test::spanned::Spanned::dummy(config.require_annotations).into();
+    match config.mode {
+        ui::Mode::Fail { .. } | ui::Mode::BuildFail | ui::Mode::CheckPass => {}
+        ui::Mode::RunPass => {
+            cfg.comment_defaults.base().require_annotations =
+                ui_test::spanned::Spanned::dummy(false).into();
+            // A binary built for another target generally cannot be run.
+            if config.target.is_none() {
+                cfg.comment_defaults.base().set_custom(
+                    "run",
+                    Run {
+                        exit_code: 0,
+                        output_conflict_handling: None,
+                    },
+                );
+            }
+        }
+    }
+
+    // Dependencies are built with Cargo and passed to the driver with `--extern`.
+    if let Some(manifest_path) = &config.dependencies_manifest {
+        let mut builder = DependencyBuilder {
+            crate_manifest_path: manifest_path.clone(),
+            ..DependencyBuilder::default()
+        };
+        if let Some(toolchain) = &config.toolchain {
+            builder
+                .program
+                .envs
+                .push((env::RUSTUP_TOOLCHAIN.into(), Some(toolchain.into())));
+        }
+        if config.offline {
+            builder.program.args.push("--offline".into());
+        }
+        cfg.comment_defaults
+            .base()
+            .set_custom("dependencies", builder);
+    }
+
+    // Auxiliary crates are built with plain rustc before the run (see `aux::build`), rather than
+    // with the driver, as ui_test would build them.
+    cfg.custom_comments
+        .insert("aux-build", |_parser, _args, _span| {});
+
+    // Known bugs are run separately (see `check_known_bugs`).
+    cfg.custom_comments
+        .insert("known-bug", |_parser, _args, _span| {});
+
+    // Files excluded by `//@only-<name>`, `//@ignore-<name>`, and `//@min-rust-version:` headers
+    // are skipped before the run (see `skipped_files`), so the headers need no effect here.
+    for &name in platform::HEADERS.iter() {
+        cfg.custom_comments.insert(name, |_parser, _args, _span| {});
+    }
+    cfg.custom_comments
+        .insert("min-rust-version", |_parser, _args, _span| {});
+
+    // A file's MSRV is passed like the one set with `Test::msrv` (see `diagnostics::header_msrv`).
+    cfg.custom_comments.insert("msrv", |parser, args, _span| {
+        parser.env_vars.push((
+            env::CARGO_PKG_RUST_VERSION.to_owned(),
+            args.trim().to_owned(),
+        ));
+    });
+
+    // ui_test understands `//@check-pass` and `//@run` itself. These headers are added for parity
+    // with `compiletest`. Since both need codegen, `--emit=link` is added to the file's flags; rustc
+    // accumulates repeated `--emit` flags.
+    cfg.custom_comments
+        .insert("build-fail", |parser, _args, _span| {
+            parser.compile_flags.push("--emit=link".to_owned());
+        });
+    // A cross-compiled binary cannot be run on the host, so it is only built.
+    let parse_run_pass: ui_test::CommandParserFunc = if config.target.is_some() {
+        build_pass
     } else {
-        1
+        run_pass
     };
-    cfg.comment_defaults.base().exit_status =
-        ui_test::spanned::Spanned::<i32>::dummy(expected_exit).into();
-    debug!(
-        "run_tests: BLESS environment variable = {}",
-        std::env::var("BLESS").unwrap_or_else(|_| "unset".to_string())
-    );
-    debug!("run_tests: is_env_truthy(BLESS) = {}", bless);
-    debug!("run_tests: src_base = {}", src_base.display());
-    debug!("run_tests: driver = {}", driver.display());
+    cfg.custom_comments.insert("run-pass", parse_run_pass);
 
     // Normalize noisy driver debug lines on stderr for stable diffs.
     // Example: "[2025-..Z DEBUG dylint_driver] [\"rustc\", ...]"
     cfg.stderr_filter(r"(?m)^\[[^\]]+\s+DEBUG\s+dylint_driver\].*\n", b"");
 
+    // An explicit level takes precedence over the one implied by the level filter.
+    let require_annotations_for_level = config.require_annotations_for_level.or_else(|| {
+        (config.level_filter != ui::LevelFilter::ALL)
+            .then(|| config.level_filter.lowest())
+            .flatten()
+    });
+    if let Some(level) = require_annotations_for_level {
+        let level = match level {
+            ui::Level::Help => ui_test::diagnostics::Level::Help,
+            ui::Level::Note => ui_test::diagnostics::Level::Note,
+            ui::Level::Warning => ui_test::diagnostics::Level::Warn,
+            ui::Level::Error => ui_test::diagnostics::Level::Error,
+        };
+        cfg.comment_defaults.base().require_annotations_for_level =
+            ui_test::spanned::Spanned::dummy(level).into();
+    }
+
+    // Remove diagnostics at excluded levels: top-level diagnostics and sub-diagnostics with spans,
+    // which start at the beginning of a line and continue with indented or numbered lines, and
+    // sub-diagnostics without spans, e.g., `= note: ...`.
+    for level in config.level_filter.excluded() {
+        let name = level.name();
+        cfg.stderr_filter(
+            &format!(r"(?m)^{name}(?:\[[^\]]*\])?: .*\n(?:[ \t0-9L].*\n)*"),
+            b"",
+        );
+        cfg.stderr_filter(&format!(r"(?m)^[ \t]*= {name}: .*\n"), b"");
+    }
+
+    // The user's filters apply after the built-in ones, so that they can match the normalized
+    // phrasing.
+    if config.normalize_rustc_noise {
+        for (pattern, replacement) in noise::FILTERS {
+            cfg.stderr_filter(pattern, *replacement);
+        }
+    }
+    for (pattern, replacement) in &config.stderr_filters {
+        cfg.stderr_filter(pattern, *replacement);
+    }
+    for (pattern, replacement) in &config.stdout_filters {
+        cfg.stdout_filter(pattern, *replacement);
+    }
+
+    // Lints may print machine-readable output to stdout. It is compared against `.stdout` files
+    // like stderr is against `.stderr` files, unless the user opted out.
+    if !config.check_stdout {
+        cfg.stdout_filter(r"(?s).+", b"");
+    }
+
+    // In JSON mode, diagnostics are snapshotted in `.json` files instead of `.stderr` files.
+    // Annotations are still matched, because ui_test extracts diagnostics before filtering.
+    if config.diagnostics_format == ui::DiagnosticsFormat::Json {
+        cfg.stderr_filter(r"(?s).+", b"");
+    }
+
+    cfg
+}
+
+/// Handles `//@run-pass`: the file is built and run, and must exit successfully.
+fn run_pass(
+    parser: &mut ui_test::CommentParser<&mut ui_test::Revisioned>,
+    args: ui_test::spanned::Spanned<&str>,
+    span: ui_test::spanned::Span,
+) {
+    build_pass(parser, args, span.clone());
+    let run = Run {
+        exit_code: 0,
+        output_conflict_handling: None,
+    };
+    parser.set_custom_once("run", run, span);
+}
+
+/// Handles `//@run-pass` when cross-compiling: the file is built but not run.
+fn build_pass(
+    parser: &mut ui_test::CommentParser<&mut ui_test::Revisioned>,
+    _args: ui_test::spanned::Spanned<&str>,
+    span: ui_test::spanned::Span,
+) {
+    use ui_test::spanned::Spanned;
+    parser.exit_status = Spanned::new(0, span.clone()).into();
+    parser.require_annotations = Spanned::new(false, span).into();
+    parser.compile_flags.push("--emit=link".to_owned());
+}
+
+/// Returns the flags passed to the driver before any others: `-Dwarnings` unless disabled,
+/// `--color`, and `--emit`. Modes that need codegen emit more than metadata, unless the fixtures are
+/// `no_std`.
+pub(crate) fn default_args(config: &ui::Config) -> Vec<String> {
+    let mut args = Vec::new();
+    if config.deny_warnings {
+        args.push("-Dwarnings".to_owned());
+    }
+    match config.color {
+        ui::ColorPolicy::Never => args.push("--color=never".to_owned()),
+        ui::ColorPolicy::Always => args.push("--color=always".to_owned()),
+        ui::ColorPolicy::Auto => {}
+    }
+    let emit = config.emit.as_deref().unwrap_or(match config.mode {
+        _ if config.no_std => "metadata",
+        ui::Mode::Fail { .. } | ui::Mode::CheckPass => "metadata",
+        ui::Mode::BuildFail | ui::Mode::RunPass => "link",
+    });
+    args.push(format!("--emit={emit}"));
+    // A `no_std` library needs no `main` function, panic handler, or unwinding support.
+    if config.no_std {
+        args.extend(["--crate-type=lib".to_owned(), "-Cpanic=abort".to_owned()]);
+    }
+    args
+}
+
+fn run_with_bless(mut cfg: ui_test::Config, bless: bool) -> Result<()> {
     if bless {
         debug!("run_tests: Running two-pass blessing approach");
         // Two-pass approach for blessing as documented:
@@ -92,7 +1307,6 @@ pub(crate) fn run_tests(driver: &Path, src_base: &Path, config: &ui::Config) ->
         // Pass 1: Verify without creating/updating expected files
         debug!("run_tests: Pass 1 - Verification (ignore_output_conflict)");
         cfg.output_conflict_handling = ui_test::ignore_output_conflict;
-        cfg.bless_command = Some(format!("{}=1 cargo test", env::BLESS));
 
         let verify_result = ui_test::run_tests(cfg.clone());
         debug!("run_tests: Pass 1 result = {:?}", verify_result);
@@ -114,7 +1328,6 @@ pub(crate) fn run_tests(driver: &Path, src_base: &Path, config: &ui::Config) ->
     } else {
         debug!("run_tests: Running non-blessing mode (error_on_output_conflict)");
         // Non-blessing mode: verify annotations and error on conflicts
-        cfg.bless_command = Some(format!("{}=1 cargo test", env::BLESS));
         cfg.output_conflict_handling = ui_test::error_on_output_conflict;
         let result = ui_test::run_tests(cfg);
         debug!("run_tests: Non-blessing result = {:?}", result);
@@ -122,14 +1335,306 @@ pub(crate) fn run_tests(driver: &Path, src_base: &Path, config: &ui::Config) ->
     }
 }
 
+/// Re-runs the driver on each `.fixed` file beneath `src_base`, requiring that the fixed code
+/// produce no diagnostics and exit successfully.
+///
+/// Annotations are stripped from the fixed code first, so that any remaining diagnostic is
+/// unexpected.
+fn verify_fixes(driver: &Driver, src_base: &Path, config: &ui::Config) -> Result<()> {
+    let tempdir = tempfile::tempdir().with_context(|| "`tempdir` failed")?;
+
+    let sources = source_files(src_base, config)?;
+    let fixed_files = files_with_extension(src_base, "fixed")?
+        .into_iter()
+        .filter(|fixed| {
+            sources
+                .iter()
+                .any(|source| is_expected_file_of(fixed, source))
+        })
+        .collect::<Vec<_>>();
+    debug!("verify_fixes: Found {} `.fixed` files", fixed_files.len());
+    if fixed_files.is_empty() {
+        return Ok(());
+    }
+
+    for fixed in fixed_files {
+        let contents = read_to_string(&fixed)
+            .with_context(|| format!("Could not read `{}`", fixed.display()))?;
+        let to = tempdir
+            .path()
+            .join(fixed.strip_prefix(src_base)?)
+            .with_extension("rs");
+        if let Some(parent) = to.parent() {
+            create_dir_all(parent)
+                .with_context(|| format!("`create_dir_all` failed for `{}`", parent.display()))?;
+        }
+        let contents = ANNOTATION_RE.replace_all(&contents, "");
+        write(&to, strip_revisions(&contents, &fixed).as_bytes())
+            .with_context(|| format!("Could not write `{}`", to.display()))?;
+    }
+
+    // The temporary directory holds only the selected files, whose names differ from the sources'.
+    let config = ui::Config {
+        only_file: None,
+        ..config.clone()
+    };
+    let mut cfg = base_config(driver, tempdir.path(), &config);
+    cfg.comment_defaults.base().exit_status = ui_test::spanned::Spanned::dummy(0).into();
+    cfg.comment_defaults.base().require_annotations = ui_test::spanned::Spanned::dummy(true).into();
+    cfg.comment_defaults
+        .base()
+        .set_custom("rustfix", RustfixMode::Disabled);
+    cfg.output_conflict_handling = ui_test::ignore_output_conflict;
+    ui_test::run_tests(cfg).map_err(|err| anyhow!("fixed code still produces diagnostics: {err}"))
+}
+
+/// Returns the code fixed for one revision as an ordinary, revision-less file.
+///
+/// ui_test names such a file `foo.rev.fixed`, and it retains the source's `//@revisions:` header.
+/// The header is removed, and headers specific to the revision (`//@[rev] ...`) are made
+/// unconditional, so that the file is compiled as the revision was.
+fn strip_revisions(contents: &str, fixed: &Path) -> String {
+    let revisions = revisions(contents);
+    let Some(revision) = fixed
+        .file_stem()
+        .map(Path::new)
+        .and_then(Path::extension)
+        .and_then(|extension| extension.to_str())
+        .filter(|extension| revisions.iter().any(|revision| revision == extension))
+    else {
+        return contents.to_owned();
+    };
+
+    let mut stripped = String::new();
+    for line in contents.split_inclusive('\n') {
+        if line.trim_start().starts_with("//@revisions:") {
+            continue;
+        }
+        if line.trim_start().starts_with("//@[") {
+            if let Some(header) = header(line, Some(revision)) {
+                stripped.push_str("//@");
+                stripped.push_str(header);
+            }
+            continue;
+        }
+        stripped.push_str(line);
+    }
+    stripped
+}
+
+/// Returns the source files beneath `src_base` to test, i.e., all of them, or only the file
+/// selected by the config, less those excluded by the bless filter or skipped (see
+/// [`skip_reason`]).
+pub(crate) fn source_files(src_base: &Path, config: &ui::Config) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for file in candidate_files(src_base, config)? {
+        if skip_reason(&file, config)?.is_none() {
+            files.push(file);
+        }
+    }
+    Ok(files)
+}
+
+/// Returns the source files that would be tested but for being skipped, along with the reason.
+pub(crate) fn skipped_files(
+    src_base: &Path,
+    config: &ui::Config,
+) -> Result<Vec<(PathBuf, String)>> {
+    let mut skipped = Vec::new();
+    for file in candidate_files(src_base, config)? {
+        if let Some(reason) = skip_reason(&file, config)? {
+            skipped.push((file, reason));
+        }
+    }
+    Ok(skipped)
+}
+
+/// Returns why `file` is skipped, if it is: an `//@only-<name>` or `//@ignore-<name>` header
+/// excluding the target (or host) platform, or a minimum Rust version the toolchain does not meet.
+/// An unreadable file is not skipped, so that ui_test reports the error.
+pub(crate) fn skip_reason(file: &Path, config: &ui::Config) -> Result<Option<String>> {
+    let Ok(source) = read_to_string(file) else {
+        return Ok(None);
+    };
+    if let Some(header) = platform::skip_reason(&source, config.target.as_deref()) {
+        return Ok(Some(header));
+    }
+    rust_version::skip_reason(&source, config).with_context(|| {
+        format!(
+            "Could not check the minimum Rust version of `{}`",
+            file.display()
+        )
+    })
+}
+
+fn candidate_files(src_base: &Path, config: &ui::Config) -> Result<Vec<PathBuf>> {
+    let is_excluded = |file: &Path| {
+        file.strip_prefix(src_base).is_ok_and(|relative| {
+            config
+                .excluded_files
+                .iter()
+                .any(|excluded| excluded == relative)
+        })
+    };
+    let files = match &config.only_file {
+        Some(file) => vec![src_base.join(file)],
+        None => files_with_extension(src_base, "rs")?
+            .into_iter()
+            .filter(|file| !aux::is_aux(src_base, file))
+            .collect(),
+    };
+    let files = files
+        .into_iter()
+        .filter(|file| !is_excluded(file))
+        .collect::<Vec<_>>();
+    let Some(pattern) = bless_filter(config) else {
+        return Ok(files);
+    };
+    Ok(files
+        .into_iter()
+        .filter(|file| {
+            file.strip_prefix(src_base)
+                .is_ok_and(|relative| glob::matches(&pattern, relative))
+        })
+        .collect())
+}
+
+/// Returns the glob selecting the files to bless, if blessing and a filter is set.
+fn bless_filter(config: &ui::Config) -> Option<String> {
+    if !env::is_blessing(config) {
+        return None;
+    }
+    config
+        .bless_filter
+        .clone()
+        .or_else(|| std::env::var(env::BLESS_FILTER).ok())
+        .filter(|pattern| !pattern.is_empty())
+}
+
+/// Returns true if `file` is beneath a directory named `clean` within `src_base`.
+fn is_clean(src_base: &Path, file: &Path) -> bool {
+    file.strip_prefix(src_base).is_ok_and(|relative| {
+        relative.parent().is_some_and(|parent| {
+            parent
+                .components()
+                .any(|component| component.as_os_str() == "clean")
+        })
+    })
+}
+
+/// Returns true if `path` is one of `source`'s expected files, e.g., `foo.stderr` or
+/// `foo.rev.fixed` for `foo.rs`.
+fn is_expected_file_of(path: &Path, source: &Path) -> bool {
+    let (Some(stem), Some(file_name)) = (source.file_stem(), path.file_name()) else {
+        return false;
+    };
+    path.parent() == source.parent()
+        && file_name
+            .to_string_lossy()
+            .starts_with(&format!("{}.", stem.to_string_lossy()))
+}
+
+/// Shuffles `items` deterministically for `seed`, using a Fisher-Yates shuffle driven by
+/// SplitMix64.
+pub(crate) fn shuffle<T>(items: &mut [T], seed: u64) {
+    let mut state = seed;
+    let mut next = || {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    };
+    for i in (1..items.len()).rev() {
+        let j = (next() % (i as u64 + 1)) as usize;
+        items.swap(i, j);
+    }
+}
+
+/// Returns the files beneath `dir` with `extension`, other than expected files (e.g.,
+/// `foo.expanded.rs` is not a source file).
+pub(crate) fn files_with_extension(dir: &Path, extension: &str) -> Result<Vec<PathBuf>> {
+    Ok(files(dir)?
+        .into_iter()
+        .filter(|path| {
+            path.extension().is_some_and(|ext| ext == extension) && !is_expected_file(path)
+        })
+        .collect())
+}
+
+/// Recursively collects the files beneath `dir`, in sorted order.
+pub(crate) fn files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in
+        read_dir(dir).with_context(|| format!("`read_dir` failed for `{}`", dir.display()))?
+    {
+        let entry = entry.with_context(|| format!("`read_dir` failed for `{}`", dir.display()))?;
+        let path = entry.path();
+        if path.is_dir() {
+            files.extend(self::files(&path)?);
+        } else {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Extensions of a fixture's expected files, including those of its revisions and the overrides
+/// for the configured toolchain and editions.
+fn expected_extensions(config: &ui::Config, revisions: &[Option<String>]) -> Vec<String> {
+    let suffixes = config
+        .edition
+        .iter()
+        .chain(&config.editions)
+        .map(|edition| format!("e{edition}"))
+        .chain(config.toolchain.clone())
+        .collect::<Vec<_>>();
+    let base = revisions
+        .iter()
+        .flat_map(|revision| {
+            EXPECTED_EXTENSIONS.map(|extension| expected_extension(revision.as_deref(), extension))
+        })
+        .collect::<Vec<_>>();
+    let mut extensions = base.clone();
+    for suffix in suffixes {
+        extensions.extend(base.iter().map(|extension| format!("{extension}.{suffix}")));
+    }
+    extensions
+}
+
 pub fn run_example_test(
-    driver: &Path,
+    driver: &Driver,
     metadata: &Metadata,
     package: &Package,
     target: &Target,
     config: &ui::Config,
 ) -> Result<()> {
-    let linking_flags = linking_flags(metadata, package, target)?;
+    // A target's required features are enabled when it is built. A feature the package does not
+    // declare cannot be, so the target is skipped rather than failing to build.
+    let unsatisfiable = unsatisfiable_features(package, target);
+    if !unsatisfiable.is_empty() {
+        eprintln!(
+            "Skipping `{}`, because `{}` does not declare its required features: {}",
+            target.name,
+            package.name,
+            unsatisfiable.join(", ")
+        );
+        return Ok(());
+    }
+    if let Some(reason) = skip_reason(target.src_path.as_std_path(), config)? {
+        eprintln!("Skipping `{}` ({reason})", target.name);
+        return Ok(());
+    }
+
+    let dir = target
+        .src_path
+        .parent()
+        .ok_or_else(|| anyhow!("Could not get parent of `{}`", target.src_path))?;
+    if bless_output_dir(config).is_none() {
+        guard_bless(dir.as_std_path(), config)?;
+    }
+    let linking = linking(metadata, package, target, config)?;
     let file_name = target
         .src_path
         .file_name()
@@ -139,39 +1644,93 @@ pub fn run_example_test(
     let src_base = tempdir.path();
     let to = src_base.join(file_name);
 
-    copy(&target.src_path, &to).with_context(|| {
-        format!(
-            "Could not copy `{}` to `{}`",
-            target.src_path,
-            to.to_string_lossy()
-        )
-    })?;
-    for extension in ["fixed", "stderr", "stdout"] {
-        copy_with_extension(&target.src_path, &to, extension)
-            .map(|_| ())
-            .unwrap_or_default();
-    }
+    // Copy the example's whole directory, so that its `mod` files and `include!`d assets are found
+    // at the same relative paths. Only the example itself is tested.
+    copy_files(dir.as_std_path(), src_base)?;
+    copy_aux_dirs(src_base, config)?;
+    let extensions = expected_extensions(config, &file_revisions(target.src_path.as_std_path())?);
 
     let mut config = config.clone();
-    config.rustc_flags.extend(linking_flags.iter().cloned());
+    config.aux_dirs.clear();
+    config.rustc_flags.extend(linking.flags.iter().cloned());
+    // The build script's variables come first, so that a value passed to `env` takes precedence.
+    config.envs.splice(0..0, linking.envs.iter().cloned());
+    config.only_file = Some(PathBuf::from(file_name));
 
-    run_tests(driver, src_base, &config)
-}
+    // Blessing is previewed below, when the blessed files would be copied back.
+    if let Err(error) = run_editions(driver, src_base, &config) {
+        if config.keep_tmp || is_env_truthy(env::DYLINT_TESTING_KEEP_TMP) {
+            let kept = tempdir.keep();
+            return Err(error.context(format!("Kept temporary directory `{}`", kept.display())));
+        }
+        return Err(error);
+    }
 
-fn copy_with_extension<P: AsRef<Path>, Q: AsRef<Path>>(
-    from: P,
-    to: Q,
-    extension: &str,
-) -> Result<u64> {
-    let from = from.as_ref().with_extension(extension);
-    let to = to.as_ref().with_extension(extension);
-    copy(from, to).map_err(Into::into)
+    // Blessed files were written to the temporary directory. Copy them back next to the example,
+    // and remove expected files that ui_test removed because the output became empty.
+    let bless_annotations = is_env_truthy(env::BLESS_ANNOTATIONS);
+    let output_dir = bless_output_dir(&config);
+    let new_only = env::is_bless_new_only(&config);
+    if env::is_bless_dry_run(&config) || output_dir.is_some() || new_only {
+        let mut changes = Vec::new();
+        if bless_annotations {
+            changes.push((
+                target.src_path.clone().into_std_path_buf(),
+                read_if_exists(target.src_path.as_std_path())?,
+                read_if_exists(&to)?,
+            ));
+        }
+        for extension in &extensions {
+            let expected = target
+                .src_path
+                .with_extension(extension)
+                .into_std_path_buf();
+            let blessed = read_if_exists(&to.with_extension(extension))?;
+            changes.push((expected.clone(), read_if_exists(&expected)?, blessed));
+        }
+        match output_dir {
+            Some(output_dir) => {
+                if new_only {
+                    changes.retain(|(_, old, _)| old.is_none());
+                }
+                write_bless_output(&changes, output_dir)?;
+            }
+            None if new_only => write_new_files(&changes, &config)?,
+            None => preview_bless(&changes, &config),
+        }
+        return Ok(());
+    }
+    if bless_annotations {
+        if read_if_exists(&to)? != read_if_exists(target.src_path.as_std_path())? {
+            record_blessed(target.src_path.as_std_path());
+        }
+        copy(&to, &target.src_path).with_context(|| {
+            format!("Could not copy `{}` to `{}`", to.display(), target.src_path)
+        })?;
+    }
+    if bless_annotations || env::is_bless_truthy(&config) {
+        for extension in &extensions {
+            let blessed = to.with_extension(extension);
+            let expected = target.src_path.with_extension(extension);
+            if read_if_exists(&blessed)? != read_if_exists(expected.as_std_path())? {
+                record_blessed(expected.as_std_path());
+            }
+            if blessed.exists() {
+                copy(&blessed, &expected).with_context(|| {
+                    format!("Could not copy `{}` to `{expected}`", blessed.display())
+                })?;
+            } else if expected.exists() {
+                remove_file(&expected).with_context(|| format!("Could not remove `{expected}`"))?;
+            }
+        }
+    }
+
+    Ok(())
 }
 
 #[cfg(test)]
 mod gating_tests {
     use super::*;
-    use std::path::PathBuf;
 
     fn write_file(dir: &Path, name: &str, contents: &str) -> PathBuf {
         let path = dir.join(name);
@@ -197,17 +1756,20 @@ mod gating_tests {
             std::fs::read_to_string(&file).unwrap()
         );
 
-        // Build minimal config
-        let config = ui::Config::default();
-
-        // Set BLESS=1 to simulate user blessing
-        unsafe { std::env::set_var("BLESS", "1") };
-        debug!("🧪 Set BLESS=1");
+        // Build minimal config, simulating BLESS=1 without changing the process environment
+        let config = ui::Config {
+            bless_value: Some("1".to_owned()),
+            ..ui::Config::default()
+        };
 
         // Test the run_tests function directly with plain rustc - no dylint driver needed!
         debug!("🧪 About to call run_tests with rustc...");
-        let rustc_path = std::path::Path::new("rustc");
-        let result = run_tests(rustc_path, tmp.path(), &config);
+        let rustc = Driver {
+            path: PathBuf::from("rustc"),
+            library: PathBuf::new(),
+            envs: Vec::new(),
+        };
+        let result = run_tests(&rustc, tmp.path(), &config);
         debug!("🧪 run_tests returned: {:?}", result);
 
         let stderr_path = file.with_extension("stderr");
@@ -237,3 +1799,434 @@ mod gating_tests {
         debug!("🧪 All assertions passed!");
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn overrides_apply_to_expected_files_only() {
+        let suffix = "nightly-2025-01-01";
+        assert_eq!(
+            override_base(Path::new("ui/foo.stderr.nightly-2025-01-01"), suffix),
+            Some(PathBuf::from("ui/foo.stderr"))
+        );
+        assert_eq!(
+            override_base(Path::new("ui/foo.stderr.stable"), suffix),
+            None
+        );
+        assert_eq!(
+            override_base(Path::new("ui/foo.txt.nightly-2025-01-01"), suffix),
+            None
+        );
+    }
+
+    #[test]
+    fn known_bugs_are_marked_by_header_or_pattern() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let src_base = tempdir.path();
+        let (marked, listed, plain) = (
+            src_base.join("marked.rs"),
+            src_base.join("listed.rs"),
+            src_base.join("plain.rs"),
+        );
+        write(&marked, "//@known-bug: #123\nfn main() {}\n").unwrap();
+        write(&listed, "fn main() {}\n").unwrap();
+        write(&plain, "// known-bug\nfn main() {}\n").unwrap();
+        let config = ui::Config {
+            expected_failures: vec!["listed*".to_owned()],
+            ..ui::Config::default()
+        };
+        assert!(is_known_bug(src_base, &marked, &config).unwrap());
+        assert!(is_known_bug(src_base, &listed, &config).unwrap());
+        assert!(!is_known_bug(src_base, &plain, &config).unwrap());
+    }
+
+    #[test]
+    fn known_bugs_that_pass_are_reported() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let src_base = tempdir.path();
+        write(src_base.join("pass.rs"), "fn main() {}\n").unwrap();
+        // The lint now fires as annotated, though no expected file has been blessed.
+        write(
+            src_base.join("fixed.rs"),
+            "//@known-bug: #1\nfn main() {\n    let x = 1; //~ WARN: unused variable\n}\n",
+        )
+        .unwrap();
+        write(
+            src_base.join("still.rs"),
+            "//@known-bug: #2\nfn main() {} //~ ERROR: never emitted\n",
+        )
+        .unwrap();
+        let config = ui::Config {
+            bless_vars: vec!["KNOWN_BUG_TEST_BLESS".to_owned()],
+            ..ui::Config::default()
+        };
+
+        let error = format!("{:?}", run_tests(&rustc(), src_base, &config).unwrap_err());
+        assert!(
+            error.contains("Files expected to fail now pass:\n    fixed.rs\n"),
+            "{error}"
+        );
+        assert!(!error.contains("still.rs"), "{error}");
+    }
+
+    #[test]
+    fn aux_dirs_are_copied_under_their_names() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let data = tempdir.path().join("data");
+        create_dir_all(data.join("nested")).unwrap();
+        write(data.join("foo.json"), "{}").unwrap();
+        write(data.join("nested/bar.txt"), "bar").unwrap();
+        let dir = tempdir.path().join("ui");
+        let config = ui::Config {
+            aux_dirs: vec![data],
+            ..ui::Config::default()
+        };
+        assert_eq!(
+            copy_aux_dirs(&dir, &config).unwrap(),
+            [
+                PathBuf::from("data/foo.json"),
+                PathBuf::from("data/nested/bar.txt")
+            ]
+        );
+        assert_eq!(
+            read_to_string(dir.join("data/nested/bar.txt")).unwrap(),
+            "bar"
+        );
+        assert!(copy_aux_dirs(&dir, &config).is_err());
+    }
+
+    /// Writes `ui/foo.rs`, which includes `data/foo.txt` from an aux dir, beneath `dir`, and returns
+    /// the source directory and a config that copies the aux dir next to it.
+    fn aux_dir_fixture(dir: &Path, source: &str) -> (PathBuf, ui::Config) {
+        let data = dir.join("data");
+        let src_base = dir.join("ui");
+        create_dir_all(&data).unwrap();
+        create_dir_all(&src_base).unwrap();
+        write(data.join("foo.txt"), "foo").unwrap();
+        write(src_base.join("foo.rs"), source).unwrap();
+        let config = ui::Config {
+            aux_dirs: vec![data],
+            ..ui::Config::default()
+        };
+        (src_base, config)
+    }
+
+    const AUX_DIR_WARNING: &str =
+        "fn main() {\n    let x = include_str!(\"data/foo.txt\"); //~ WARN: unused variable\n}\n";
+
+    fn rustc() -> Driver {
+        Driver {
+            path: PathBuf::from("rustc"),
+            library: PathBuf::new(),
+            envs: Vec::new(),
+        }
+    }
+
+    // `bless_output_dir`, dry runs, and new-only blessing all write or report the changes found by
+    // `bless_overlay`.
+    #[test]
+    fn aux_dirs_are_blessed_to_output_dir_by_real_path() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let (src_base, mut config) = aux_dir_fixture(tempdir.path(), AUX_DIR_WARNING);
+        config.bless_output_dir = Some(tempdir.path().join("out"));
+        config.bless_value = Some("1".to_owned());
+
+        let changes = bless_overlay(&rustc(), &src_base, &config).unwrap();
+
+        let changed = changes
+            .iter()
+            .filter(|(_, old, new)| old != new)
+            .map(|(path, old, _)| (path.clone(), old.is_none()))
+            .collect::<Vec<_>>();
+        assert_eq!(changed, [(src_base.join("foo.stderr"), true)]);
+        assert!(
+            changes
+                .iter()
+                .all(|(path, _, _)| path.starts_with(&src_base))
+        );
+        assert!(!src_base.join("data").exists());
+    }
+
+    #[test]
+    fn aux_dirs_are_not_written_beside_fixtures_when_blessing() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let (src_base, mut config) = aux_dir_fixture(tempdir.path(), AUX_DIR_WARNING);
+        config.bless_value = Some("1".to_owned());
+
+        run_tests(&rustc(), &src_base, &config).unwrap();
+
+        assert!(src_base.join("foo.stderr").exists());
+        assert_eq!(
+            files(&src_base).unwrap(),
+            [src_base.join("foo.rs"), src_base.join("foo.stderr")]
+        );
+    }
+
+    #[test]
+    fn aux_dirs_are_recorded_incrementally_by_real_path() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let (src_base, mut config) = aux_dir_fixture(
+            tempdir.path(),
+            "fn main() {\n    let _ = include_str!(\"data/foo.txt\");\n}\n",
+        );
+        let cache_path = tempdir.path().join("incremental.json");
+        config.incremental_cache = Some(cache_path.clone());
+        // The fingerprint includes the library's metadata, so any existing file stands in for it.
+        let driver = Driver {
+            library: src_base.join("foo.rs"),
+            ..rustc()
+        };
+
+        run_tests(&driver, &src_base, &config).unwrap();
+
+        let cache = read_to_string(&cache_path).unwrap();
+        let key = serde_json::to_string(&src_base.join("foo.rs").display().to_string()).unwrap();
+        assert!(cache.contains(&key), "{cache}");
+        assert!(!src_base.join("data").exists());
+        assert_eq!(
+            incremental::unchanged_files(
+                &driver,
+                &src_base,
+                &[src_base.join("foo.rs")],
+                &config,
+                &cache_path
+            )
+            .unwrap(),
+            [PathBuf::from("foo.rs")]
+        );
+    }
+
+    #[test]
+    fn color_is_disabled_by_default() {
+        let mut config = ui::Config::default();
+        assert!(default_args(&config).contains(&"--color=never".to_owned()));
+        config.color = ui::ColorPolicy::Always;
+        assert!(default_args(&config).contains(&"--color=always".to_owned()));
+        config.color = ui::ColorPolicy::Auto;
+        assert!(
+            !default_args(&config)
+                .iter()
+                .any(|arg| arg.starts_with("--color"))
+        );
+    }
+
+    #[test]
+    fn expected_extensions_include_overrides() {
+        let config = ui::Config {
+            editions: vec!["2021".to_owned(), "2024".to_owned()],
+            toolchain: Some("nightly-2025-01-01".to_owned()),
+            ..ui::Config::default()
+        };
+        let extensions = expected_extensions(&config, &[None, Some("a".to_owned())]);
+        for extension in [
+            "stderr",
+            "a.stderr",
+            "a.stderr.e2024",
+            "stderr.e2021",
+            "stderr.e2024",
+            "stderr.nightly-2025-01-01",
+        ] {
+            assert!(extensions.iter().any(|ext| ext == extension), "{extension}");
+        }
+    }
+
+    #[test]
+    fn blessing_writes_overrides_only_when_output_differs() {
+        let src_base = tempfile::tempdir().unwrap();
+        let overlay = tempfile::tempdir().unwrap();
+        let suffix = "nightly-2025-01-01";
+        for dir in [src_base.path(), overlay.path()] {
+            for name in ["same.rs", "differs.rs", "new.rs"] {
+                std::fs::write(dir.join(name), "").unwrap();
+            }
+        }
+        std::fs::write(src_base.path().join("same.stderr"), "same").unwrap();
+        std::fs::write(src_base.path().join("differs.stderr"), "base").unwrap();
+        std::fs::write(overlay.path().join("same.stderr"), "same").unwrap();
+        std::fs::write(overlay.path().join("differs.stderr"), "other").unwrap();
+        std::fs::write(overlay.path().join("new.stderr"), "new").unwrap();
+
+        copy_back_overrides(overlay.path(), src_base.path(), suffix, false).unwrap();
+
+        let read = |name: &str| std::fs::read_to_string(src_base.path().join(name)).ok();
+        assert_eq!(read("same.stderr").as_deref(), Some("same"));
+        assert_eq!(read("same.stderr.nightly-2025-01-01"), None);
+        assert_eq!(read("differs.stderr").as_deref(), Some("base"));
+        assert_eq!(
+            read("differs.stderr.nightly-2025-01-01").as_deref(),
+            Some("other")
+        );
+        assert_eq!(read("new.stderr").as_deref(), Some("new"));
+        assert_eq!(read("new.stderr.nightly-2025-01-01"), None);
+    }
+
+    #[test]
+    fn fixed_revisions_are_stripped() {
+        let contents = "//@revisions: a b\n//@[a] compile-flags: --cfg x\n//@[b] edition: 2024\nfn main() {}\n";
+        assert_eq!(
+            strip_revisions(contents, Path::new("ui/foo.a.fixed")),
+            "//@compile-flags: --cfg x\nfn main() {}\n"
+        );
+        assert_eq!(
+            strip_revisions(contents, Path::new("ui/foo.fixed")),
+            contents
+        );
+    }
+
+    #[test]
+    fn shuffle_is_deterministic() {
+        let sorted = (0..16).collect::<Vec<_>>();
+        let mut a = sorted.clone();
+        let mut b = sorted.clone();
+        shuffle(&mut a, 42);
+        shuffle(&mut b, 42);
+        assert_eq!(a, b);
+        assert_ne!(a, sorted);
+
+        let mut c = sorted.clone();
+        shuffle(&mut c, 43);
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    fn orphans_are_expected_files_without_sources() {
+        let tmp = tempfile::tempdir().unwrap();
+        for file in [
+            "kept.rs",
+            "kept.stderr",
+            "kept.rev.fixed",
+            "kept.stderr.e2024",
+            "kept.expanded.rs",
+            "gone.stderr",
+            "gone.expanded.rs",
+            "gone.stdout.nightly-2025-01-01",
+            "notes.txt",
+        ] {
+            write(tmp.path().join(file), "").unwrap();
+        }
+        assert_eq!(
+            orphans(tmp.path()).unwrap(),
+            [
+                tmp.path().join("gone.expanded.rs"),
+                tmp.path().join("gone.stderr"),
+                tmp.path().join("gone.stdout.nightly-2025-01-01")
+            ]
+        );
+    }
+
+    #[test]
+    fn timing_table_is_sorted_slowest_first() {
+        let mut timings = [
+            (Duration::from_millis(250), PathBuf::from("fast.rs")),
+            (Duration::from_millis(1500), PathBuf::from("slow.rs")),
+        ];
+        assert_eq!(
+            timing_table(Path::new("ui"), &mut timings),
+            "Timings for `ui` (slowest first):\n     1.500s  slow.rs\n     0.250s  fast.rs\n     1.750s  total\n"
+        );
+    }
+
+    #[test]
+    fn clean_files_are_beneath_clean_directories() {
+        let src_base = Path::new("/ui");
+        assert!(is_clean(src_base, Path::new("/ui/clean/ok.rs")));
+        assert!(is_clean(src_base, Path::new("/ui/lint/clean/ok.rs")));
+        assert!(!is_clean(src_base, Path::new("/ui/clean.rs")));
+        assert!(!is_clean(
+            Path::new("/clean/ui"),
+            Path::new("/clean/ui/fail.rs")
+        ));
+    }
+
+    #[test]
+    fn bless_output_holds_only_changed_files() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let output_dir = tempdir.path();
+        let changes = [
+            (
+                PathBuf::from("ui/changed.stderr"),
+                Some(b"old".to_vec()),
+                Some(b"new".to_vec()),
+            ),
+            (
+                PathBuf::from("ui/same.stderr"),
+                Some(b"same".to_vec()),
+                Some(b"same".to_vec()),
+            ),
+            (
+                PathBuf::from("ui/created.stderr"),
+                None,
+                Some(b"created".to_vec()),
+            ),
+            (
+                PathBuf::from("ui/removed.stderr"),
+                Some(b"old".to_vec()),
+                None,
+            ),
+        ];
+        write_bless_output(&changes, output_dir).unwrap();
+        assert_eq!(
+            files(output_dir).unwrap(),
+            [
+                output_dir.join("ui/changed.stderr"),
+                output_dir.join("ui/created.stderr")
+            ]
+        );
+        assert_eq!(
+            read_to_string(output_dir.join("ui/changed.stderr")).unwrap(),
+            "new"
+        );
+    }
+
+    #[test]
+    fn blessed_files_are_recorded_only_if_changed() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let dir = tempdir.path();
+        write(dir.join("changed.stderr"), "old").unwrap();
+        write(dir.join("same.stderr"), "same").unwrap();
+        write(dir.join("removed.stderr"), "old").unwrap();
+        let before = snapshot(dir).unwrap();
+
+        write(dir.join("changed.stderr"), "new").unwrap();
+        write(dir.join("same.stderr"), "same").unwrap();
+        remove_file(dir.join("removed.stderr")).unwrap();
+        write(dir.join("created.stderr"), "created").unwrap();
+        take_blessed();
+        record_changes(dir, &before).unwrap();
+        assert_eq!(
+            take_blessed(),
+            [
+                dir.join("changed.stderr"),
+                dir.join("created.stderr"),
+                dir.join("removed.stderr")
+            ]
+        );
+        assert!(take_blessed().is_empty());
+    }
+
+    #[test]
+    fn new_only_blessing_creates_missing_files() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let dir = tempdir.path();
+        write(dir.join("changed.stderr"), "old").unwrap();
+        write(dir.join("removed.stderr"), "old").unwrap();
+        let changes = [
+            (
+                dir.join("changed.stderr"),
+                Some(b"old".to_vec()),
+                Some(b"new".to_vec()),
+            ),
+            (dir.join("created.stderr"), None, Some(b"created".to_vec())),
+            (dir.join("removed.stderr"), Some(b"old".to_vec()), None),
+        ];
+        write_new_files(&changes, &ui::Config::default()).unwrap();
+        assert_eq!(read_to_string(dir.join("changed.stderr")).unwrap(), "old");
+        assert_eq!(
+            read_to_string(dir.join("created.stderr")).unwrap(),
+            "created"
+        );
+        assert!(dir.join("removed.stderr").exists());
+    }
+}
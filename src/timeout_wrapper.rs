@@ -0,0 +1,14 @@
+use crate::rustc_wrapper;
+use anyhow::Result;
+use cargo_metadata::camino::Utf8Path;
+use std::path::PathBuf;
+
+/// Source of the wrapper that kills the driver when it runs too long.
+const SOURCE: &str = include_str!("timeout_wrapper/main.rs");
+
+/// Returns the path of the wrapper, compiling it into `target_directory` if necessary.
+///
+/// The wrapper is invoked as `timeout_wrapper <milliseconds> <program> [args...]`.
+pub fn path(target_directory: &Utf8Path) -> Result<PathBuf> {
+    rustc_wrapper::compile(target_directory, "timeout_wrapper", SOURCE)
+}
@@ -0,0 +1,61 @@
+// This file is compiled on first use and wraps the driver when a timeout is set. It runs the
+// program given on its command line, and kills it if it does not finish within the given number of
+// milliseconds. It must not depend on anything other than `std`.
+
+use std::{
+    env::args_os,
+    process::{Command, exit},
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+/// Exit code reported when the program is killed, the same as that of `timeout(1)`.
+const TIMEOUT_EXIT_CODE: i32 = 124;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+fn main() {
+    let mut args = args_os().skip(1);
+    let (Some(millis), Some(program)) = (args.next(), args.next()) else {
+        eprintln!("timeout_wrapper: usage: timeout_wrapper <milliseconds> <program> [args...]");
+        exit(1);
+    };
+    let Some(millis) = millis.to_str().and_then(|millis| millis.parse::<u64>().ok()) else {
+        eprintln!("timeout_wrapper: invalid number of milliseconds: {millis:?}");
+        exit(1);
+    };
+    let args = args.collect::<Vec<_>>();
+
+    let mut child = match Command::new(&program).args(&args).spawn() {
+        Ok(child) => child,
+        Err(error) => {
+            eprintln!("timeout_wrapper: could not run {program:?}: {error}");
+            exit(1);
+        }
+    };
+
+    let deadline = Instant::now() + Duration::from_millis(millis);
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) => exit(status.code().unwrap_or(1)),
+            Ok(None) => {}
+            Err(error) => {
+                eprintln!("timeout_wrapper: could not wait for {program:?}: {error}");
+                exit(1);
+            }
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            let file = args
+                .iter()
+                .rev()
+                .map(|arg| arg.to_string_lossy())
+                .find(|arg| arg.ends_with(".rs"))
+                .unwrap_or_default();
+            eprintln!("error: `{file}` timed out after {millis}ms, and the driver was killed");
+            exit(TIMEOUT_EXIT_CODE);
+        }
+        sleep(POLL_INTERVAL);
+    }
+}
@@ -1,41 +1,514 @@
 use std::{
+    any::Any,
+    collections::BTreeMap,
     env::current_dir,
+    fs::{copy, create_dir_all, read_to_string, remove_file, write},
+    ops::BitOr,
+    panic::{AssertUnwindSafe, catch_unwind},
     path::{Path, PathBuf},
+    process::Command,
+    slice,
+    sync::Arc,
+    thread::sleep,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
+use anyhow::{Context, Result, anyhow};
+use cargo_metadata::camino::Utf8PathBuf;
+use libtest_mimic::{Failed, Trial};
 use log::debug;
 
 use crate::{
-    cargo_integration::{example_target, example_targets},
-    runtime::initialize,
-    test_runner::run_example_test,
+    cache,
+    cargo_integration::{
+        bin_target, example_target, example_targets, package_with_name, test_target,
+    },
+    dependencies,
+    diagnostics::Diagnostic,
+    diagnostics_wrapper, env, inline, lint_counts, lints, metadata,
+    runtime::{self, initialize},
+    test_runner::{files, run_example_test, shuffle, skipped_files, source_files, take_blessed},
+    timeout_wrapper,
+    workspace::run_workspace_test,
 };
+#[derive(Clone)]
 enum Target {
     SrcBase(PathBuf),
+    SrcBases(Vec<PathBuf>),
     Example(String),
+    ExampleInPackage(String, String),
     Examples,
+    Bin(String),
+    IntegrationTest(String),
+    Workspace(PathBuf),
+}
+
+/// Format in which diagnostics are snapshotted
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DiagnosticsFormat {
+    /// Rendered diagnostics, compared textually against `.stderr` files
+    #[default]
+    Rendered,
+    /// Diagnostics in rustc's JSON format, normalized and compared structurally against `.json`
+    /// files. Span byte offsets and rendered text are ignored, which makes the snapshots robust to
+    /// changes in how rustc renders diagnostics.
+    ///
+    /// In this mode, a diagnostic's code (i.e., its lint's name) is appended to its message in
+    /// brackets before annotations are matched. So an annotation such as `//~ ERROR my_lint` fails
+    /// if the diagnostic on that line comes from another lint or has another level.
+    Json,
+}
+
+/// Level of a diagnostic or sub-diagnostic, in increasing order of severity
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Help,
+    Note,
+    Warning,
+    Error,
+}
+
+impl Level {
+    const ALL: [Self; 4] = [Self::Help, Self::Note, Self::Warning, Self::Error];
+
+    /// Returns the level's name as rustc prints it, e.g., `warning`.
+    pub(super) const fn name(self) -> &'static str {
+        match self {
+            Self::Help => "help",
+            Self::Note => "note",
+            Self::Warning => "warning",
+            Self::Error => "error",
+        }
+    }
+
+    /// Parses the `level` of a diagnostic in rustc's JSON format. Other levels, such as those of
+    /// internal compiler errors, are not filtered and yield `None`.
+    pub(super) fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|level| level.name() == name)
+    }
+
+    const fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+/// Level of a lint, as set with `#![allow(...)]`, `#![warn(...)]`, etc.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LintLevel {
+    Allow,
+    Warn,
+    ForceWarn,
+    Deny,
+    Forbid,
+}
+
+impl LintLevel {
+    /// Returns the rustc flag that sets `lint` to this level.
+    fn flag(self, lint: &str) -> String {
+        match self {
+            Self::Allow => format!("-A{lint}"),
+            Self::Warn => format!("-W{lint}"),
+            Self::ForceWarn => format!("--force-warn={lint}"),
+            Self::Deny => format!("-D{lint}"),
+            Self::Forbid => format!("-F{lint}"),
+        }
+    }
+
+    /// Returns the level's name as `--cap-lints` expects it.
+    const fn name(self) -> &'static str {
+        match self {
+            Self::Allow => "allow",
+            Self::Warn | Self::ForceWarn => "warn",
+            Self::Deny => "deny",
+            Self::Forbid => "forbid",
+        }
+    }
+}
+
+/// Set of [`Level`]s, built by combining levels with `|`, e.g., `Level::Error | Level::Warning`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LevelFilter(u8);
+
+impl LevelFilter {
+    /// Every level
+    pub const ALL: Self = Self(0b1111);
+
+    /// Returns true if the filter includes `level`.
+    #[must_use]
+    pub const fn contains(self, level: Level) -> bool {
+        self.0 & level.bit() != 0
+    }
+
+    /// Returns the levels the filter excludes.
+    pub(super) fn excluded(self) -> impl Iterator<Item = Level> {
+        Level::ALL
+            .into_iter()
+            .filter(move |level| !self.contains(*level))
+    }
+
+    /// Returns the least severe level the filter includes.
+    pub(super) fn lowest(self) -> Option<Level> {
+        Level::ALL.into_iter().find(|level| self.contains(*level))
+    }
+}
+
+impl Default for LevelFilter {
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+
+impl From<Level> for LevelFilter {
+    fn from(level: Level) -> Self {
+        Self(level.bit())
+    }
+}
+
+impl BitOr for Level {
+    type Output = LevelFilter;
+
+    fn bitor(self, rhs: Self) -> LevelFilter {
+        LevelFilter::from(self) | rhs
+    }
+}
+
+impl BitOr<Level> for LevelFilter {
+    type Output = Self;
+
+    fn bitor(self, rhs: Level) -> Self {
+        Self(self.0 | rhs.bit())
+    }
+}
+
+/// How each file is expected to fare, unless its header says otherwise
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Mode {
+    /// Checking fails with the driver's expected exit status (the default). If `require_patterns`
+    /// is true, every diagnostic must be matched by a `//~` annotation.
+    Fail { require_patterns: bool },
+    /// Checking succeeds without diagnostics (`//@check-pass`)
+    CheckPass,
+    /// Checking succeeds, but building, i.e., through codegen, fails (`//@build-fail`)
+    BuildFail,
+    /// The file builds into a binary that runs successfully. Its output is compared against
+    /// `.run.stdout` and `.run.stderr` files (`//@run-pass`).
+    RunPass,
+}
+
+impl Default for Mode {
+    fn default() -> Self {
+        Self::Fail {
+            require_patterns: true,
+        }
+    }
+}
+
+/// Whether the driver colors its diagnostics, as set with [`Test::color`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ColorPolicy {
+    /// Never color diagnostics (`--color=never`, the default), so that expected files do not
+    /// depend on whether the driver detects a terminal
+    #[default]
+    Never,
+    /// Always color diagnostics (`--color=always`), so that expected files hold ANSI escape codes
+    Always,
+    /// Let the driver decide, as it does outside of the harness
+    Auto,
+}
+
+/// Closure passed to [`Test::assert_diagnostics`]
+pub(super) type AssertDiagnostics = Arc<dyn Fn(&[Diagnostic]) + Send + Sync>;
+
+/// Closure passed to [`Test::wrap_command`]
+pub(super) type WrapCommand = Arc<dyn Fn(&mut Command) + Send + Sync>;
+
+/// Closure passed to [`Test::examples_filter`]
+pub(super) type ExamplesFilter = Arc<dyn Fn(&str) -> bool + Send + Sync>;
+
+/// Closure passed to [`Test::on_complete`]
+pub(super) type OnComplete = Arc<dyn Fn(&TestSummary) + Send + Sync>;
+
+/// Intermediate representation dumped by [`Test::dump`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DumpKind {
+    /// The HIR tree, as printed by `-Zunpretty=hir-tree`
+    Hir,
+    /// The MIR of each body, as printed by `-Zunpretty=mir`
+    Mir,
 }
 
+/// How often [`Test::watch`] checks for changes
+const WATCH_INTERVAL: Duration = Duration::from_millis(250);
+
 /// Expected exit status for dylint driver (101 instead of 1 for some reason, ask upstream);
 const DEFAULT_EXPECTED_EXIT_STATUS: i32 = 101;
 
+/// Options of a test. They are internal, and set only through [`Test`]'s methods, so that options
+/// can be added without breaking users.
 #[derive(Clone)]
 pub(super) struct Config {
     pub(super) rustc_flags: Vec<String>,
+    pub(super) retain_flags: Vec<String>,
+    pub(super) rustc_flags_from_env: bool,
     pub(super) dylint_toml: Option<String>,
     pub(super) expected_exit_status: i32,
+    pub(super) bless_exit_status: bool,
+    pub(super) ice_backtrace: bool,
+    pub(super) repro_scripts: bool,
+    pub(super) incremental: bool,
+    pub(super) expected_failures: Vec<String>,
+    pub(super) inline_sources: Vec<(String, String)>,
+    pub(super) inline_expected: Vec<(String, String)>,
+    pub(super) aux_dirs: Vec<PathBuf>,
+    pub(super) target_dir: Option<PathBuf>,
+    pub(super) require_annotations: bool,
+    pub(super) check_stdout: bool,
+    pub(super) stderr_filters: Vec<(String, &'static [u8])>,
+    pub(super) stdout_filters: Vec<(String, &'static [u8])>,
+    pub(super) normalize_rustc_noise: bool,
+    pub(super) color: ColorPolicy,
+    pub(super) check_expanded: bool,
+    pub(super) verify_fixes: bool,
+    pub(super) diagnostics_format: DiagnosticsFormat,
+    pub(super) assert_diagnostics: Option<AssertDiagnostics>,
+    pub(super) toolchain: Option<String>,
+    pub(super) toolchains: Vec<String>,
+    pub(super) editions: Vec<String>,
+    pub(super) envs: Vec<(String, String)>,
+    pub(super) env_clear: bool,
+    pub(super) env_passthrough: Vec<String>,
+    pub(super) timeout: Option<Duration>,
+    pub(super) level_filter: LevelFilter,
+    pub(super) require_annotations_for_level: Option<Level>,
+    pub(super) dylint_toml_path: Option<PathBuf>,
+    pub(super) discover_dylint_toml: bool,
+    pub(super) mode: Mode,
+    pub(super) dependencies: Vec<(String, String)>,
+    pub(super) dependencies_manifest: Option<PathBuf>,
+    pub(super) keep_tmp: bool,
+    pub(super) shuffle_seed: Option<u64>,
+    pub(super) diff_context_lines: usize,
+    pub(super) wrap_command: Option<WrapCommand>,
+    pub(super) coverage: bool,
+    pub(super) timings: bool,
+    pub(super) self_profile: bool,
+    pub(super) dumps: Vec<DumpKind>,
+    pub(super) max_time_per_file: Option<Duration>,
+    pub(super) workspace_metadata: Option<serde_json::Value>,
+    pub(super) deny_warnings: bool,
+    pub(super) emit: Option<String>,
+    pub(super) target: Option<String>,
+    pub(super) no_std: bool,
+    pub(super) features: Vec<String>,
+    pub(super) all_features: bool,
+    pub(super) driver_path: Option<PathBuf>,
+    pub(super) offline: bool,
+    pub(super) profile: Option<String>,
+    pub(super) skip_build: bool,
+    pub(super) bless_filter: Option<String>,
+    pub(super) bless_command: Option<String>,
+    pub(super) bless_vars: Vec<String>,
+    pub(super) bless_guard: bool,
+    pub(super) prune_orphans: bool,
+    pub(super) dedup_diagnostics: bool,
+    pub(super) check_suggestions: bool,
+    pub(super) only_lint_diagnostics: bool,
+    pub(super) require_coverage: bool,
+    pub(super) expected_lint_counts: Vec<(String, usize)>,
+    pub(super) require_spanless_annotations: bool,
+    pub(super) examples_filter: Option<ExamplesFilter>,
+    pub(super) min_rust_version: Option<String>,
+    pub(super) bless_output_dir: Option<PathBuf>,
+    pub(super) on_complete: Option<OnComplete>,
+    pub(super) msrv: Option<String>,
+    pub(super) clippy_toml: Option<String>,
+    pub(super) hermetic: bool,
+
+    // Set by the harness for a run, rather than by a `Test` method
+    pub(super) edition: Option<String>,
+    pub(super) only_file: Option<PathBuf>,
+    pub(super) repro_dir: Option<PathBuf>,
+    pub(super) incremental_cache: Option<PathBuf>,
+    pub(super) excluded_files: Vec<PathBuf>,
+    pub(super) timeout_wrapper: Option<PathBuf>,
+    pub(super) dump_dir: Option<PathBuf>,
+    pub(super) diagnostics_wrapper: Option<PathBuf>,
+    pub(super) library_lints: Vec<String>,
+    pub(super) lint_counts_path: Option<PathBuf>,
+    pub(super) hermetic_dir: Option<PathBuf>,
+    // The bless variable's value, when given directly (e.g., by a unit test) rather than read from
+    // the environment
+    pub(super) bless_value: Option<String>,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
             rustc_flags: Vec::new(),
+            retain_flags: Vec::new(),
+            rustc_flags_from_env: false,
             dylint_toml: None,
             expected_exit_status: DEFAULT_EXPECTED_EXIT_STATUS,
+            bless_exit_status: false,
+            ice_backtrace: false,
+            repro_scripts: true,
+            incremental: false,
+            expected_failures: Vec::new(),
+            inline_sources: Vec::new(),
+            inline_expected: Vec::new(),
+            aux_dirs: Vec::new(),
+            target_dir: None,
+            require_annotations: true,
+            check_stdout: true,
+            stderr_filters: Vec::new(),
+            stdout_filters: Vec::new(),
+            normalize_rustc_noise: false,
+            color: ColorPolicy::default(),
+            check_expanded: false,
+            verify_fixes: false,
+            diagnostics_format: DiagnosticsFormat::default(),
+            assert_diagnostics: None,
+            toolchain: None,
+            toolchains: Vec::new(),
+            editions: Vec::new(),
+            envs: Vec::new(),
+            env_clear: false,
+            env_passthrough: Vec::new(),
+            timeout: None,
+            level_filter: LevelFilter::ALL,
+            require_annotations_for_level: None,
+            dylint_toml_path: None,
+            discover_dylint_toml: true,
+            mode: Mode::default(),
+            dependencies: Vec::new(),
+            dependencies_manifest: None,
+            keep_tmp: false,
+            shuffle_seed: None,
+            diff_context_lines: 3,
+            wrap_command: None,
+            coverage: false,
+            timings: false,
+            self_profile: false,
+            dumps: Vec::new(),
+            max_time_per_file: None,
+            workspace_metadata: None,
+            deny_warnings: true,
+            emit: None,
+            target: None,
+            no_std: false,
+            features: Vec::new(),
+            all_features: false,
+            driver_path: None,
+            offline: false,
+            profile: None,
+            skip_build: false,
+            bless_filter: None,
+            bless_command: None,
+            bless_vars: Vec::new(),
+            bless_guard: false,
+            prune_orphans: false,
+            dedup_diagnostics: false,
+            check_suggestions: false,
+            only_lint_diagnostics: false,
+            require_coverage: false,
+            expected_lint_counts: Vec::new(),
+            require_spanless_annotations: true,
+            examples_filter: None,
+            min_rust_version: None,
+            bless_output_dir: None,
+            on_complete: None,
+            msrv: None,
+            clippy_toml: None,
+            hermetic: false,
+            edition: None,
+            only_file: None,
+            repro_dir: None,
+            incremental_cache: None,
+            excluded_files: Vec::new(),
+            timeout_wrapper: None,
+            dump_dir: None,
+            diagnostics_wrapper: None,
+            library_lints: Vec::new(),
+            lint_counts_path: None,
+            hermetic_dir: None,
+            bless_value: None,
         }
     }
 }
 
+/// Panics if `pattern`, passed to [`Test::stderr_filter`] or [`Test::stdout_filter`], is not a
+/// valid regex, so that the mistake is reported where it was made rather than when the test runs.
+fn check_filter(pattern: &str) {
+    if let Err(error) = regex::Regex::new(pattern) {
+        panic!("Invalid filter `{pattern}`: {error}");
+    }
+}
+
+/// Returns a regex matching `path`, with its components separated by either `/` or `\`.
+fn path_pattern(path: &Path) -> String {
+    regex::escape(&path.to_string_lossy())
+        .replace(r"\\", "/")
+        .replace('/', r"[/\\]")
+}
+
+impl Config {
+    /// Returns true if the example named `name` should be tested.
+    fn selects_example(&self, name: &str) -> bool {
+        self.examples_filter
+            .as_ref()
+            .is_none_or(|predicate| predicate(name))
+    }
+}
+
+/// Outcome of [`Test::try_run`]
+#[derive(Debug, Default)]
+#[non_exhaustive]
+pub struct TestSummary {
+    /// Names of the files or targets that passed
+    pub passed: Vec<String>,
+    /// Files or targets that failed
+    pub failed: Vec<TestFailure>,
+    /// Names of the files skipped because an `//@only-<name>` or `//@ignore-<name>` header
+    /// excludes the platform, or because the toolchain is older than the minimum Rust version
+    pub skipped: Vec<String>,
+    /// Names of the files or targets that passed while blessing, and whose expected files blessing
+    /// wrote, changed, or removed
+    pub blessed: Vec<String>,
+    /// Time taken by the whole run
+    pub duration: Duration,
+    /// Time taken by each file or target, in the order they were run
+    pub per_file: Vec<(String, Duration)>,
+}
+
+impl TestSummary {
+    /// Returns true if nothing failed.
+    #[must_use]
+    pub fn is_success(&self) -> bool {
+        self.failed.is_empty()
+    }
+
+    /// Returns the number of files or targets run.
+    #[must_use]
+    pub fn total(&self) -> usize {
+        self.passed.len() + self.failed.len()
+    }
+}
+
+/// A file or target that failed, as reported by [`Test::try_run`]
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct TestFailure {
+    /// The file's path relative to the source directory, or a description of the target, e.g.,
+    /// `example foo`
+    pub name: String,
+    /// Why the file or target failed, including any diffs against expected files
+    pub message: String,
+}
+
 /// Test builder
 pub struct Test {
     name: String,
@@ -52,6 +525,30 @@ impl Test {
         Self::new(name, Target::SrcBase(src_base.as_ref().to_owned()))
     }
 
+    /// Test a library on all source files in each of several directories, e.g., `ui` and
+    /// `ui_toml`.
+    ///
+    /// Each directory has its own expected files, as with [`Test::src_base`], but the library and
+    /// driver are built only once. Every directory is run, even if an earlier one fails.
+    #[must_use]
+    pub fn src_bases(name: &str, src_bases: &[impl AsRef<Path>]) -> Self {
+        Self::new(
+            name,
+            Target::SrcBases(
+                src_bases
+                    .iter()
+                    .map(|src_base| src_base.as_ref().to_owned())
+                    .collect(),
+            ),
+        )
+    }
+
+    /// Test a library on only the fixtures passed to [`Test::inline_source`].
+    #[must_use]
+    pub fn inline(name: &str) -> Self {
+        Self::new(name, Target::SrcBases(Vec::new()))
+    }
+
     /// Test a library on one example target (similar to [`ui_test_example`]).
     ///
     /// [`ui_test_example`]: crate::ui_test_example
@@ -60,6 +557,18 @@ impl Test {
         Self::new(name, Target::Example(example.to_owned()))
     }
 
+    /// Test a library on one example target of another workspace package.
+    ///
+    /// This allows dependency-bearing fixtures to live in a separate package, so that they do not
+    /// add to the library's own dependencies.
+    #[must_use]
+    pub fn example_in_package(name: &str, package: &str, example: &str) -> Self {
+        Self::new(
+            name,
+            Target::ExampleInPackage(package.to_owned(), example.to_owned()),
+        )
+    }
+
     /// Test a library on all example targets (similar to [`ui_test_examples`]).
     ///
     /// [`ui_test_examples`]: crate::ui_test_examples
@@ -68,6 +577,31 @@ impl Test {
         Self::new(name, Target::Examples)
     }
 
+    /// Test a library on one binary target (similar to [`ui_test_bin`]).
+    ///
+    /// [`ui_test_bin`]: crate::ui_test_bin
+    #[must_use]
+    pub fn bin(name: &str, bin: &str) -> Self {
+        Self::new(name, Target::Bin(bin.to_owned()))
+    }
+
+    /// Test a library on one integration test target, i.e., a file in the package's `tests`
+    /// directory.
+    ///
+    /// The target is compiled with `--test` and has access to the package's dev-dependencies.
+    #[must_use]
+    pub fn integration_test(name: &str, test: &str) -> Self {
+        Self::new(name, Target::IntegrationTest(test.to_owned()))
+    }
+
+    /// Test a library on a fixture Cargo workspace (similar to [`ui_test_workspace`]).
+    ///
+    /// [`ui_test_workspace`]: crate::ui_test_workspace
+    #[must_use]
+    pub fn workspace(name: &str, workspace: impl AsRef<Path>) -> Self {
+        Self::new(name, Target::Workspace(workspace.as_ref().to_owned()))
+    }
+
     /// Pass flags to the compiler when running the test.
     pub fn rustc_flags(
         &mut self,
@@ -79,88 +613,1699 @@ impl Test {
         self
     }
 
+    /// Keep the given flags (and their arguments) from Cargo's build of an example, binary, or
+    /// integration test target, in addition to those kept by default: `--edition`, `--extern`,
+    /// `-L`, `--test`, `--cfg`, `--check-cfg`, and `--cap-lints`.
+    ///
+    /// For example, `retain_flags(["-C"])` keeps codegen options such as `-C debuginfo=2`. A flag
+    /// also matches its attached forms, e.g., `-Cdebuginfo=2` and `--remap-path-prefix=...`.
+    pub fn retain_flags(&mut self, flags: impl IntoIterator<Item = impl AsRef<str>>) -> &mut Self {
+        self.config
+            .retain_flags
+            .extend(flags.into_iter().map(|s| s.as_ref().to_owned()));
+        self
+    }
+
+    /// Pass the flags in `RUSTFLAGS` (or `CARGO_ENCODED_RUSTFLAGS`) and `DYLINT_RUSTFLAGS` to the
+    /// compiler, as well as those passed to [`Test::rustc_flags`] (default `false`).
+    ///
+    /// This keeps the driver's output consistent with builds that use the same flags, e.g., in CI.
+    /// The flags are split as Cargo splits them and passed in the order `RUSTFLAGS`,
+    /// `DYLINT_RUSTFLAGS`, and then those passed to `rustc_flags`, so that later ones take
+    /// precedence.
+    pub fn rustc_flags_from_env(&mut self, value: bool) -> &mut Self {
+        self.config.rustc_flags_from_env = value;
+        self
+    }
+
+    /// Set `lint` to `level` for every file, as with `-A`, `-W`, `--force-warn`, `-D`, or `-F`.
+    ///
+    /// This makes it easy to test how a lint behaves at different levels, without adding
+    /// `#![warn(...)]` attributes to every fixture. Note that, unless [`Test::deny_warnings`] is
+    /// disabled, warnings are still denied.
+    pub fn lint_level(&mut self, lint: &str, level: LintLevel) -> &mut Self {
+        self.config.rustc_flags.push(level.flag(lint));
+        self
+    }
+
+    /// Cap the level of every lint at `level`, as with `--cap-lints`.
+    pub fn cap_lints(&mut self, level: LintLevel) -> &mut Self {
+        self.config
+            .rustc_flags
+            .push(format!("--cap-lints={}", level.name()));
+        self
+    }
+
     /// Set the `dylint.toml` file's contents (for testing configurable libraries).
     pub fn dylint_toml(&mut self, dylint_toml: impl AsRef<str>) -> &mut Self {
         self.config.dylint_toml = Some(dylint_toml.as_ref().to_owned());
         self
     }
 
+    /// Set the `clippy.toml` file's contents (for libraries reading their configuration with
+    /// `clippy_utils`).
+    ///
+    /// The file is written to a temporary directory, which is passed to the driver as
+    /// `CLIPPY_CONF_DIR`, so that Clippy's configuration loader finds it for every source file and
+    /// example, and a `clippy.toml` in the package is ignored.
+    pub fn clippy_toml(&mut self, clippy_toml: impl AsRef<str>) -> &mut Self {
+        self.config.clippy_toml = Some(clippy_toml.as_ref().to_owned());
+        self
+    }
+
+    /// Set an environment variable for the driver, e.g., one read by the library under test.
+    ///
+    /// The variable is set for the driver's process only. Unlike calling `std::env::set_var`, this
+    /// does not race with other tests or with the harness. Call repeatedly to set several variables.
+    pub fn env(&mut self, key: impl AsRef<str>, value: impl AsRef<str>) -> &mut Self {
+        self.config
+            .envs
+            .push((key.as_ref().to_owned(), value.as_ref().to_owned()));
+        self
+    }
+
+    /// Do not let the driver or dependency builds inherit the test's environment.
+    ///
+    /// Only variables essential to running the driver and Cargo (e.g., `PATH` and `RUSTUP_HOME`)
+    /// are inherited, along with those named in [`Test::env_passthrough`]. In addition, the driver
+    /// inherits `RUST_BACKTRACE` and `RUST_LOG`, and builds of example targets inherit proxy and
+    /// network configuration (e.g., `HTTPS_PROXY` and `CARGO_HTTP_PROXY`). Variables set with
+    /// [`Test::env`] are always passed to the driver.
+    pub fn env_clear(&mut self) -> &mut Self {
+        self.config.env_clear = true;
+        self
+    }
+
+    /// Run the driver in a controlled environment, so that `.stderr` files do not depend on the
+    /// developer's machine (default `false`).
+    ///
+    /// The environment is cleared as by [`Test::env_clear`], and the driver's `HOME`, `CARGO_HOME`,
+    /// and temporary directory (`TMPDIR`, `TMP`, and `TEMP`) point to fresh directories, so that no
+    /// global configuration is read. `RUSTUP_HOME` still points to the real one, so that the
+    /// toolchain is found. Builds of the library, examples, and dependencies use the real
+    /// `CARGO_HOME`, so that crates need not be downloaded again.
+    pub fn hermetic(&mut self, hermetic: bool) -> &mut Self {
+        self.config.hermetic = hermetic;
+        self
+    }
+
+    /// Inherit the named environment variables even when [`Test::env_clear`] is called.
+    pub fn env_passthrough(
+        &mut self,
+        vars: impl IntoIterator<Item = impl AsRef<str>>,
+    ) -> &mut Self {
+        self.config
+            .env_passthrough
+            .extend(vars.into_iter().map(|s| s.as_ref().to_owned()));
+        self
+    }
+
+    /// Read the `dylint.toml` file's contents from `path`, so that the same file drives both
+    /// `cargo dylint` and the test. A relative path is resolved against the current directory.
+    ///
+    /// Contents passed to [`Test::dylint_toml`] take precedence.
+    pub fn dylint_toml_path(&mut self, path: impl AsRef<Path>) -> &mut Self {
+        self.config.dylint_toml_path = Some(path.as_ref().to_owned());
+        self
+    }
+
+    /// Use the package root's `dylint.toml`, if any, when no other configuration is given
+    /// (default `true`).
+    pub fn discover_dylint_toml(&mut self, discover_dylint_toml: bool) -> &mut Self {
+        self.config.discover_dylint_toml = discover_dylint_toml;
+        self
+    }
+
     /// Set the expected exit status for the dylint driver.
+    ///
+    /// A file's `//@exit-status: <code>` header (or `//@[revision] exit-status: <code>`) overrides
+    /// it for that file, e.g., for a fixture that makes the driver ICE.
     pub fn expected_exit_status(&mut self, code: i32) -> &mut Self {
         self.config.expected_exit_status = code;
         self
     }
 
-    /// Run the test.
-    #[allow(clippy::needless_pass_by_ref_mut)]
-    pub fn run(&mut self) {
-        self.run_immutable();
+    /// When blessing, record each file's exit status in an `//@exit-status:` header where it
+    /// differs from the expected one, and remove headers that have become unnecessary (default
+    /// `false`).
+    ///
+    /// This lets one suite hold files that legitimately exit differently, without each header being
+    /// written by hand. Review the headers before committing them, since they merely record the
+    /// current behavior.
+    pub fn bless_exit_status(&mut self, bless_exit_status: bool) -> &mut Self {
+        self.config.bless_exit_status = bless_exit_status;
+        self
     }
 
-    fn new(name: &str, target: Target) -> Self {
-        Self {
-            name: name.to_owned(),
-            target,
-            config: Config::default(),
-        }
+    /// When the driver panics on a file, compile the file again with `RUST_BACKTRACE=full` and
+    /// include the backtrace in the failure message (default `false`).
+    ///
+    /// Whether or not this is enabled, a failing test reports each file on which the driver panicked
+    /// (an internal compiler error) separately, with the start of the panic message and a command
+    /// line that reproduces it outside the harness.
+    pub fn ice_backtrace(&mut self, ice_backtrace: bool) -> &mut Self {
+        self.config.ice_backtrace = ice_backtrace;
+        self
     }
 
-    fn run_immutable(&self) {
-        debug!(
-            "run_immutable: Starting run_immutable for library '{}'",
-            self.name
-        );
-        let driver = initialize(&self.name).unwrap();
-        debug!("run_immutable: Got driver: {}", driver.display());
+    /// When the test fails, write scripts that run the driver on each file as the test did to
+    /// `target/dylint_testing/repro/<name>` (default `true`).
+    ///
+    /// Each file gets a `.sh` and a `.ps1` script with the driver's variables, arguments, and
+    /// working directory, so that one file can be iterated on without the harness. When the files
+    /// are run separately, e.g., by [`Test::collect`], only the failing ones get scripts. The
+    /// scripts of an example test refer to a temporary copy of the example, which is only kept with
+    /// [`Test::keep_going_artifacts`].
+    pub fn repro_scripts(&mut self, repro_scripts: bool) -> &mut Self {
+        self.config.repro_scripts = repro_scripts;
+        self
+    }
 
-        match &self.target {
-            Target::SrcBase(src_base) => {
-                debug!(
-                    "run_immutable: Running SrcBase target with src_base: {}",
-                    src_base.display()
-                );
-                crate::test_runner::run_tests(driver, src_base, &self.config)
-                    .expect("run tests failed");
-            }
-            Target::Example(example) => {
-                debug!("run_immutable: Running Example target: {}", example);
-                let metadata = dylint_internal::cargo::current_metadata().unwrap();
-                let current_dir = current_dir().unwrap();
-                let package =
-                    dylint_internal::cargo::package_with_root(&metadata, &current_dir).unwrap();
-                let target = example_target(&package, example).unwrap();
+    /// Skip the source files whose inputs are unchanged since they last passed (default `false`).
+    ///
+    /// A file's inputs are its contents, those of its expected files (e.g., `foo.stderr`), the built
+    /// library, and the flags, variables, and `dylint.toml` given to the driver. They are recorded
+    /// in `target/dylint_testing/cache.json` when a run passes. Other changes, e.g., to auxiliary
+    /// crates or the toolchain, are not detected, so run without this before committing. This
+    /// applies to source directories, not examples.
+    pub fn incremental(&mut self, incremental: bool) -> &mut Self {
+        self.config.incremental = incremental;
+        self
+    }
 
-                crate::test_runner::run_example_test(
-                    driver,
-                    &metadata,
-                    &package,
-                    &target,
-                    &self.config,
-                )
-                .unwrap();
-            }
-            Target::Examples => {
-                let metadata = dylint_internal::cargo::current_metadata().unwrap();
-                let current_dir = current_dir().unwrap();
-                let package =
-                    dylint_internal::cargo::package_with_root(&metadata, &current_dir).unwrap();
-                let targets = example_targets(&package).unwrap();
+    /// Add a source file named `name` (e.g., `"foo.rs"`) with contents `code`, for small fixtures
+    /// that do not merit a file of their own. May be called more than once.
+    ///
+    /// Inline fixtures are written to a temporary directory and run like the files of a source
+    /// directory, after the test's other files. Their expected files are passed to
+    /// [`Test::inline_expected`]. When blessing, the expected files that changed are printed, to be
+    /// copied into the test. [`Test::collect`] runs the inline fixtures as a single test.
+    pub fn inline_source(&mut self, name: &str, code: &str) -> &mut Self {
+        self.config
+            .inline_sources
+            .push((name.to_owned(), code.to_owned()));
+        self
+    }
 
-                for target in targets {
-                    run_example_test(driver, &metadata, &package, &target, &self.config).unwrap();
-                }
-            }
+    /// Add an expected file named `name` (e.g., `"foo.stderr"`) with contents `contents`, for a
+    /// fixture passed to [`Test::inline_source`]. May be called more than once.
+    pub fn inline_expected(&mut self, name: &str, contents: &str) -> &mut Self {
+        self.config
+            .inline_expected
+            .push((name.to_owned(), contents.to_owned()));
+        self
+    }
+
+    /// Copy the directory at `path` (e.g., `tests/data`, relative to the package root) next to the
+    /// source files under its own name (e.g., `data`), so that they can read what is in it, e.g.,
+    /// with `include_str!("data/foo.json")`. May be called more than once.
+    ///
+    /// Source files are then run from a temporary copy of their directory, as examples already
+    /// are, and blessed files are copied back, so that nothing is written beside them. The
+    /// directory's files are neither run nor blessed.
+    pub fn aux_dir(&mut self, path: impl AsRef<Path>) -> &mut Self {
+        self.config.aux_dirs.push(path.as_ref().to_owned());
+        self
+    }
+
+    /// Expect the source files matching `pattern` (e.g., `"known_bugs/*.rs"`) to fail, as with an
+    /// `//@known-bug` header. May be called more than once.
+    ///
+    /// Such a file is run on its own, after the others, and its expected files are neither checked
+    /// nor blessed. The test passes as long as the file fails, and fails once the file passes, so
+    /// that the header or pattern is removed when the bug is fixed.
+    pub fn expect_failure(&mut self, pattern: &str) -> &mut Self {
+        self.config.expected_failures.push(pattern.to_owned());
+        self
+    }
+
+    /// Require that every diagnostic be matched by a `//~` annotation (default `true`).
+    ///
+    /// Disabling this eases migrating suites written for `compiletest`, whose fixtures often lack
+    /// annotations. Output is still compared against the expected `.stderr` files.
+    pub fn require_annotations(&mut self, require_annotations: bool) -> &mut Self {
+        self.config.require_annotations = require_annotations;
+        self
+    }
+
+    /// Set how files are expected to fare (default [`Mode::Fail`] requiring annotations).
+    ///
+    /// A file can override the mode with a `//@check-pass`, `//@build-fail`, or `//@run-pass`
+    /// header.
+    pub fn mode(&mut self, mode: Mode) -> &mut Self {
+        self.config.mode = mode;
+        if let Mode::Fail { require_patterns } = mode {
+            self.config.require_annotations = require_patterns;
         }
+        self
     }
-}
 
-#[cfg(test)]
-mod test {
-    use super::*;
+    /// Pass `-D warnings` to the driver (default `true`).
+    ///
+    /// Disable this for lints that intentionally emit warnings, so that the warnings are not turned
+    /// into errors. The expected exit status is unaffected; see [`Test::expected_exit_status`].
+    pub fn deny_warnings(&mut self, deny_warnings: bool) -> &mut Self {
+        self.config.deny_warnings = deny_warnings;
+        self
+    }
 
-    // smoelius: Verify that `rustc_flags` compiles when used as intended.
-    #[allow(dead_code)]
-    fn rustc_flags() {
-        let _ = Test::src_base("name", PathBuf::new()).rustc_flags(["--test"]);
+    /// Set the kinds of output the driver emits, as with rustc's `--emit`, e.g., `"link"` for
+    /// fixtures that need real codegen.
+    ///
+    /// The default is `metadata`, or `link` for [`Mode::BuildFail`] and [`Mode::RunPass`].
+    pub fn emit(&mut self, emit: &str) -> &mut Self {
+        self.config.emit = Some(emit.to_owned());
+        self
+    }
+
+    /// Compare the driver's stdout against `.stdout` files (default `true`).
+    ///
+    /// When disabled, stdout is discarded, and `.stdout` files are neither checked nor blessed.
+    pub fn check_stdout(&mut self, check_stdout: bool) -> &mut Self {
+        self.config.check_stdout = check_stdout;
+        self
+    }
+
+    /// Replace the matches of the regex `pattern` in the driver's stderr with `replacement` before
+    /// it is compared against `.stderr` files, e.g., to scrub version numbers, timestamps, or
+    /// hashes. May be called more than once; filters apply in the order they were added.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `pattern` is not a valid regex.
+    pub fn stderr_filter(
+        &mut self,
+        pattern: &str,
+        replacement: &'static (impl AsRef<[u8]> + ?Sized),
+    ) -> &mut Self {
+        check_filter(pattern);
+        self.config
+            .stderr_filters
+            .push((pattern.to_owned(), replacement.as_ref()));
+        self
+    }
+
+    /// Like [`Test::stderr_filter`], but for the driver's stdout and `.stdout` files.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `pattern` is not a valid regex.
+    pub fn stdout_filter(
+        &mut self,
+        pattern: &str,
+        replacement: &'static (impl AsRef<[u8]> + ?Sized),
+    ) -> &mut Self {
+        check_filter(pattern);
+        self.config
+            .stdout_filters
+            .push((pattern.to_owned(), replacement.as_ref()));
+        self
+    }
+
+    /// Replace `path` in the driver's stderr and stdout with `replacement`, e.g., to scrub a
+    /// machine-specific directory. The path matches whichever separators it is printed with.
+    pub fn path_filter(
+        &mut self,
+        path: impl AsRef<Path>,
+        replacement: &'static (impl AsRef<[u8]> + ?Sized),
+    ) -> &mut Self {
+        let pattern = path_pattern(path.as_ref());
+        self.config
+            .stderr_filters
+            .push((pattern.clone(), replacement.as_ref()));
+        self.config
+            .stdout_filters
+            .push((pattern, replacement.as_ref()));
+        self
+    }
+
+    /// Normalize rustc phrasing that varies between toolchains in the driver's stderr (default
+    /// `false`), so that expected files survive toolchain bumps.
+    ///
+    /// The counts in summaries such as `error: aborting due to 2 previous errors` are replaced with
+    /// `N`, error codes (e.g., `error[E0425]`) and the `rustc --explain` hints that follow them are
+    /// removed, and lint group notes are reduced to their older form. Bless after enabling this.
+    pub fn normalize_rustc_noise(&mut self, normalize_rustc_noise: bool) -> &mut Self {
+        self.config.normalize_rustc_noise = normalize_rustc_noise;
+        self
+    }
+
+    /// Set whether the driver colors its diagnostics (default [`ColorPolicy::Never`]).
+    ///
+    /// By default, color is disabled, since whether a driver colors its output can depend on the
+    /// terminal it runs in. Use [`ColorPolicy::Always`] to snapshot colored output intentionally.
+    pub fn color(&mut self, color: ColorPolicy) -> &mut Self {
+        self.config.color = color;
+        self
+    }
+
+    /// Compare each file's macro-expanded source against a `.expanded.rs` file (default `false`).
+    ///
+    /// The source is expanded by the driver with `-Zunpretty=expanded`, so the snapshot shows
+    /// exactly the code a lint sees after expansion. The files are written when blessing.
+    pub fn check_expanded(&mut self, check_expanded: bool) -> &mut Self {
+        self.config.check_expanded = check_expanded;
+        self
+    }
+
+    /// Verify the library's machine-applicable suggestions (default `false`).
+    ///
+    /// Suggestions are applied and compared against `.fixed` files (written when blessing). Then
+    /// the driver is re-run on the fixed code, which must produce no diagnostics.
+    pub fn verify_fixes(&mut self, verify_fixes: bool) -> &mut Self {
+        self.config.verify_fixes = verify_fixes;
+        self
+    }
+
+    /// Keep only diagnostics and sub-diagnostics at the given levels, e.g., `Level::Error |
+    /// Level::Warning` (default all levels).
+    ///
+    /// Others are removed from the `.stderr` files, JSON snapshots, and the diagnostics passed to
+    /// [`Test::assert_diagnostics`]. This helps when a lint emits long, version-sensitive notes that
+    /// fixtures should not have to encode. Diagnostics at excluded levels need not be annotated;
+    /// unless [`Test::require_annotations_for_level`] is also called, those at the least severe
+    /// included level and above must be.
+    pub fn diagnostic_level_filter(&mut self, levels: impl Into<LevelFilter>) -> &mut Self {
+        self.config.level_filter = levels.into();
+        self
+    }
+
+    /// Require annotations for diagnostics at `level` and above, e.g., `Level::Note` to require
+    /// that notes be matched too.
+    ///
+    /// By default, ui_test requires annotations only for the most severe level of diagnostic that
+    /// a file emits. This takes precedence over the level implied by
+    /// [`Test::diagnostic_level_filter`], whichever is called first.
+    pub fn require_annotations_for_level(&mut self, level: Level) -> &mut Self {
+        self.config.require_annotations_for_level = Some(level);
+        self
+    }
+
+    /// Set the format in which diagnostics are snapshotted (default [`DiagnosticsFormat::Rendered`]).
+    pub fn diagnostics_format(&mut self, diagnostics_format: DiagnosticsFormat) -> &mut Self {
+        self.config.diagnostics_format = diagnostics_format;
+        self
+    }
+
+    /// Assert on the diagnostics programmatically instead of comparing against expected files.
+    ///
+    /// The closure is called once per source file with that file's diagnostics, parsed from the
+    /// driver's JSON output. Annotations and `.stderr` files are not checked, so fixtures need
+    /// nothing but source. Panic in the closure to fail the test.
+    ///
+    /// ```rust,ignore
+    /// dylint_testing::ui::Test::src_base(env!("CARGO_PKG_NAME"), "ui")
+    ///     .assert_diagnostics(|diagnostics| {
+    ///         assert!(diagnostics.iter().any(|diagnostic| {
+    ///             diagnostic.code.as_ref().is_some_and(|code| code.code == "my_lint")
+    ///         }));
+    ///     })
+    ///     .run();
+    /// ```
+    pub fn assert_diagnostics(
+        &mut self,
+        assert: impl Fn(&[Diagnostic]) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.config.assert_diagnostics = Some(Arc::new(assert));
+        self
+    }
+
+    /// Compile the source files, examples, and auxiliary crates for `target`, e.g.,
+    /// `"wasm32-unknown-unknown"`, rather than for the host.
+    ///
+    /// The target's standard library must be installed, e.g., with `rustup target add`. Files in
+    /// [`Mode::RunPass`] (or with `//@run-pass` headers) are built but not run. Procedural macro
+    /// auxiliary crates are still built for the host.
+    pub fn target(&mut self, target: &str) -> &mut Self {
+        self.config.target = Some(target.to_owned());
+        self
+    }
+
+    /// Compile the source files as `#![no_std]` libraries (default `false`).
+    ///
+    /// Each file is compiled with `--crate-type=lib` and `-C panic=abort`, and only metadata is
+    /// emitted (unless [`Test::emit`] says otherwise), so fixtures need no `main` function, panic
+    /// handler, or `eh_personality`, and nothing is linked. Combine this with [`Test::target`] to
+    /// check fixtures against a bare-metal target's `core`, e.g., `thumbv7em-none-eabihf`.
+    pub fn no_std(&mut self, no_std: bool) -> &mut Self {
+        self.config.no_std = no_std;
+        self
+    }
+
+    /// Enable the package's `features` when linting, so that feature-gated code is linted.
+    ///
+    /// Example targets are built with `--features`. Source files are compiled with
+    /// `--cfg feature="..."` for each feature.
+    ///
+    /// An example's `required-features` are enabled regardless. An example requiring a feature the
+    /// package does not declare is skipped with a note.
+    pub fn features(&mut self, features: &[&str]) -> &mut Self {
+        self.config
+            .features
+            .extend(features.iter().map(|&feature| feature.to_owned()));
+        self
+    }
+
+    /// Enable all of the package's features when linting (default `false`).
+    ///
+    /// Example targets are built with `--all-features`. Source files are compiled with
+    /// `--cfg feature="..."` for each feature declared in the package's manifest.
+    pub fn all_features(&mut self, all_features: bool) -> &mut Self {
+        self.config.all_features = all_features;
+        self
+    }
+
+    /// Use the prebuilt driver at `path` rather than having Dylint find or build one.
+    ///
+    /// This helps in sandboxed CI, where the driver cannot be downloaded or built. Setting
+    /// `DYLINT_TESTING_DRIVER` has the same effect. A relative path is resolved against the current
+    /// directory. The driver must have been built for the toolchain the test uses.
+    pub fn driver_path(&mut self, path: impl AsRef<Path>) -> &mut Self {
+        self.config.driver_path = Some(path.as_ref().to_owned());
+        self
+    }
+
+    /// Pass `--offline` to the Cargo commands the test runs (default `false`).
+    ///
+    /// This is for hermetic build systems, e.g., Bazel or Nix, in which the test must not access
+    /// the network. The library, the examples, and any [`Test::dependencies`] are then built only
+    /// from crates already downloaded, and a build that would need to download one fails. Dylint
+    /// may still need the network to build the driver, so combine this with [`Test::driver_path`]
+    /// unless the driver is already built.
+    pub fn offline(&mut self, offline: bool) -> &mut Self {
+        self.config.offline = offline;
+        self
+    }
+
+    /// Build the library with the Cargo profile `profile`, e.g., `release` (default `dev`).
+    ///
+    /// The library is then looked for in the profile's directory, e.g., `target/release`, rather
+    /// than in `target/debug`. Custom profiles are supported. Setting `CARGO_PROFILE` has the same
+    /// effect. The target directory is the one Cargo reports, so `CARGO_TARGET_DIR` and
+    /// `build.target-dir` are respected.
+    pub fn profile(&mut self, profile: &str) -> &mut Self {
+        self.config.profile = Some(profile.to_owned());
+        self
+    }
+
+    /// Do not build the library; only look for it (default `false`).
+    ///
+    /// This is for libraries built by an external build system. The library is looked for by name
+    /// and toolchain, first in the directories in `DYLINT_LIBRARY_PATH`, and then in the profile's
+    /// directory (see [`Test::profile`]). The test fails if the library is not found. Example
+    /// targets are still built, since their linking flags are needed.
+    pub fn skip_build(&mut self, skip_build: bool) -> &mut Self {
+        self.config.skip_build = skip_build;
+        self
+    }
+
+    /// When blessing, only test and bless the files matching the glob `pattern`, e.g., `foo*.rs` or
+    /// `subdir/**/*.rs`.
+    ///
+    /// The other files are not run, so their expected files cannot be rewritten, e.g., after a
+    /// toolchain bump. A pattern without a `/` is matched against file names, and one with a `/`
+    /// against paths relative to the source directory. Setting `BLESS_FILTER` has the same effect.
+    /// The filter has no effect unless `BLESS` is set.
+    pub fn bless_filter(&mut self, pattern: &str) -> &mut Self {
+        self.config.bless_filter = Some(pattern.to_owned());
+        self
+    }
+
+    /// Suggest `command` for blessing in failure messages, e.g., `BLESS=1 cargo test -p my_lint`.
+    ///
+    /// By default, the command runs just the current test target, e.g., `BLESS=1 cargo test -p
+    /// my_lint --test ui`, as derived from `CARGO_PKG_NAME` and the name of the test binary.
+    pub fn bless_command(&mut self, command: &str) -> &mut Self {
+        self.config.bless_command = Some(command.to_owned());
+        self
+    }
+
+    /// Read whether to bless from the environment variable `var` rather than `BLESS`, e.g.,
+    /// `DYLINT_BLESS`, so that it cannot collide with other tools. May be called more than once, in
+    /// which case the first variable that is set is used.
+    ///
+    /// The variable accepts the same values as `BLESS`, and failure messages name it.
+    pub fn bless_var(&mut self, var: &str) -> &mut Self {
+        self.config.bless_vars.push(var.to_owned());
+        self
+    }
+
+    /// When blessing, write the blessed files beneath `dir` rather than next to the fixtures, e.g.,
+    /// `target/ui-new`.
+    ///
+    /// Each file is written to its path relative to the current directory, e.g., `ui/foo.stderr` to
+    /// `target/ui-new/ui/foo.stderr`, and only if blessing changes it. The fixture directory is left
+    /// untouched, so that the new outputs can be reviewed before [`Test::accept`] copies them back.
+    pub fn bless_output_dir(&mut self, dir: impl AsRef<Path>) -> &mut Self {
+        self.config.bless_output_dir = Some(dir.as_ref().to_owned());
+        self
+    }
+
+    /// Refuse to bless if the fixtures have unstaged changes in Git (default `false`).
+    ///
+    /// Before blessing, `git status` is checked for the source directory (or the example's, or the
+    /// workspace's directory), and the test fails if any file there has changes that are not
+    /// staged, so that blessing cannot overwrite manual edits. Stage or stash the changes first, or
+    /// set `DYLINT_TESTING_ALLOW_DIRTY=1` to bless anyway. Outside a Git repository, the guard does
+    /// nothing.
+    pub fn bless_guard(&mut self, bless_guard: bool) -> &mut Self {
+        self.config.bless_guard = bless_guard;
+        self
+    }
+
+    /// Remove expected files that belong to no source file when blessing, and fail if any exist
+    /// otherwise (default `false`).
+    ///
+    /// An expected file, e.g., `foo.stderr` or `foo.fixed`, is orphaned when `foo.rs` is renamed or
+    /// removed. By default, orphans are only reported, when blessing. This applies to source
+    /// directories, not examples.
+    pub fn prune_orphans(&mut self, prune_orphans: bool) -> &mut Self {
+        self.config.prune_orphans = prune_orphans;
+        self
+    }
+
+    /// Collapse diagnostics with the same message, level, and primary span into the first of them
+    /// (default `false`).
+    ///
+    /// Macro-heavy fixtures can make a lint fire many times at one place, which `rustc` does not
+    /// deduplicate when the diagnostics' notes differ. With this option, the repeats are dropped
+    /// before the output is compared or blessed. The driver is then run by a small wrapper that
+    /// filters its output. The `aborting due to` summary still counts the dropped diagnostics.
+    pub fn dedup_diagnostics(&mut self, dedup_diagnostics: bool) -> &mut Self {
+        self.config.dedup_diagnostics = dedup_diagnostics;
+        self
+    }
+
+    /// Let annotations check suggested replacements (default `false`).
+    ///
+    /// Each suggested replacement is appended to the message of the sub-diagnostic making it, along
+    /// with its applicability, e.g., `try [SUGGESTION foo() (MachineApplicable)]`, so that
+    /// `//~| HELP: SUGGESTION foo()` checks it. Only ui_test's matching sees the added text. The
+    /// driver is then run by a small wrapper that filters its output.
+    pub fn check_suggestions(&mut self, check_suggestions: bool) -> &mut Self {
+        self.config.check_suggestions = check_suggestions;
+        self
+    }
+
+    /// Compare only the diagnostics of the library's own lints (default `false`).
+    ///
+    /// The library's lints are listed by the driver (with `DYLINT_LIST` set) before the files are
+    /// run. Other diagnostics, e.g., warnings from a fixture's scaffolding or rustc's own errors,
+    /// are dropped before the output is compared or blessed, and so need no annotations. So are
+    /// diagnostics without a lint name, such as the `aborting due to` summary. The driver's exit
+    /// status is unaffected.
+    pub fn only_lint_diagnostics(&mut self, only_lint_diagnostics: bool) -> &mut Self {
+        self.config.only_lint_diagnostics = only_lint_diagnostics;
+        self
+    }
+
+    /// Fail if any lint the library registers fires in none of the source files (default
+    /// `false`), so that untested lints are noticed.
+    ///
+    /// The library's lints are listed by the driver (with `DYLINT_LIST` set), and the files are
+    /// compiled once more after they pass, to count each lint's diagnostics. This applies to source
+    /// directories, not examples, and not to a single file run on its own.
+    pub fn require_coverage(&mut self, require_coverage: bool) -> &mut Self {
+        self.config.require_coverage = require_coverage;
+        self
+    }
+
+    /// Expect `lint` to emit exactly `count` diagnostics across the source files (and their
+    /// revisions), so that a change that stops some of its matches from firing is noticed. Calling
+    /// this again for the same lint replaces the count.
+    ///
+    /// With this or [`Test::require_coverage`], the number of diagnostics each lint emits is
+    /// written to `target/dylint_testing/lint_counts/<name>.json`. Like
+    /// [`Test::require_coverage`], this applies to source directories, not examples, and not to a
+    /// single file run on its own.
+    pub fn expect_lint_count(&mut self, lint: &str, count: usize) -> &mut Self {
+        self.config
+            .expected_lint_counts
+            .retain(|(expected, _)| expected != lint);
+        self.config
+            .expected_lint_counts
+            .push((lint.to_owned(), count));
+        self
+    }
+
+    /// Require file-level annotations for diagnostics without a span (default `true`).
+    ///
+    /// Crate-level diagnostics cannot be annotated with `//~` on a line. Instead, annotate them with
+    /// a `//@error-in-other-file: <message>` header, which `BLESS_ANNOTATIONS=1` also injects. The
+    /// header matches diagnostics without a span or with a span in another file. Disable this to
+    /// allow such diagnostics without annotations; they are still compared against the `.stderr`
+    /// file. The driver is then run by a small wrapper that filters its output.
+    pub fn require_spanless_annotations(
+        &mut self,
+        require_spanless_annotations: bool,
+    ) -> &mut Self {
+        self.config.require_spanless_annotations = require_spanless_annotations;
+        self
+    }
+
+    /// Test only the example targets whose names satisfy `predicate`, when testing all of them with
+    /// [`Test::examples`].
+    ///
+    /// This skips, e.g., known-slow or platform-specific examples without restructuring the
+    /// examples directory:
+    ///
+    /// ```rust,ignore
+    /// test.examples_filter(|name| !name.starts_with("slow_") && name != "windows_only");
+    /// ```
+    pub fn examples_filter(
+        &mut self,
+        predicate: impl Fn(&str) -> bool + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.config.examples_filter = Some(Arc::new(predicate));
+        self
+    }
+
+    /// Call `callback` with the [`TestSummary`] once the test has run, e.g., to push metrics to a
+    /// dashboard or to enforce a policy:
+    ///
+    /// ```rust,ignore
+    /// test.on_complete(|summary| assert!(summary.skipped.len() <= 3, "too many skipped files"));
+    /// ```
+    ///
+    /// The callback is called by [`Test::try_run`], and by [`Test::run`], which then runs each file
+    /// or target separately. A panic in the callback fails the test.
+    pub fn on_complete(
+        &mut self,
+        callback: impl Fn(&TestSummary) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.config.on_complete = Some(Arc::new(callback));
+        self
+    }
+
+    /// Test MSRV-aware lints as though the package's minimum supported Rust version were `msrv`,
+    /// e.g., `1.70`.
+    ///
+    /// The MSRV is passed to the driver as `CARGO_PKG_RUST_VERSION`, as Cargo passes a package's
+    /// `rust-version`, which `clippy_utils`' `Msrv` falls back to when no `msrv` is configured. A
+    /// file can set its own with an `//@msrv: 1.65` header. With revisions, e.g., `//@[old] msrv:
+    /// 1.60`, one file can test a lint's suggestions at several MSRVs.
+    pub fn msrv(&mut self, msrv: &str) -> &mut Self {
+        self.config.msrv = Some(msrv.to_owned());
+        self
+    }
+
+    /// Skip fixtures if the toolchain is older than Rust `version`, e.g., `1.78`.
+    ///
+    /// A fixture can set its own minimum with a `//@min-rust-version: 1.78` header, which takes
+    /// precedence. Pre-release suffixes are ignored, so a `1.78.0-nightly` toolchain meets a minimum
+    /// of `1.78`. Skipped fixtures are reported like those skipped by platform headers.
+    pub fn min_rust_version(&mut self, version: &str) -> &mut Self {
+        self.config.min_rust_version = Some(version.to_owned());
+        self
+    }
+
+    /// Run the test with a specific rustup toolchain, e.g., `nightly-2024-06-01`, rather than the
+    /// one in `RUSTUP_TOOLCHAIN`.
+    ///
+    /// The library, the driver, and any example targets are built with that toolchain. Unless
+    /// [`Test::target_dir`] is also called, artifacts are built into a toolchain-specific
+    /// directory, so that tests using different toolchains do not rebuild each other's artifacts.
+    pub fn toolchain(&mut self, toolchain: impl AsRef<str>) -> &mut Self {
+        self.config.toolchain = Some(toolchain.as_ref().to_owned());
+        self
+    }
+
+    /// Set the toolchains that [`Test::run_matrix`] runs the test with.
+    ///
+    /// Diagnostics text can shift between compiler versions. So with each toolchain, an expected
+    /// file such as `foo.stderr.nightly-2025-01-01` is used in place of `foo.stderr` if it exists.
+    /// Blessing writes such an override only when the output differs from `foo.stderr`.
+    pub fn toolchains(
+        &mut self,
+        toolchains: impl IntoIterator<Item = impl AsRef<str>>,
+    ) -> &mut Self {
+        self.config
+            .toolchains
+            .extend(toolchains.into_iter().map(|s| s.as_ref().to_owned()));
+        self
+    }
+
+    /// Run each file under each of the given editions, e.g., `["2021", "2024"]`.
+    ///
+    /// Many lints have edition-dependent spans or suggestions. So under each edition, an expected
+    /// file such as `foo.stderr.e2024` is used in place of `foo.stderr` if it exists. Blessing writes
+    /// such an override only when the output differs from `foo.stderr`. A file's `//@edition:`
+    /// header still takes precedence.
+    pub fn editions(&mut self, editions: impl IntoIterator<Item = impl AsRef<str>>) -> &mut Self {
+        self.config
+            .editions
+            .extend(editions.into_iter().map(|s| s.as_ref().to_owned()));
+        self
+    }
+
+    /// Make third-party crates available to the source files, e.g., `dependencies(&[("serde",
+    /// "1")])`.
+    ///
+    /// Each dependency is a crate name and a version requirement. The crates are built with Cargo
+    /// before the test runs and passed to the driver with `--extern`, so that `src_base` fixtures
+    /// can `use` them without being example targets. May be called more than once.
+    pub fn dependencies(&mut self, dependencies: &[(&str, &str)]) -> &mut Self {
+        self.config.dependencies.extend(
+            dependencies
+                .iter()
+                .map(|&(name, version)| (name.to_owned(), version.to_owned())),
+        );
+        self
+    }
+
+    /// Make the dependencies of the package at `manifest_path` available to the source files.
+    ///
+    /// This is like [`Test::dependencies`], but allows path, git, and feature-bearing dependencies.
+    /// A relative path is resolved against the current directory.
+    pub fn dependencies_manifest(&mut self, manifest_path: impl AsRef<Path>) -> &mut Self {
+        self.config.dependencies_manifest = Some(manifest_path.as_ref().to_owned());
+        self
+    }
+
+    /// Keep the temporary directory of a failed example, binary, or integration test, and print its
+    /// path in the failure message (default `false`).
+    ///
+    /// The directory holds the copied sources and the outputs ui_test produced. Setting
+    /// `DYLINT_TESTING_KEEP_TMP=1` has the same effect.
+    pub fn keep_going_artifacts(&mut self, keep_going_artifacts: bool) -> &mut Self {
+        self.config.keep_tmp = keep_going_artifacts;
+        self
+    }
+
+    /// Run the source files (or example targets) in a random order, to catch lints that depend on
+    /// state left behind by earlier files.
+    ///
+    /// If `seed` is `None`, one is chosen from the clock. Either way, the seed is printed, so that
+    /// the order can be reproduced by passing it back in. Without this method, files run in
+    /// lexicographic order of their paths.
+    pub fn shuffle(&mut self, seed: Option<u64>) -> &mut Self {
+        self.config.shuffle_seed = Some(seed.unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |duration| duration.as_nanos() as u64)
+        }));
+        self
+    }
+
+    /// Set the number of unchanged lines shown around each change in failure diffs (default 3).
+    ///
+    /// Diffs are colored unless `NO_COLOR` is set, `CARGO_TERM_COLOR` is `never`, or stderr is not a
+    /// terminal (`CARGO_TERM_COLOR=always` forces color). This applies to the diffs this crate
+    /// reports, e.g., for `.json` snapshots; ui_test renders `.stderr` and `.stdout` diffs itself.
+    pub fn diff_context_lines(&mut self, diff_context_lines: usize) -> &mut Self {
+        self.config.diff_context_lines = diff_context_lines;
+        self
+    }
+
+    /// Modify or replace the command that runs the driver, before each invocation.
+    ///
+    /// The closure receives a command for the driver (or for the [`Test::timeout`] wrapper) whose
+    /// file-specific arguments have not yet been added; they are appended afterward. This allows
+    /// interposing tools such as `strace`, a sanitizer wrapper, or a timing harness:
+    ///
+    /// ```rust,ignore
+    /// test.wrap_command(|command| {
+    ///     let mut strace = std::process::Command::new("strace");
+    ///     strace.arg("-f").arg(command.get_program()).args(command.get_args());
+    ///     *command = strace;
+    /// });
+    /// ```
+    pub fn wrap_command(
+        &mut self,
+        wrap_command: impl Fn(&mut Command) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.config.wrap_command = Some(Arc::new(wrap_command));
+        self
+    }
+
+    /// Build the library with `-C instrument-coverage`, so that running the test produces coverage
+    /// of the library's code (default `false`).
+    ///
+    /// The instrumented library is built in its own target directory. Each driver invocation writes
+    /// a `.profraw` file named after the library, the test (e.g., the source file, when run through
+    /// [`Test::collect`]), and the process. The files are written next to `LLVM_PROFILE_FILE` if it
+    /// is set, e.g., by `cargo llvm-cov`, and to `target/dylint_testing/coverage/profraw` otherwise.
+    pub fn coverage(&mut self, coverage: bool) -> &mut Self {
+        self.config.coverage = coverage;
+        self
+    }
+
+    /// Time each source file and print a table of the timings, slowest first (default `false`).
+    ///
+    /// The files are run one at a time rather than in parallel, so that the timings are not skewed
+    /// by contention. This helps to catch pathological lint slowdowns.
+    pub fn timings(&mut self, timings: bool) -> &mut Self {
+        self.config.timings = timings;
+        self
+    }
+
+    /// Fail the test if any source file takes longer than `max_time` to run, turning performance
+    /// regressions into test failures.
+    ///
+    /// Like [`Test::timings`], this runs the files one at a time. Unlike [`Test::timeout`], the
+    /// driver is not killed; the file fails after it finishes.
+    pub fn max_time_per_file(&mut self, max_time: Duration) -> &mut Self {
+        self.config.max_time_per_file = Some(max_time);
+        self
+    }
+
+    /// Pass `-Z self-profile` to the driver, writing profiles to
+    /// `target/dylint_testing/self_profile` (default `false`).
+    ///
+    /// The profiles can be inspected with the [`measureme`] tools, e.g., `summarize`.
+    ///
+    /// [`measureme`]: https://github.com/rust-lang/measureme
+    pub fn self_profile(&mut self, self_profile: bool) -> &mut Self {
+        self.config.self_profile = self_profile;
+        self
+    }
+
+    /// Dump `kind` of intermediate representation for each file to
+    /// `target/dylint_testing/dumps/<name>`, when `DYLINT_TESTING_DUMP` is set. May be called more
+    /// than once.
+    ///
+    /// Setting `DYLINT_TESTING_DUMP=1` dumps every file, and any other value is a glob selecting the
+    /// files to dump, e.g., `DYLINT_TESTING_DUMP=foo*.rs`. The dumps show what a lint sees without
+    /// modifying the lint. They are not compared against anything, and a file that cannot be dumped
+    /// (e.g., MIR of a file that does not type check) does not fail the test.
+    pub fn dump(&mut self, kind: DumpKind) -> &mut Self {
+        if !self.config.dumps.contains(&kind) {
+            self.config.dumps.push(kind);
+        }
+        self
+    }
+
+    /// Make `metadata` the `[workspace.metadata]` table seen by the library, for lints configured
+    /// through workspace metadata rather than `dylint.toml`, e.g.:
+    ///
+    /// ```rust,ignore
+    /// test.workspace_metadata(serde_json::json!({ "dylint": { "max_depth": 3 } }));
+    /// ```
+    ///
+    /// A package whose manifest holds the table is written to the target directory, and the driver
+    /// is run with `CARGO_MANIFEST_DIR` pointing at it. `metadata` must be an object without nulls.
+    pub fn workspace_metadata(&mut self, metadata: serde_json::Value) -> &mut Self {
+        self.config.workspace_metadata = Some(metadata);
+        self
+    }
+
+    /// Kill the driver if compiling any one file takes longer than `timeout`.
+    ///
+    /// A lint that loops forever would otherwise hang `cargo test`. The timed-out file is reported
+    /// on stderr, and the test fails because the driver's exit status is not the expected one.
+    pub fn timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.config.timeout = Some(timeout);
+        self
+    }
+
+    /// Build the library, examples, and UI test outputs into `target_dir` rather than the
+    /// workspace's target directory.
+    ///
+    /// This isolates test suites that would otherwise race on shared build artifacts, e.g.,
+    /// parallel CI jobs or concurrently running test binaries. A relative path is resolved against
+    /// the current directory.
+    pub fn target_dir(&mut self, target_dir: impl AsRef<Path>) -> &mut Self {
+        self.config.target_dir = Some(target_dir.as_ref().to_owned());
+        self
+    }
+
+    /// Run the test.
+    ///
+    /// If [`Test::on_complete`] was called, each file or target is run separately, as by
+    /// [`Test::try_run`], and the test fails afterward if any of them failed.
+    #[allow(clippy::needless_pass_by_ref_mut)]
+    pub fn run(&mut self) {
+        if self.config.on_complete.is_none() {
+            self.run_immutable();
+            return;
+        }
+        let summary = self.try_run().unwrap_or_else(|error| panic!("{error:?}"));
+        assert!(
+            summary.is_success(),
+            "{}",
+            summary
+                .failed
+                .iter()
+                .map(|failure| format!("{}: {}", failure.name, failure.message))
+                .collect::<Vec<_>>()
+                .join("\n\n")
+        );
+    }
+
+    /// Accept the blessed files written beneath the directory passed to [`Test::bless_output_dir`].
+    ///
+    /// Each file is copied to its path relative to the current directory and then removed from the
+    /// output directory, so delete any files you reject first. Returns the accepted files' paths.
+    #[allow(clippy::needless_pass_by_ref_mut)]
+    pub fn accept(&mut self) -> Result<Vec<PathBuf>> {
+        let output_dir = self
+            .config
+            .bless_output_dir
+            .as_ref()
+            .ok_or_else(|| anyhow!("`accept` requires `bless_output_dir`"))?;
+        if !output_dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut accepted = Vec::new();
+        for file in files(output_dir)? {
+            let dest = file.strip_prefix(output_dir)?.to_owned();
+            if let Some(parent) = dest.parent() {
+                create_dir_all(parent).with_context(|| {
+                    format!("`create_dir_all` failed for `{}`", parent.display())
+                })?;
+            }
+            copy(&file, &dest).with_context(|| {
+                format!(
+                    "Could not copy `{}` to `{}`",
+                    file.display(),
+                    dest.display()
+                )
+            })?;
+            remove_file(&file).with_context(|| format!("Could not remove `{}`", file.display()))?;
+            accepted.push(dest);
+        }
+        Ok(accepted)
+    }
+
+    /// Run the test, returning a summary of its results instead of panicking if it fails.
+    ///
+    /// Like [`Test::collect`], each source file (for [`Test::src_base`]) or example target (for
+    /// [`Test::examples`]) is run separately, so that the summary can say which ones failed. An
+    /// error is returned only if the files or targets could not be listed.
+    #[allow(clippy::needless_pass_by_ref_mut)]
+    pub fn try_run(&mut self) -> Result<TestSummary> {
+        let start = Instant::now();
+        let mut summary = TestSummary::default();
+        for (name, test) in self.units()? {
+            let unit_start = Instant::now();
+            // The runner records the files blessing changes, on this thread.
+            take_blessed();
+            let result = catch_unwind(AssertUnwindSafe(|| test.run_immutable()));
+            summary.per_file.push((name.clone(), unit_start.elapsed()));
+            let blessed = !take_blessed().is_empty();
+            match result {
+                Ok(()) => {
+                    if blessed {
+                        summary.blessed.push(name.clone());
+                    }
+                    summary.passed.push(name);
+                }
+                Err(payload) => summary.failed.push(TestFailure {
+                    name,
+                    message: panic_message(payload.as_ref()),
+                }),
+            }
+        }
+        summary.skipped = self.skipped_files()?;
+        summary.duration = start.elapsed();
+        if let Some(on_complete) = &self.config.on_complete {
+            on_complete(&summary);
+        }
+        Ok(summary)
+    }
+
+    /// Run the test, then watch the library's sources and the test's files, and re-run the parts of
+    /// the test that changes affect. Returns only if an error occurs.
+    ///
+    /// Like [`Test::try_run`], each source file (for [`Test::src_base`]) or example target (for
+    /// [`Test::examples`]) is run separately. A source file is re-run when it or one of its expected
+    /// files changes, e.g., `foo.rs` or `foo.stderr`. Other tests are re-run when a file beneath the
+    /// package's `examples` or `tests` directory changes. When the library's sources change, the
+    /// library is rebuilt and everything is re-run. Otherwise, the driver and library are reused, so
+    /// that a change is tested in about the time it takes to compile the affected files.
+    ///
+    /// Call it from an example or an ignored test, e.g., `cargo test -- --ignored watch`.
+    #[allow(clippy::needless_pass_by_ref_mut)]
+    pub fn watch(&mut self) -> Result<()> {
+        let package_root = current_dir().with_context(|| "Could not get current directory")?;
+        let mut library = None;
+        let mut fingerprints = BTreeMap::new();
+        loop {
+            let fingerprint = cache::fingerprint(&package_root)?;
+            let rerun_all = library.as_ref() != Some(&fingerprint);
+            if rerun_all && library.is_some() {
+                eprintln!("Library `{}` changed; rebuilding", self.name);
+                runtime::forget(&self.name);
+            }
+            library = Some(fingerprint);
+
+            let mut ran = false;
+            for (name, test) in self.units()? {
+                let watched = test.watched_files()?;
+                if !rerun_all
+                    && fingerprints.get(&name) == Some(&cache::fingerprint_files(watched.clone())?)
+                {
+                    continue;
+                }
+                ran = true;
+                match catch_unwind(AssertUnwindSafe(|| test.run_immutable())) {
+                    Ok(()) => eprintln!("{name}: ok"),
+                    Err(payload) => {
+                        eprintln!("{name}: FAILED\n{}", panic_message(payload.as_ref()));
+                    }
+                }
+                // The fingerprint is taken after the run, so that files written by blessing do not
+                // trigger another run.
+                fingerprints.insert(name, cache::fingerprint_files(watched)?);
+            }
+            if ran {
+                eprintln!("Watching for changes...");
+            }
+            sleep(WATCH_INTERVAL);
+        }
+    }
+
+    /// Returns the files, other than the library's sources, whose changes affect the test: for a
+    /// single source file, the file and its expected files, and otherwise the files beneath the
+    /// package's `examples` and `tests` directories and the test's own directories.
+    fn watched_files(&self) -> Result<Vec<PathBuf>> {
+        if let (Target::SrcBase(src_base), Some(file)) = (&self.target, &self.config.only_file) {
+            let file = src_base.join(file);
+            let (Some(dir), Some(stem)) = (file.parent(), file.file_stem()) else {
+                return Ok(vec![file]);
+            };
+            let prefix = format!("{}.", stem.to_string_lossy());
+            return Ok(files(dir)?
+                .into_iter()
+                .filter(|path| {
+                    path.parent() == Some(dir)
+                        && path
+                            .file_name()
+                            .is_some_and(|name| name.to_string_lossy().starts_with(&prefix))
+                })
+                .collect());
+        }
+        let package_root = current_dir().with_context(|| "Could not get current directory")?;
+        let mut dirs = vec![package_root.join("examples"), package_root.join("tests")];
+        dirs.extend(self.src_base_dirs().iter().cloned());
+        if let Target::Workspace(workspace) = &self.target {
+            dirs.push(workspace.clone());
+        }
+        let mut watched = Vec::new();
+        for dir in dirs.iter().filter(|dir| dir.is_dir()) {
+            watched.extend(files(dir)?);
+        }
+        Ok(watched)
+    }
+
+    /// Split the test into one [`libtest_mimic::Trial`] per source file (for [`Test::src_base`])
+    /// or per example target (for [`Test::examples`]), so that each is reported with its own
+    /// name and pass/fail status. Other tests yield a single trial. Files skipped by a platform
+    /// or Rust version header yield ignored trials.
+    ///
+    /// Run the trials from an integration test with `harness = false`:
+    ///
+    /// ```rust,ignore
+    /// use dylint_uitesting::{libtest_mimic::Arguments, ui::Test};
+    ///
+    /// fn main() {
+    ///     let trials = Test::src_base(env!("CARGO_PKG_NAME"), "ui").collect();
+    ///     dylint_uitesting::libtest_mimic::run(&Arguments::from_args(), trials).exit();
+    /// }
+    /// ```
+    #[must_use]
+    pub fn collect(&self) -> Vec<Trial> {
+        self.units()
+            .unwrap_or_else(|error| panic!("{error:?}"))
+            .into_iter()
+            .map(|(name, test)| {
+                Trial::test(name, move || {
+                    catch_unwind(AssertUnwindSafe(|| test.run_immutable()))
+                        .map_err(|payload| Failed::from(panic_message(payload.as_ref())))
+                })
+            })
+            .chain(
+                self.skipped_files()
+                    .unwrap_or_else(|error| panic!("{error:?}"))
+                    .into_iter()
+                    .map(|name| Trial::test(name, || Ok(())).with_ignored_flag(true)),
+            )
+            .collect()
+    }
+
+    /// Returns the names of the source files skipped by a platform or Rust version header, for
+    /// [`Test::src_base`] and [`Test::src_bases`].
+    fn skipped_files(&self) -> Result<Vec<String>> {
+        let mut names = Vec::new();
+        for src_base in self.src_base_dirs() {
+            names.extend(
+                skipped_files(src_base, &self.config)
+                    .with_context(|| format!("Could not collect `{}`", src_base.display()))?
+                    .into_iter()
+                    .map(|(file, _)| self.file_name(src_base, &file)),
+            );
+        }
+        Ok(names)
+    }
+
+    /// Returns the directories of a [`Test::src_base`] or [`Test::src_bases`] test.
+    fn src_base_dirs(&self) -> &[PathBuf] {
+        match &self.target {
+            Target::SrcBase(src_base) => std::slice::from_ref(src_base),
+            Target::SrcBases(src_bases) => src_bases,
+            _ => &[],
+        }
+    }
+
+    /// Returns the name of `file` beneath `src_base`: its path relative to `src_base`, prefixed by
+    /// `src_base` if the test has several directories.
+    fn file_name(&self, src_base: &Path, file: &Path) -> String {
+        let relative = file.strip_prefix(src_base).unwrap_or(file);
+        if let Target::SrcBases(_) = &self.target {
+            src_base.join(relative).display().to_string()
+        } else {
+            relative.display().to_string()
+        }
+    }
+
+    /// Splits the test into one test per source file or example target, each with a name. The
+    /// inline fixtures, if any, form one more test.
+    fn units(&self) -> Result<Vec<(String, Self)>> {
+        let mut units = self.target_units()?;
+        if !self.config.inline_sources.is_empty() {
+            for (_, unit) in &mut units {
+                unit.config.inline_sources.clear();
+                unit.config.inline_expected.clear();
+            }
+            units.push((
+                "inline".to_owned(),
+                Self {
+                    name: self.name.clone(),
+                    target: Target::SrcBases(Vec::new()),
+                    config: self.config.clone(),
+                },
+            ));
+        }
+        Ok(units)
+    }
+
+    fn target_units(&self) -> Result<Vec<(String, Self)>> {
+        let unit = |name: String, target: Target, config: Config| {
+            (
+                name,
+                Self {
+                    name: self.name.clone(),
+                    target,
+                    config,
+                },
+            )
+        };
+        match &self.target {
+            Target::SrcBase(_) | Target::SrcBases(_) => {
+                let mut units = Vec::new();
+                for src_base in self.src_base_dirs() {
+                    units.extend(
+                        source_files(src_base, &self.config)
+                            .with_context(|| format!("Could not collect `{}`", src_base.display()))?
+                            .into_iter()
+                            .map(|file| {
+                                let relative =
+                                    file.strip_prefix(src_base).unwrap_or(&file).to_owned();
+                                let mut config = self.config.clone();
+                                config.only_file = Some(relative);
+                                unit(
+                                    self.file_name(src_base, &file),
+                                    Target::SrcBase(src_base.clone()),
+                                    config,
+                                )
+                            }),
+                    );
+                }
+                Ok(units)
+            }
+            Target::Examples => {
+                let metadata = dylint_internal::cargo::current_metadata()?;
+                let package =
+                    dylint_internal::cargo::package_with_root(&metadata, &current_dir()?)?;
+                Ok(example_targets(&package)?
+                    .into_iter()
+                    .filter(|target| self.config.selects_example(&target.name))
+                    .map(|target| {
+                        unit(
+                            format!("example {}", target.name),
+                            Target::Example(target.name),
+                            self.config.clone(),
+                        )
+                    })
+                    .collect())
+            }
+            target => {
+                let name = match target {
+                    Target::Example(example) | Target::ExampleInPackage(_, example) => {
+                        format!("example {example}")
+                    }
+                    Target::Bin(bin) => format!("bin {bin}"),
+                    Target::IntegrationTest(test) => format!("test {test}"),
+                    Target::Workspace(workspace) => format!("workspace {}", workspace.display()),
+                    Target::SrcBase(_) | Target::SrcBases(_) | Target::Examples => {
+                        unreachable!()
+                    }
+                };
+                Ok(vec![unit(name, target.clone(), self.config.clone())])
+            }
+        }
+    }
+
+    /// Run the test once with each toolchain passed to [`Test::toolchains`].
+    ///
+    /// Every toolchain is run, even if an earlier one fails, and each toolchain's result is
+    /// reported. The test fails afterward if any toolchain failed.
+    #[allow(clippy::needless_pass_by_ref_mut)]
+    pub fn run_matrix(&mut self) {
+        assert!(
+            !self.config.toolchains.is_empty(),
+            "`run_matrix` requires at least one toolchain; call `toolchains` first"
+        );
+
+        let mut failures = Vec::new();
+        for toolchain in &self.config.toolchains {
+            let mut config = self.config.clone();
+            config.toolchain = Some(toolchain.clone());
+            let result = catch_unwind(AssertUnwindSafe(|| self.run_with_config(&config)));
+            if result.is_ok() {
+                eprintln!("toolchain `{toolchain}`: ok");
+            } else {
+                eprintln!("toolchain `{toolchain}`: FAILED");
+                failures.push(toolchain.as_str());
+            }
+        }
+
+        assert!(
+            failures.is_empty(),
+            "failed with toolchains: {}",
+            failures.join(", ")
+        );
+    }
+
+    fn new(name: &str, target: Target) -> Self {
+        Self {
+            name: name.to_owned(),
+            target,
+            config: Config::default(),
+        }
+    }
+
+    fn run_immutable(&self) {
+        self.run_with_config(&self.config);
+    }
+
+    fn run_with_config(&self, config: &Config) {
+        debug!(
+            "run_immutable: Starting run_immutable for library '{}'",
+            self.name
+        );
+        let mut metadata = dylint_internal::cargo::current_metadata().unwrap();
+        let current_dir = current_dir().unwrap();
+        if let Some(target_dir) = &config.target_dir {
+            metadata.target_directory = Utf8PathBuf::from_path_buf(current_dir.join(target_dir))
+                .unwrap_or_else(|path| panic!("Non-UTF-8 target directory: {}", path.display()));
+        } else if let Some(toolchain) = &config.toolchain {
+            metadata.target_directory = metadata
+                .target_directory
+                .join("dylint_testing/toolchains")
+                .join(toolchain);
+        }
+        // The profile directory is chosen before the instrumented build gets its own target
+        // directory, so that it does not depend on the toolchain.
+        let profile_dir = config.coverage.then(|| {
+            std::env::var_os(env::LLVM_PROFILE_FILE)
+                .and_then(|path| PathBuf::from(path).parent().map(Path::to_path_buf))
+                .unwrap_or_else(|| {
+                    metadata
+                        .target_directory
+                        .join("dylint_testing/coverage/profraw")
+                        .into_std_path_buf()
+                })
+        });
+        if config.coverage {
+            metadata.target_directory = metadata.target_directory.join("dylint_testing/coverage");
+        }
+
+        let mut config = config.clone();
+        if config.dylint_toml.is_none() {
+            config.dylint_toml = discovered_dylint_toml(&config, &current_dir).unwrap();
+        }
+        // The directories of a hermetic test are removed when the test ends.
+        let hermetic_dir = config.hermetic.then(|| {
+            let dir = tempfile::tempdir().unwrap();
+            for subdir in ["cargo", "home", "tmp"] {
+                create_dir_all(dir.path().join(subdir)).unwrap();
+            }
+            dir
+        });
+        config.hermetic_dir = hermetic_dir.as_ref().map(|dir| dir.path().to_owned());
+        // The directory holding `clippy.toml` is removed when the test ends. Its variable comes
+        // first, so that a value passed to `env` takes precedence.
+        let clippy_conf_dir = config.clippy_toml.as_ref().map(|clippy_toml| {
+            let dir = tempfile::tempdir().unwrap();
+            write(dir.path().join("clippy.toml"), clippy_toml).unwrap();
+            dir
+        });
+        if let Some(dir) = &clippy_conf_dir {
+            config.envs.insert(
+                0,
+                (
+                    env::CLIPPY_CONF_DIR.to_owned(),
+                    dir.path().to_string_lossy().into_owned(),
+                ),
+            );
+        }
+        if !config.dependencies.is_empty() {
+            assert!(
+                config.dependencies_manifest.is_none(),
+                "`dependencies` and `dependencies_manifest` cannot both be used"
+            );
+            config.dependencies_manifest = Some(
+                dependencies::manifest(
+                    &metadata.target_directory,
+                    &self.name,
+                    &config.dependencies,
+                )
+                .unwrap(),
+            );
+        } else if let Some(manifest_path) = &config.dependencies_manifest {
+            config.dependencies_manifest = Some(current_dir.join(manifest_path));
+        }
+        if let Some(profile_dir) = profile_dir {
+            let profile_file = profile_dir.join(format!(
+                "{}-{}-%p-%m.profraw",
+                self.name,
+                self.profile_name(&config)
+            ));
+            config.envs.push((
+                env::LLVM_PROFILE_FILE.to_owned(),
+                profile_file.to_string_lossy().into_owned(),
+            ));
+        }
+        if let Some(workspace_metadata) = &config.workspace_metadata {
+            let package =
+                metadata::package(&metadata.target_directory, &self.name, workspace_metadata)
+                    .unwrap();
+            config.envs.push((
+                env::CARGO_MANIFEST_DIR.to_owned(),
+                package.to_string_lossy().into_owned(),
+            ));
+        }
+        // The MSRV comes first, so that a value passed to `env` takes precedence.
+        if let Some(msrv) = &config.msrv {
+            config
+                .envs
+                .insert(0, (env::CARGO_PKG_RUST_VERSION.to_owned(), msrv.clone()));
+        }
+        // Flags from the environment come first, so that those passed to `rustc_flags` take
+        // precedence.
+        if config.rustc_flags_from_env {
+            config.rustc_flags.splice(0..0, env::rustc_flags_from_env());
+        }
+        if let Some(target) = &config.target {
+            config.rustc_flags.push(format!("--target={target}"));
+        }
+        // Example targets get their features from Cargo (see `cargo_integration::rustc_flags`).
+        if let Target::SrcBase(_) | Target::SrcBases(_) = &self.target {
+            let mut features = config.features.clone();
+            if config.all_features {
+                let package =
+                    dylint_internal::cargo::package_with_root(&metadata, &current_dir).unwrap();
+                features.extend(package.features.keys().cloned());
+            }
+            for feature in features {
+                config
+                    .rustc_flags
+                    .push(format!("--cfg=feature=\"{feature}\""));
+            }
+        }
+        if config.self_profile {
+            config.rustc_flags.push(format!(
+                "-Zself-profile={}",
+                metadata
+                    .target_directory
+                    .join("dylint_testing/self_profile")
+            ));
+        }
+        if config.incremental {
+            config.incremental_cache = Some(
+                metadata
+                    .target_directory
+                    .join("dylint_testing/cache.json")
+                    .into_std_path_buf(),
+            );
+        }
+        if config.repro_scripts {
+            config.repro_dir = Some(
+                metadata
+                    .target_directory
+                    .join("dylint_testing/repro")
+                    .join(&self.name)
+                    .into_std_path_buf(),
+            );
+        }
+        if config.require_coverage || !config.expected_lint_counts.is_empty() {
+            config.lint_counts_path = Some(
+                metadata
+                    .target_directory
+                    .join("dylint_testing/lint_counts")
+                    .join(format!("{}.json", self.name))
+                    .into_std_path_buf(),
+            );
+        }
+        if !config.dumps.is_empty() {
+            config.dump_dir = Some(
+                metadata
+                    .target_directory
+                    .join("dylint_testing/dumps")
+                    .join(&self.name)
+                    .into_std_path_buf(),
+            );
+        }
+        if config.timeout.is_some() {
+            config.timeout_wrapper =
+                Some(timeout_wrapper::path(&metadata.target_directory).unwrap());
+        }
+        if diagnostics_wrapper::options(&config, true).is_some() {
+            config.diagnostics_wrapper =
+                Some(diagnostics_wrapper::path(&metadata.target_directory).unwrap());
+        }
+
+        let driver = initialize(&self.name, &metadata.target_directory, &config).unwrap();
+        debug!("run_immutable: Got driver: {}", driver.path.display());
+        if config.only_lint_diagnostics || config.require_coverage {
+            config.library_lints = lints::library_lints(driver).unwrap();
+        }
+        let config = &config;
+
+        match &self.target {
+            Target::SrcBase(src_base) => {
+                debug!(
+                    "run_immutable: Running SrcBase target with src_base: {}",
+                    src_base.display()
+                );
+                crate::test_runner::run_tests(driver, src_base, config).expect("run tests failed");
+                lint_counts::check(driver, slice::from_ref(src_base), config)
+                    .expect("lint count check failed");
+            }
+            Target::SrcBases(src_bases) => {
+                let failures = src_bases
+                    .iter()
+                    .filter_map(|src_base| {
+                        debug!(
+                            "run_immutable: Running SrcBases target with src_base: {}",
+                            src_base.display()
+                        );
+                        crate::test_runner::run_tests(driver, src_base, config)
+                            .err()
+                            .map(|error| format!("`{}`: {error:?}", src_base.display()))
+                    })
+                    .collect::<Vec<_>>();
+                assert!(
+                    failures.is_empty(),
+                    "run tests failed:\n{}",
+                    failures.join("\n")
+                );
+                lint_counts::check(driver, src_bases, config).expect("lint count check failed");
+            }
+            Target::Example(example) => {
+                debug!("run_immutable: Running Example target: {}", example);
+                let package =
+                    dylint_internal::cargo::package_with_root(&metadata, &current_dir).unwrap();
+                let target = example_target(&package, example).unwrap();
+
+                crate::test_runner::run_example_test(driver, &metadata, &package, &target, config)
+                    .unwrap();
+            }
+            Target::ExampleInPackage(package, example) => {
+                debug!(
+                    "run_immutable: Running Example target: {} in package: {}",
+                    example, package
+                );
+                let package = package_with_name(&metadata, package).unwrap();
+                let target = example_target(&package, example).unwrap();
+
+                run_example_test(driver, &metadata, &package, &target, config).unwrap();
+            }
+            Target::Examples => {
+                let package =
+                    dylint_internal::cargo::package_with_root(&metadata, &current_dir).unwrap();
+                let mut targets = example_targets(&package).unwrap();
+                targets.retain(|target| config.selects_example(&target.name));
+                if let Some(seed) = config.shuffle_seed {
+                    eprintln!("Shuffling examples with seed {seed}");
+                    shuffle(&mut targets, seed);
+                }
+
+                for target in targets {
+                    run_example_test(driver, &metadata, &package, &target, config).unwrap();
+                }
+            }
+            Target::Bin(bin) => {
+                debug!("run_immutable: Running Bin target: {}", bin);
+                let package =
+                    dylint_internal::cargo::package_with_root(&metadata, &current_dir).unwrap();
+                let target = bin_target(&package, bin).unwrap();
+
+                run_example_test(driver, &metadata, &package, &target, config).unwrap();
+            }
+            Target::IntegrationTest(test) => {
+                debug!("run_immutable: Running IntegrationTest target: {}", test);
+                let package =
+                    dylint_internal::cargo::package_with_root(&metadata, &current_dir).unwrap();
+                let target = test_target(&package, test).unwrap();
+
+                run_example_test(driver, &metadata, &package, &target, config).unwrap();
+            }
+            Target::Workspace(workspace) => {
+                debug!(
+                    "run_immutable: Running Workspace target: {}",
+                    workspace.display()
+                );
+                run_workspace_test(
+                    driver,
+                    &metadata.target_directory,
+                    &current_dir.join(workspace),
+                    config,
+                )
+                .unwrap();
+            }
+        }
+
+        if !config.inline_sources.is_empty() && config.only_file.is_none() {
+            inline::run(driver, config).expect("inline fixtures failed");
+        }
+    }
+}
+
+impl Test {
+    /// Returns a name for the test's coverage profiles that is safe to use in a file name.
+    fn profile_name(&self, config: &Config) -> String {
+        let name = match (&self.target, &config.only_file) {
+            (_, Some(file)) => file.with_extension("").display().to_string(),
+            (Target::SrcBase(src_base), None) => src_base.display().to_string(),
+            (Target::SrcBases(src_bases), None) => src_bases
+                .iter()
+                .map(|src_base| src_base.display().to_string())
+                .collect::<Vec<_>>()
+                .join("-"),
+            (Target::Example(example) | Target::ExampleInPackage(_, example), None) => {
+                format!("example-{example}")
+            }
+            (Target::Examples, None) => "examples".to_owned(),
+            (Target::Bin(bin), None) => format!("bin-{bin}"),
+            (Target::IntegrationTest(test), None) => format!("test-{test}"),
+            (Target::Workspace(workspace), None) => format!("workspace-{}", workspace.display()),
+        };
+        name.chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect()
+    }
+}
+
+/// Returns the contents of the `dylint.toml` file named by `config`, or else of the one discovered
+/// in the package root, if any.
+fn discovered_dylint_toml(config: &Config, package_root: &Path) -> Result<Option<String>> {
+    let path = if let Some(path) = &config.dylint_toml_path {
+        package_root.join(path)
+    } else if config.discover_dylint_toml {
+        let path = package_root.join("dylint.toml");
+        if !path.exists() {
+            return Ok(None);
+        }
+        path
+    } else {
+        return Ok(None);
+    };
+    debug!("discovered_dylint_toml: Reading {}", path.display());
+    read_to_string(&path)
+        .map(Some)
+        .with_context(|| format!("Could not read `{}`", path.display()))
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(ToString::to_string)
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "test panicked".to_owned())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // smoelius: Verify that `rustc_flags` compiles when used as intended.
+    #[allow(dead_code)]
+    fn rustc_flags() {
+        let _ = Test::src_base("name", PathBuf::new()).rustc_flags(["--test"]);
+    }
+
+    #[test]
+    fn dylint_toml_is_discovered_in_package_root() {
+        let tmp = tempfile::tempdir().unwrap();
+        std::fs::write(tmp.path().join("dylint.toml"), "[lint]\n").unwrap();
+        std::fs::write(tmp.path().join("other.toml"), "[other]\n").unwrap();
+
+        let mut config = Config::default();
+        assert_eq!(
+            discovered_dylint_toml(&config, tmp.path())
+                .unwrap()
+                .as_deref(),
+            Some("[lint]\n")
+        );
+
+        config.dylint_toml_path = Some(PathBuf::from("other.toml"));
+        assert_eq!(
+            discovered_dylint_toml(&config, tmp.path())
+                .unwrap()
+                .as_deref(),
+            Some("[other]\n")
+        );
+
+        config.dylint_toml_path = None;
+        config.discover_dylint_toml = false;
+        assert_eq!(discovered_dylint_toml(&config, tmp.path()).unwrap(), None);
+    }
+
+    #[test]
+    fn path_filters_match_either_separator() {
+        let pattern = regex::Regex::new(&path_pattern(Path::new("/home/me/my.lint"))).unwrap();
+        assert!(pattern.is_match("--> /home/me/my.lint/src/main.rs"));
+        assert!(pattern.is_match(r"--> \home\me\my.lint\src\main.rs"));
+        assert!(!pattern.is_match("--> /home/me/myxlint/src/main.rs"));
+    }
+
+    #[test]
+    fn explicit_annotation_level_survives_level_filter() {
+        let mut test = Test::src_base("name", PathBuf::new());
+        test.require_annotations_for_level(Level::Help)
+            .diagnostic_level_filter(Level::Error | Level::Warning);
+        assert_eq!(test.config.require_annotations_for_level, Some(Level::Help));
+        assert_eq!(
+            (Level::Error | Level::Warning).lowest(),
+            Some(Level::Warning)
+        );
     }
 }
@@ -0,0 +1,214 @@
+use crate::{
+    diagnostics::is_summary,
+    env, report,
+    runtime::Driver,
+    test_runner::{guard_bless, record_blessed},
+    ui,
+};
+use anyhow::{Context, Result, anyhow, ensure};
+use cargo_metadata::{Message, MetadataCommand, camino::Utf8Path};
+use log::debug;
+use std::{
+    collections::BTreeMap,
+    fs::{read_to_string, remove_file, write},
+    io::BufReader,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+/// Checks the fixture workspace at `workspace` with the driver as `RUSTC_WORKSPACE_WRAPPER`, and
+/// compares each member's diagnostics against the `<package>.stderr` file next to its manifest.
+///
+/// A member that produces no diagnostics must have no `.stderr` file. The workspace is built into
+/// its own directory beneath `target_directory`, and its members are cleaned first, so that their
+/// diagnostics are always produced by the current library.
+pub(crate) fn run_workspace_test(
+    driver: &Driver,
+    target_directory: &Utf8Path,
+    workspace: &Path,
+    config: &ui::Config,
+) -> Result<()> {
+    guard_bless(workspace, config)?;
+
+    let manifest_path = workspace.join("Cargo.toml");
+    let metadata = MetadataCommand::new()
+        .manifest_path(&manifest_path)
+        .no_deps()
+        .exec()
+        .with_context(|| format!("Could not get metadata for `{}`", manifest_path.display()))?;
+    let members = metadata
+        .workspace_packages()
+        .into_iter()
+        .map(|package| (package.id.clone(), package.clone()))
+        .collect::<BTreeMap<_, _>>();
+    let workspace_name = workspace
+        .file_name()
+        .ok_or_else(|| anyhow!("Could not get file name of `{}`", workspace.display()))?
+        .to_string_lossy();
+    let target_dir = target_directory
+        .join("dylint_testing/workspaces")
+        .join(&*workspace_name);
+
+    let cargo = std::env::var_os("CARGO").unwrap_or_else(|| "cargo".into());
+
+    let mut clean = Command::new(&cargo);
+    clean
+        .args(["clean", "--manifest-path"])
+        .arg(&manifest_path)
+        .args(["--target-dir", target_dir.as_str()]);
+    for package in members.values() {
+        clean.args(["--package", &package.name]);
+    }
+    if config.offline {
+        clean.arg("--offline");
+    }
+    let status = clean
+        .status()
+        .with_context(|| format!("Could not clean `{}`", workspace.display()))?;
+    ensure!(
+        status.success(),
+        "Could not clean `{}`",
+        workspace.display()
+    );
+
+    let mut command = Command::new(&cargo);
+    for key in env::cleared_vars(config, true) {
+        command.env_remove(key);
+    }
+    command
+        .current_dir(workspace)
+        .args([
+            "check",
+            "--workspace",
+            "--all-targets",
+            "--message-format=json",
+        ])
+        .args(["--manifest-path"])
+        .arg(&manifest_path)
+        .args(["--target-dir", target_dir.as_str()])
+        .env_remove(env::CARGO_TERM_COLOR)
+        .env(env::RUSTC_WORKSPACE_WRAPPER, &driver.path)
+        .envs(driver.envs.iter().map(|(key, val)| (*key, val)));
+    if let Some(toolchain) = &config.toolchain {
+        command.env(env::RUSTUP_TOOLCHAIN, toolchain);
+    }
+    if config.offline {
+        command.arg("--offline");
+    }
+    if let Some(dylint_toml) = &config.dylint_toml {
+        command.env(env::DYLINT_TOML, dylint_toml);
+    } else {
+        command.env_remove(env::DYLINT_TOML);
+    }
+    command.envs(config.envs.iter().map(|(key, val)| (key, val)));
+
+    debug!("run_workspace_test: {:?}", command);
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Could not check `{}`", workspace.display()))?;
+    let stdout = child.stdout.take().unwrap();
+
+    // Rendered diagnostics, keyed by package and then by target, so that the order does not depend
+    // on the order in which Cargo builds them
+    let mut rendered = BTreeMap::<_, BTreeMap<_, String>>::new();
+    for message in Message::parse_stream(BufReader::new(stdout)) {
+        let message = message.with_context(|| "Could not parse Cargo's output")?;
+        let Message::CompilerMessage(message) = message else {
+            continue;
+        };
+        if !members.contains_key(&message.package_id)
+            || is_summary(&serde_json::to_value(&message.message)?)
+        {
+            continue;
+        }
+        if let Some(text) = &message.message.rendered {
+            rendered
+                .entry(message.package_id.clone())
+                .or_default()
+                .entry(message.target.name.clone())
+                .or_default()
+                .push_str(text);
+        }
+    }
+    let output = child
+        .wait_with_output()
+        .with_context(|| format!("Could not check `{}`", workspace.display()))?;
+    ensure!(
+        output.status.success() || !rendered.is_empty(),
+        "Could not check `{}`:\n{}",
+        workspace.display(),
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let bless = env::is_bless_truthy(config);
+    let dry_run = env::is_bless_dry_run(config);
+    let new_only = env::is_bless_new_only(config);
+    let mut mismatches = Vec::new();
+    for (id, package) in &members {
+        let actual = rendered
+            .get(id)
+            .map(|targets| targets.values().map(String::as_str).collect::<String>())
+            .unwrap_or_default();
+        let expected_path = package
+            .manifest_path
+            .with_file_name(format!("{}.stderr", package.name));
+        let expected = if expected_path.exists() {
+            read_to_string(&expected_path)
+                .with_context(|| format!("Could not read `{expected_path}`"))?
+        } else {
+            String::new()
+        };
+        if actual == expected {
+            continue;
+        }
+        if dry_run {
+            eprint!(
+                "{}",
+                report::bless_preview(
+                    expected_path.as_str(),
+                    expected_path.exists().then_some(expected.as_str()),
+                    (!actual.is_empty()).then_some(actual.as_str()),
+                    config.diff_context_lines,
+                    report::color_enabled(),
+                )
+            );
+            continue;
+        }
+        if bless && new_only && expected_path.exists() {
+            eprintln!("Leaving `{expected_path}` unchanged");
+            continue;
+        }
+        if bless {
+            if actual.is_empty() {
+                remove_file(&expected_path)
+                    .with_context(|| format!("Could not remove `{expected_path}`"))?;
+            } else {
+                write(&expected_path, &actual)
+                    .with_context(|| format!("Could not write `{expected_path}`"))?;
+            }
+            record_blessed(expected_path.as_std_path());
+            continue;
+        }
+        mismatches.push(format!(
+            "`{expected_path}` differs from actual diagnostics:\n{}",
+            report::unified_diff(
+                expected_path.as_str(),
+                "actual",
+                &expected,
+                &actual,
+                config.diff_context_lines,
+                report::color_enabled(),
+            )
+        ));
+    }
+
+    ensure!(
+        mismatches.is_empty(),
+        "{}\n\nRun `{}` to update the `.stderr` files.",
+        mismatches.join("\n\n"),
+        env::bless_command(config)
+    );
+    Ok(())
+}